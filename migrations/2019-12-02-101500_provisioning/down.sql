@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "invitations";
+
+-- SQLite can't drop columns, the table has to be rebuilt
+CREATE TABLE "users_old" (
+	"id"    INTEGER NOT NULL UNIQUE,
+	"name"  TEXT NOT NULL,
+	"email" TEXT NOT NULL,
+	"role"  TEXT NOT NULL,
+	PRIMARY KEY("id")
+);
+INSERT INTO "users_old" SELECT "id", "name", "email", "role" FROM "users";
+DROP TABLE "users";
+ALTER TABLE "users_old" RENAME TO "users";
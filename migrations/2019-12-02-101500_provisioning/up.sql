@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE "users"
+	ADD COLUMN "active" INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE "invitations" (
+	"id"         INTEGER NOT NULL UNIQUE,
+	"email"      TEXT NOT NULL UNIQUE,
+	"role"       TEXT NOT NULL,
+	"invited_by" TEXT NOT NULL,
+	"created"    TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY("id")
+);
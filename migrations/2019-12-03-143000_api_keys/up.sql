@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "api_keys" (
+	"id"          INTEGER NOT NULL UNIQUE,
+	"name"        TEXT NOT NULL,
+	"prefix"      TEXT NOT NULL UNIQUE,
+	"secret_hash" TEXT NOT NULL,
+	"scopes"      TEXT NOT NULL,
+	"created_by"  TEXT NOT NULL,
+	"created"     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	"expires"     TIMESTAMP,
+	"last_used"   TIMESTAMP,
+	"revoked"     INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY("id")
+);
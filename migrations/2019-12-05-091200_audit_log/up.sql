@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "audit_log" (
+	"id"          INTEGER NOT NULL UNIQUE,
+	"actor"       TEXT NOT NULL,
+	"action"      TEXT NOT NULL,
+	"target_type" TEXT NOT NULL,
+	"target_id"   INTEGER,
+	"before"      TEXT,
+	"after"       TEXT,
+	"diff"        TEXT,
+	"created"     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	"request_id"  TEXT NOT NULL,
+	PRIMARY KEY("id")
+);
+CREATE INDEX "audit_log_target" ON "audit_log" ("target_type", "target_id");
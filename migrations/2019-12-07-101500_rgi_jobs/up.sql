@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE "rgi_jobs" (
+	"id"          INTEGER NOT NULL UNIQUE,
+	"script"      TEXT NOT NULL,
+	"method"      TEXT NOT NULL,
+	"status"      TEXT NOT NULL DEFAULT 'queued',
+	"result"      TEXT,
+	"error"       TEXT,
+	"code"        INTEGER,
+	"created_by"  TEXT,
+	"created"     TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	"started"     TIMESTAMP,
+	"finished"    TIMESTAMP,
+	PRIMARY KEY("id")
+);
+CREATE INDEX "rgi_jobs_status" ON "rgi_jobs" ("status");
@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+-- SQLite neumí DROP COLUMN, tabulka se musí přestavět
+DROP INDEX "booking_series_id";
+CREATE TABLE "booking_old" (
+	"id"	INTEGER NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	"description"	TEXT NOT NULL,
+	"author"		TEXT NOT NULL,
+	"rooms"	INTEGER NOT NULL,
+	"begin_time"	TEXT NOT NULL,
+	"end_time"	TEXT NOT NULL,
+	"layout"	INTEGER NOT NULL,
+	"approved"	INTEGER NOT NULL,
+	"people"	UNSIGNED INT NOT NULL DEFAULT '0',
+	PRIMARY KEY("id")
+);
+INSERT INTO "booking_old" SELECT "id", "name", "description", "author", "rooms", "begin_time", "end_time", "layout", "approved", "people" FROM "booking";
+DROP TABLE "booking";
+ALTER TABLE "booking_old" RENAME TO "booking";
+DROP TABLE "booking_series";
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "booking_series" (
+	"id"      INTEGER NOT NULL UNIQUE,
+	"rrule"   TEXT NOT NULL,
+	"exdates" TEXT NOT NULL DEFAULT '[]',
+	"author"  TEXT NOT NULL,
+	"created" TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY("id")
+);
+ALTER TABLE "booking"
+	ADD COLUMN "series_id" INTEGER REFERENCES "booking_series" ("id");
+CREATE INDEX "booking_series_id" ON "booking" ("series_id");
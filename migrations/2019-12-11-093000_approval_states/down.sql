@@ -0,0 +1,24 @@
+-- This file should undo anything in `up.sql`
+-- SQLite neumí DROP COLUMN, tabulka se musí přestavět
+DROP INDEX "booking_series_id";
+CREATE TABLE "booking_old" (
+	"id"	INTEGER NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	"description"	TEXT NOT NULL,
+	"author"		TEXT NOT NULL,
+	"rooms"	INTEGER NOT NULL,
+	"begin_time"	TEXT NOT NULL,
+	"end_time"	TEXT NOT NULL,
+	"layout"	INTEGER NOT NULL,
+	"approved"	INTEGER NOT NULL,
+	"people"	UNSIGNED INT NOT NULL DEFAULT '0',
+	"series_id"	INTEGER REFERENCES "booking_series" ("id"),
+	PRIMARY KEY("id")
+);
+INSERT INTO "booking_old"
+	SELECT "id", "name", "description", "author", "rooms", "begin_time", "end_time", "layout",
+		CASE "status" WHEN 'approved' THEN 1 ELSE 0 END, "people", "series_id"
+	FROM "booking";
+DROP TABLE "booking";
+ALTER TABLE "booking_old" RENAME TO "booking";
+CREATE INDEX "booking_series_id" ON "booking" ("series_id");
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- stav rezervace: pending, approved, rejected, cancelled, withdrawn
+ALTER TABLE "booking" ADD COLUMN "status" TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE "booking" ADD COLUMN "reason" TEXT;
+ALTER TABLE "booking" ADD COLUMN "approved_by" TEXT;
+ALTER TABLE "booking" ADD COLUMN "decided_at" TIMESTAMP;
+UPDATE "booking" SET "status" = 'approved' WHERE "approved" = 1;
@@ -0,0 +1,41 @@
+-- This file should undo anything in `up.sql`
+-- zpátky na text v místním čase auditoria, letní čas platí od poslední neděle v březnu 01:00 UTC
+-- do poslední neděle v říjnu 01:00 UTC
+DROP INDEX "booking_series_id";
+CREATE TABLE "booking_local" (
+	"id"	INTEGER NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	"description"	TEXT NOT NULL,
+	"author"		TEXT NOT NULL,
+	"rooms"	INTEGER NOT NULL,
+	"begin_time"	TEXT NOT NULL,
+	"end_time"	TEXT NOT NULL,
+	"layout"	INTEGER NOT NULL,
+	"approved"	INTEGER NOT NULL,
+	"people"	UNSIGNED INT NOT NULL DEFAULT '0',
+	"series_id"	INTEGER REFERENCES "booking_series" ("id"),
+	"status"	TEXT NOT NULL DEFAULT 'pending',
+	"reason"	TEXT,
+	"approved_by"	TEXT,
+	"decided_at"	TIMESTAMP,
+	PRIMARY KEY("id")
+);
+INSERT INTO "booking_local"
+	SELECT "id", "name", "description", "author", "rooms",
+		CASE
+			WHEN "begin_time" >= date(strftime('%Y', "begin_time") || '-03-25', 'weekday 0') || ' 01:00:00'
+				AND "begin_time" < date(strftime('%Y', "begin_time") || '-10-25', 'weekday 0') || ' 01:00:00'
+				THEN strftime('%Y-%m-%dT%H:%M', "begin_time", '+2 hours')
+			ELSE strftime('%Y-%m-%dT%H:%M', "begin_time", '+1 hours')
+		END,
+		CASE
+			WHEN "end_time" >= date(strftime('%Y', "end_time") || '-03-25', 'weekday 0') || ' 01:00:00'
+				AND "end_time" < date(strftime('%Y', "end_time") || '-10-25', 'weekday 0') || ' 01:00:00'
+				THEN strftime('%Y-%m-%dT%H:%M', "end_time", '+2 hours')
+			ELSE strftime('%Y-%m-%dT%H:%M', "end_time", '+1 hours')
+		END,
+		"layout", "approved", "people", "series_id", "status", "reason", "approved_by", "decided_at"
+	FROM "booking";
+DROP TABLE "booking";
+ALTER TABLE "booking_local" RENAME TO "booking";
+CREATE INDEX "booking_series_id" ON "booking" ("series_id");
@@ -0,0 +1,51 @@
+-- Your SQL goes here
+-- časy rezervací byly text v místním čase auditoria (Europe/Prague), jak je poslal frontend,
+-- teď jsou to TIMESTAMP v UTC (`YYYY-MM-DD HH:MM:SS`), viz src/datetime.rs
+--
+-- letní čas (+02:00) platí od poslední neděle v březnu 02:00 do poslední neděle v říjnu 03:00
+-- místního času, jinak je +01:00, dvojznačná hodina v říjnu se bere jako letní.
+-- Časy s vlastním posunem (`Z`, `+01:00`) převede SQLite samo.
+-- Čas, který nejde přečíst, skončí jako NULL a migrace selže na NOT NULL, takový řádek je potřeba opravit ručně.
+UPDATE "booking" SET
+	"begin_time" = CASE
+		WHEN "begin_time" LIKE '%Z' OR substr("begin_time", -6, 1) IN ('+', '-') THEN datetime("begin_time")
+		WHEN datetime("begin_time") >= date(strftime('%Y', "begin_time") || '-03-25', 'weekday 0') || ' 02:00:00'
+			AND datetime("begin_time") < date(strftime('%Y', "begin_time") || '-10-25', 'weekday 0') || ' 03:00:00'
+			THEN datetime("begin_time", '-2 hours')
+		ELSE datetime("begin_time", '-1 hours')
+	END,
+	"end_time" = CASE
+		WHEN "end_time" LIKE '%Z' OR substr("end_time", -6, 1) IN ('+', '-') THEN datetime("end_time")
+		WHEN datetime("end_time") >= date(strftime('%Y', "end_time") || '-03-25', 'weekday 0') || ' 02:00:00'
+			AND datetime("end_time") < date(strftime('%Y', "end_time") || '-10-25', 'weekday 0') || ' 03:00:00'
+			THEN datetime("end_time", '-2 hours')
+		ELSE datetime("end_time", '-1 hours')
+	END;
+
+-- SQLite neumí změnit typ sloupce, tabulka se musí přestavět
+DROP INDEX "booking_series_id";
+CREATE TABLE "booking_utc" (
+	"id"	INTEGER NOT NULL UNIQUE,
+	"name"	TEXT NOT NULL,
+	"description"	TEXT NOT NULL,
+	"author"		TEXT NOT NULL,
+	"rooms"	INTEGER NOT NULL,
+	"begin_time"	TIMESTAMP NOT NULL,
+	"end_time"	TIMESTAMP NOT NULL,
+	"layout"	INTEGER NOT NULL,
+	"approved"	INTEGER NOT NULL,
+	"people"	UNSIGNED INT NOT NULL DEFAULT '0',
+	"series_id"	INTEGER REFERENCES "booking_series" ("id"),
+	"status"	TEXT NOT NULL DEFAULT 'pending',
+	"reason"	TEXT,
+	"approved_by"	TEXT,
+	"decided_at"	TIMESTAMP,
+	PRIMARY KEY("id")
+);
+INSERT INTO "booking_utc"
+	SELECT "id", "name", "description", "author", "rooms", "begin_time", "end_time", "layout", "approved", "people",
+		"series_id", "status", "reason", "approved_by", "decided_at"
+	FROM "booking";
+DROP TABLE "booking";
+ALTER TABLE "booking_utc" RENAME TO "booking";
+CREATE INDEX "booking_series_id" ON "booking" ("series_id");
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE "layouts" (
+	"id"          INTEGER NOT NULL UNIQUE,
+	"name"        TEXT NOT NULL UNIQUE,
+	"description" TEXT NOT NULL DEFAULT '',
+	"diagram"     TEXT,
+	PRIMARY KEY("id")
+);
+-- kombinace místností (bitflagy jako v booking.rooms), ve kterých jde rozložení použít
+CREATE TABLE "layout_capacities" (
+	"layout_id" INTEGER NOT NULL REFERENCES "layouts" ("id") ON DELETE CASCADE,
+	"rooms"     INTEGER NOT NULL,
+	"capacity"  INTEGER NOT NULL,
+	PRIMARY KEY("layout_id", "rooms")
+);
+INSERT INTO "layouts" ("id", "name", "description") VALUES
+	(0, 'divadlo', 'řady židlí čelem k pódiu'),
+	(1, 'stoly', 'stoly po šesti, třeba na workshopy');
+INSERT INTO "layout_capacities" ("layout_id", "rooms", "capacity") VALUES
+	(0, 1, 70), (0, 2, 70), (0, 3, 150),
+	(1, 1, 30), (1, 2, 30), (1, 3, 60);
+-- rozložení, která už rezervace používají, bez kapacit, takže se s nimi nedá nic nového rezervovat
+INSERT INTO "layouts" ("id", "name")
+	SELECT DISTINCT "layout", 'rozložení ' || "layout" FROM "booking" WHERE "layout" NOT IN (0, 1);
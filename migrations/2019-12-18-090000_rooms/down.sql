@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "reservation_rooms_room_id";
+DROP TABLE "reservation_rooms";
+DROP TABLE "room_parts";
+DROP TABLE "rooms";
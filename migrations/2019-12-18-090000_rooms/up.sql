@@ -0,0 +1,31 @@
+-- Your SQL goes here
+-- místnost, která není složená z jiných, má svůj bit v booking.rooms
+CREATE TABLE "rooms" (
+	"id"          INTEGER NOT NULL UNIQUE,
+	"name"        TEXT NOT NULL UNIQUE,
+	"description" TEXT NOT NULL DEFAULT '',
+	"capacity"    INTEGER NOT NULL,
+	"bit"         INTEGER UNIQUE,
+	PRIMARY KEY("id")
+);
+-- které místnosti jde spojit do jedné (celé auditorium = sever + jih)
+CREATE TABLE "room_parts" (
+	"room_id" INTEGER NOT NULL REFERENCES "rooms" ("id"),
+	"part_id" INTEGER NOT NULL REFERENCES "rooms" ("id"),
+	PRIMARY KEY("room_id", "part_id")
+);
+-- místnosti, které rezervace zabírá (jen ty s bitem)
+CREATE TABLE "reservation_rooms" (
+	"reservation_id" INTEGER NOT NULL REFERENCES "booking" ("id"),
+	"room_id"        INTEGER NOT NULL REFERENCES "rooms" ("id"),
+	PRIMARY KEY("reservation_id", "room_id")
+);
+CREATE INDEX "reservation_rooms_room_id" ON "reservation_rooms" ("room_id");
+INSERT INTO "rooms" ("id", "name", "description", "capacity", "bit") VALUES
+	(1, 'sever', 'severní polovina auditoria', 70, 1),
+	(2, 'jih', 'jižní polovina auditoria', 70, 2),
+	(3, 'celé auditorium', 'sever a jih spojené dohromady', 150, NULL);
+INSERT INTO "room_parts" ("room_id", "part_id") VALUES (3, 1), (3, 2);
+INSERT INTO "reservation_rooms" ("reservation_id", "room_id")
+	SELECT "booking"."id", "rooms"."id" FROM "booking"
+	JOIN "rooms" ON "rooms"."bit" IS NOT NULL AND "booking"."rooms" & "rooms"."bit" != 0;
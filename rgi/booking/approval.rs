@@ -0,0 +1,179 @@
+//! schvalování rezervací
+//!
+//! stavy rezervace (`status`):
+//! - `pending` -> čeká na schválení, takhle rezervace vzniká
+//! - `approved` -> schválená, jen schválené rezervace blokují místnosti (viz [`super::conflicts`])
+//! - `rejected` -> zamítnutá schvalovatelem, vždy s důvodem v `reason`
+//! - `cancelled` -> zrušená schvalovatelem
+//! - `withdrawn` -> stažená autorem
+//!
+//! povolené přechody:
+//! ```text
+//! pending            --approve-->  approved   (BookingApprove, kontrolují se kolize)
+//! pending, approved  --reject--->  rejected   (BookingApprove, povinný důvod)
+//! pending, approved  --cancel--->  cancelled  (BookingApprove)
+//! pending, approved  --withdraw->  withdrawn  (autor)
+//! approved, rejected --úprava--->  pending    (autor změní čas, místnosti nebo rozložení)
+//! ```
+//! `cancelled` a `withdrawn` jsou konečné, takovou rezervaci už nejde upravit.
+//! Nepovolený přechod je 409 se současným stavem v `status`. `booking.py` hlídá stejná pravidla.
+
+use chrono::NaiveDateTime;
+use diesel::dsl::now;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::conflicts;
+use crate::db::{Reservation, ReservationChanges, User};
+use crate::error::ApiError;
+use crate::rgi::response::wrong_state;
+
+/// čeká na schválení
+pub const PENDING: &str = "pending";
+/// schválená
+pub const APPROVED: &str = "approved";
+/// zamítnutá
+pub const REJECTED: &str = "rejected";
+/// zrušená schvalovatelem
+pub const CANCELLED: &str = "cancelled";
+/// stažená autorem
+pub const WITHDRAWN: &str = "withdrawn";
+
+/// všechny stavy
+pub const STATES: &[&str] = &[PENDING, APPROVED, REJECTED, CANCELLED, WITHDRAWN];
+
+/// důvod rozhodnutí, data pro zamítnutí a zrušení
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Reason {
+	/// důvod, u zamítnutí povinný
+	#[serde(default)]
+	pub reason: Option<String>,
+}
+
+/// rozhodnutí o rezervaci
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+	/// schválit
+	Approve,
+	/// zamítnout s důvodem
+	Reject(String),
+	/// zrušit, důvod je nepovinný
+	Cancel(Option<String>),
+	/// stáhnout (autor)
+	Withdraw,
+}
+
+impl Decision {
+	/// metoda, se kterou se volá `booking.py`
+	pub fn method(&self) -> &'static str {
+		match self {
+			Decision::Approve => "APPROVE",
+			Decision::Reject(_) => "REJECT",
+			Decision::Cancel(_) => "CANCEL",
+			Decision::Withdraw => "WITHDRAW",
+		}
+	}
+
+	/// akce v audit logu
+	pub fn action(&self) -> &'static str {
+		match self {
+			Decision::Approve => "booking.approve",
+			Decision::Reject(_) => "booking.reject",
+			Decision::Cancel(_) => "booking.cancel",
+			Decision::Withdraw => "booking.withdraw",
+		}
+	}
+
+	/// důvod rozhodnutí
+	pub fn reason(&self) -> Option<&str> {
+		match self {
+			Decision::Reject(reason) => Some(reason.as_str()),
+			Decision::Cancel(reason) => reason.as_ref().map(String::as_str),
+			_ => None,
+		}
+	}
+
+	/// rozhoduje schvalovatel (a zapíše se do `approved_by`)?
+	pub fn by_approver(&self) -> bool {
+		*self != Decision::Withdraw
+	}
+
+	/// stav po rozhodnutí, 409 pokud z `current` nevede
+	pub fn next(&self, current: &str) -> Result<&'static str, ApiError> {
+		let (from, to): (&[&str], _) = match self {
+			Decision::Approve => (&[PENDING], APPROVED),
+			Decision::Reject(_) => (&[PENDING, APPROVED], REJECTED),
+			Decision::Cancel(_) => (&[PENDING, APPROVED], CANCELLED),
+			Decision::Withdraw => (&[PENDING, APPROVED], WITHDRAWN),
+		};
+
+		if from.contains(&current) {
+			Ok(to)
+		} else {
+			Err(wrong_state(current))
+		}
+	}
+}
+
+/// zrušenou nebo staženou rezervaci už nejde upravit, jinak 409
+pub fn check_editable(r: &Reservation) -> Result<(), ApiError> {
+	match r.status.as_str() {
+		CANCELLED | WITHDRAWN => Err(wrong_state(&r.status)),
+		_ => Ok(()),
+	}
+}
+
+/// vrátí úprava rezervaci ke schválení?
+///
+/// ano, když autor u schválené nebo zamítnuté rezervace mění čas, místnosti nebo rozložení
+pub fn reopens(current: &Reservation, changes: &ReservationChanges, caller: &User) -> bool {
+	let changed = changes.rooms.map(|r| r != current.rooms).unwrap_or(false)
+		|| changes.begin_time.as_ref().map(|t| *t != current.begin_time).unwrap_or(false)
+		|| changes.end_time.as_ref().map(|t| *t != current.end_time).unwrap_or(false)
+		|| changes.layout.map(|l| l != current.layout).unwrap_or(false);
+
+	changed && current.author.trim() == caller.email.trim() && [APPROVED, REJECTED].contains(&current.status.as_str())
+}
+
+/// vrátí rezervaci do `pending` a zapomene předchozí rozhodnutí
+pub fn reopen(con: &SqliteConnection, r_id: i32) -> QueryResult<usize> {
+	use crate::schema::booking::dsl::*;
+
+	diesel::update(booking.find(r_id))
+		.set((
+			status.eq(PENDING),
+			approved.eq(0),
+			reason.eq(None::<String>),
+			approved_by.eq(None::<String>),
+			decided_at.eq(None::<NaiveDateTime>),
+		))
+		.execute(con)
+}
+
+/// ověří přechod a zapíše rozhodnutí, schválení navíc nesmí kolidovat, vrací nový stav
+pub fn decide(
+	con: &SqliteConnection,
+	r: &Reservation,
+	decision: &Decision,
+	caller: &User,
+) -> Result<&'static str, ApiError> {
+	use crate::schema::booking::dsl::*;
+
+	let next = decision.next(&r.status)?;
+	if next == APPROVED {
+		conflicts::check(con, r.rooms, r.begin_time, r.end_time, Some(r.id))?;
+	}
+
+	let decider = if decision.by_approver() { Some(caller.email.clone()) } else { r.approved_by.clone() };
+	diesel::update(booking.find(r.id))
+		.set((
+			status.eq(next),
+			approved.eq((next == APPROVED) as i32),
+			reason.eq(decision.reason().map(String::from)),
+			approved_by.eq(decider),
+			decided_at.eq(now),
+		))
+		.execute(con)?;
+
+	Ok(next)
+}
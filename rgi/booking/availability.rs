@@ -0,0 +1,424 @@
+//! hledání volných termínů
+//!
+//! `GET /availability` bere parametry [`AvailabilityQuery`]:
+//! - `from`, `to` -> interval, ve kterém se hledá, povinné, nejvýš [`MAX_RANGE_DAYS`] dní,
+//!   časy jako u rezervací (viz [`crate::datetime`])
+//! - `rooms` -> místnosti, pro které se hledá, bitflagy nebo id či jména oddělená čárkou
+//!   (viz [`super::rooms::parse`]), každá zvlášť, bez `rooms` celý katalog
+//! - `duration` -> délka termínu v minutách (výchozí 60), meze jako u rezervace
+//! - `pending` -> s `true` termín blokují i neschválené žádosti, jinak jen schválené rezervace
+//! - `at` -> požadovaný začátek, odpověď v `requested` řekne, ve kterých místnostech je termín
+//!   volný, a když v žádné, nabídne nejbližší volné časy v těchže místnostech (`times`)
+//!   a jiné místnosti volné ve stejný čas (`rooms`), viz [`suggest`]
+//! - `people` -> jiné místnosti se nabízí jen s kapacitou aspoň pro tolik lidí
+//!
+//! `duration`, `pending` a `people`, které nejdou přečíst, jsou 422 jako u [`super::query`].
+//!
+//! volný termín leží v otevírací době ([`OpeningHours`]) a nezasahuje do rezervace, která
+//! s místností sdílí bit, takže celé auditorium je obsazené, když je obsazená kterákoli
+//! polovina, a naopak.
+//!
+//! ```text
+//! GET /rgi/availability?rooms=sever,jih&from=2019-12-12T08:00&to=2019-12-13T08:00&duration=90&at=2019-12-12T18:00
+//! ```
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Timelike};
+use rocket::config::Config;
+use rocket::request::Form;
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+use super::approval::{APPROVED, PENDING};
+use super::backend::{Backend, BookingBackend};
+use super::conflicts::{rooms_intersect, times_overlap};
+use super::query::number;
+use super::rooms::{self, Catalogue, RoomDetail};
+use crate::auth::roles::Noob;
+use crate::auth::AuthToken;
+use crate::datetime;
+use crate::db;
+use crate::db::Rooms;
+use crate::error::{ApiError, ApiResult};
+
+/// nejdelší interval hledání ve dnech
+pub const MAX_RANGE_DAYS: i64 = 31;
+/// výchozí délka termínu v minutách
+pub const DEFAULT_DURATION_MINUTES: i64 = 60;
+/// kolik nejbližších časů se nabídne
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// dny v týdnu, jak se jmenují v konfiguraci, od pondělí
+const DAYS: &[&str] = &["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// otevírací doba auditoria v místním čase
+///
+/// ```toml
+/// opening_hours = { sat = "09:00-18:00", sun = "closed" }
+/// ```
+/// den, který v konfiguraci není, má 07:00-22:00, zavírat jde i ve `24:00`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpeningHours {
+	/// od pondělí (otevření, zavření) v minutách od půlnoci, `None` je zavřeno
+	days: [Option<(i64, i64)>; 7],
+}
+
+impl Default for OpeningHours {
+	fn default() -> Self {
+		OpeningHours { days: [Some((7 * 60, 22 * 60)); 7] }
+	}
+}
+
+impl OpeningHours {
+	/// nastaví otevírací dobu jednoho dne (`mon`, ..., `sun`), `"closed"` je zavřeno
+	pub fn with_day(mut self, day: &str, hours: &str) -> Result<Self, String> {
+		let i = DAYS.iter().position(|d| *d == day).ok_or_else(|| format!("unknown day `{}`, use mon-sun", day))?;
+		self.days[i] = parse_hours(hours)?;
+
+		Ok(self)
+	}
+
+	/// načte `opening_hours` z konfigurace Rocketu
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let mut hours = OpeningHours::default();
+		if let Ok(table) = config.get_table("opening_hours") {
+			for (day, value) in table {
+				let value = value.as_str().ok_or_else(|| format!("opening hours of `{}` must be a string", day))?;
+				hours = hours.with_day(day, value)?;
+			}
+		}
+
+		Ok(hours)
+	}
+
+	/// úseky otevírací doby mezi `from` a `to` v UTC
+	pub fn windows(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Slot> {
+		let mut found = vec![];
+		let mut day = datetime::to_local(from).date();
+		while day <= datetime::to_local(to).date() {
+			if let Some((open, close)) = self.days[day.weekday().num_days_from_monday() as usize] {
+				let midnight = day.and_hms(0, 0, 0);
+				let begin_time = utc(midnight + Duration::minutes(open)).max(from);
+				let end_time = utc(midnight + Duration::minutes(close)).min(to);
+				if begin_time < end_time {
+					found.push(Slot { begin_time, end_time });
+				}
+			}
+			day = day.succ();
+		}
+
+		found
+	}
+}
+
+/// `07:00-22:00` -> minuty od půlnoci, `closed` -> `None`
+fn parse_hours(src: &str) -> Result<Option<(i64, i64)>, String> {
+	let src = src.trim();
+	if src.is_empty() || src == "closed" {
+		return Ok(None);
+	}
+
+	let bad = || format!("invalid opening hours `{}`, use 07:00-22:00 or closed", src);
+	let minutes = |time: &str| match time.trim() {
+		"24:00" => Some(24 * 60),
+		time => NaiveTime::parse_from_str(time, "%H:%M").ok().map(|t| i64::from(t.hour() * 60 + t.minute())),
+	};
+
+	let mut parts = src.splitn(2, '-');
+	let open = parts.next().and_then(minutes).ok_or_else(bad)?;
+	let close = parts.next().and_then(minutes).ok_or_else(bad)?;
+	if open >= close {
+		Err(bad())?
+	}
+
+	Ok(Some((open, close)))
+}
+
+/// místní čas -> UTC, čas zrušený letním časem se posune o hodinu
+fn utc(local: NaiveDateTime) -> NaiveDateTime {
+	datetime::from_local(local).or_else(|| datetime::from_local(local + Duration::hours(1))).unwrap_or(local)
+}
+
+/// termín `[begin_time, end_time)` v UTC
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+	/// začátek
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub begin_time: NaiveDateTime,
+	/// konec
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub end_time:   NaiveDateTime,
+}
+
+impl Slot {
+	/// vejde se do termínu celý `other`?
+	pub fn contains(&self, other: &Slot) -> bool {
+		self.begin_time <= other.begin_time && other.end_time <= self.end_time
+	}
+}
+
+/// volné termíny aspoň `duration` dlouhé v otevírací době mezi `from` a `to`, `busy` jsou obsazené intervaly
+pub fn free_slots(
+	hours: &OpeningHours,
+	from: NaiveDateTime,
+	to: NaiveDateTime,
+	busy: &[Slot],
+	duration: Duration,
+) -> Vec<Slot> {
+	let mut busy = busy.to_vec();
+	busy.sort_by_key(|b| b.begin_time);
+
+	let mut free = vec![];
+	for window in hours.windows(from, to) {
+		let mut begin_time = window.begin_time;
+		let overlapping =
+			busy.iter().filter(|b| times_overlap((b.begin_time, b.end_time), (window.begin_time, window.end_time)));
+		for b in overlapping {
+			if b.begin_time > begin_time {
+				free.push(Slot { begin_time, end_time: b.begin_time });
+			}
+			begin_time = begin_time.max(b.end_time);
+		}
+		if begin_time < window.end_time {
+			free.push(Slot { begin_time, end_time: window.end_time });
+		}
+	}
+
+	free.into_iter().filter(|s| s.end_time - s.begin_time >= duration).collect()
+}
+
+/// parametry hledání, viz dokumentace modulu
+#[derive(FromForm, Debug, Clone, Default, PartialEq)]
+pub struct AvailabilityQuery {
+	/// místnosti, bitflagy nebo id či jména oddělená čárkou
+	pub rooms:    Option<String>,
+	/// hledá se od
+	pub from:     Option<String>,
+	/// hledá se do
+	pub to:       Option<String>,
+	/// délka termínu v minutách
+	pub duration: Option<String>,
+	/// blokují i neschválené žádosti? (`true` nebo `false`)
+	pub pending:  Option<String>,
+	/// požadovaný začátek
+	pub at:       Option<String>,
+	/// kolik lidí se musí vejít do nabízených místností
+	pub people:   Option<String>,
+}
+
+impl AvailabilityQuery {
+	/// interval hledání, oba konce jsou povinné, jinak 422
+	pub fn interval(&self) -> Result<(NaiveDateTime, NaiveDateTime), ApiError> {
+		let parse = |name: &str, value: &Option<String>| {
+			let value = value.as_ref().ok_or_else(|| ApiError::invalid(format!("`{}` is required", name)))?;
+			datetime::parse(value).map_err(ApiError::invalid)
+		};
+		let (from, to) = (parse("from", &self.from)?, parse("to", &self.to)?);
+
+		if to <= from {
+			Err(ApiError::invalid("from must be before to"))?
+		}
+		if to - from > Duration::days(MAX_RANGE_DAYS) {
+			Err(ApiError::invalid(format!("can't search more than {} days at once", MAX_RANGE_DAYS)))?
+		}
+
+		Ok((from, to))
+	}
+
+	/// délka termínu, meze jako u rezervace (viz [`datetime::check_span`])
+	pub fn duration(&self) -> Result<Duration, ApiError> {
+		let minutes = number("duration", &self.duration)?.unwrap_or(DEFAULT_DURATION_MINUTES);
+		if !(datetime::MIN_DURATION_MINUTES..=datetime::MAX_DURATION_DAYS * 24 * 60).contains(&minutes) {
+			Err(ApiError::invalid(format!(
+				"duration must be between {} minutes and {} days",
+				datetime::MIN_DURATION_MINUTES,
+				datetime::MAX_DURATION_DAYS
+			)))?
+		}
+
+		Ok(Duration::minutes(minutes))
+	}
+
+	/// blokují i neschválené žádosti? jako Rocket bere i `on` a `off`, jinak 422
+	pub fn pending(&self) -> Result<bool, ApiError> {
+		match self.pending.as_ref().map(|p| p.trim()) {
+			None | Some("false") | Some("off") => Ok(false),
+			Some("true") | Some("on") => Ok(true),
+			Some(_) => Err(ApiError::invalid("`pending` must be true or false")),
+		}
+	}
+
+	/// kolik lidí se musí vejít do nabízených místností, výchozí 0
+	pub fn people(&self) -> Result<i32, ApiError> {
+		Ok(number("people", &self.people)?.unwrap_or(0))
+	}
+
+	/// požadovaný termín podle `at`, musí ležet v intervalu hledání
+	pub fn requested(
+		&self,
+		from: NaiveDateTime,
+		to: NaiveDateTime,
+		duration: Duration,
+	) -> Result<Option<Slot>, ApiError> {
+		let begin_time = match &self.at {
+			Some(at) => datetime::parse(at).map_err(ApiError::invalid)?,
+			None => return Ok(None),
+		};
+		let slot = Slot { begin_time, end_time: begin_time + duration };
+
+		if slot.begin_time < from || slot.end_time > to {
+			Err(ApiError::invalid("the requested time must lie between from and to"))?
+		}
+
+		Ok(Some(slot))
+	}
+
+	/// id místností, pro které se hledá, každá místnost z `rooms` zvlášť, jinak 422
+	pub fn wanted(&self, catalogue: &Catalogue) -> Result<Vec<i32>, ApiError> {
+		let each = match self.rooms.as_ref().map(|src| rooms::parse(src)) {
+			Some(Rooms::List(refs)) => refs.into_iter().map(|r| Rooms::List(vec![r])).collect(),
+			Some(other) => vec![other],
+			None => return Ok(catalogue.rooms.iter().map(|r| r.room.id).collect()),
+		};
+
+		each.iter().map(|r| catalogue.resolve(r).map(|found| found.room.id)).collect()
+	}
+}
+
+/// volné termíny jedné místnosti
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RoomSlots {
+	/// id místnosti
+	pub id:       i32,
+	/// jméno místnosti
+	pub name:     String,
+	/// kapacita místnosti
+	pub capacity: i32,
+	/// volné termíny
+	pub free:     Vec<Slot>,
+}
+
+/// termín v místnosti, který se nabízí místo požadovaného
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Suggestion {
+	/// id místnosti
+	pub room: i32,
+	/// jméno místnosti
+	pub name: String,
+	/// termín
+	#[serde(flatten)]
+	pub slot: Slot,
+}
+
+/// jak dopadl požadovaný termín
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Requested {
+	/// požadovaný termín
+	#[serde(flatten)]
+	pub slot:      Slot,
+	/// id místností, ve kterých je termín volný
+	pub available: Vec<i32>,
+	/// nejbližší volné časy ve stejných místnostech
+	pub times:     Vec<Suggestion>,
+	/// jiné místnosti volné ve stejný čas
+	pub rooms:     Vec<Suggestion>,
+}
+
+/// odpověď `GET /availability`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Availability {
+	/// volné termíny podle místností
+	pub rooms:     Vec<RoomSlots>,
+	/// požadovaný termín, jen s `at`
+	pub requested: Option<Requested>,
+}
+
+/// požadovaný termín: kde je volný, a když nikde, nejbližší volné časy v `wanted`
+/// a místnosti z `others` pro aspoň `people` lidí, které jsou ve stejný čas volné
+pub fn suggest(slot: Slot, wanted: &[RoomSlots], others: &[RoomSlots], people: i32) -> Requested {
+	let fits = |r: &RoomSlots| r.free.iter().any(|f| f.contains(&slot));
+	let available = wanted.iter().filter(|r| fits(r)).map(|r| r.id).collect::<Vec<_>>();
+	if !available.is_empty() {
+		return Requested { slot, available, times: vec![], rooms: vec![] };
+	}
+
+	// z každého volného termínu začátek co nejblíž požadovanému
+	let length = slot.end_time - slot.begin_time;
+	let mut times = wanted
+		.iter()
+		.flat_map(|r| {
+			r.free.iter().map(move |f| {
+				let begin_time = slot.begin_time.max(f.begin_time).min(f.end_time - length);
+				let slot = Slot { begin_time, end_time: begin_time + length };
+				Suggestion { room: r.id, name: r.name.clone(), slot }
+			})
+		})
+		.collect::<Vec<_>>();
+	times.sort_by_key(|s| ((s.slot.begin_time - slot.begin_time).num_seconds().abs(), s.slot.begin_time, s.room));
+	times.truncate(MAX_SUGGESTIONS);
+
+	let rooms = others
+		.iter()
+		.filter(|r| r.capacity >= people && fits(r))
+		.map(|r| Suggestion { room: r.id, name: r.name.clone(), slot })
+		.collect();
+
+	Requested { slot, available, times, rooms }
+}
+
+/// najde volné termíny podle dotazu, obsazenost bere z backendu
+pub fn search(
+	backend: &dyn BookingBackend,
+	hours: &OpeningHours,
+	query: &AvailabilityQuery,
+) -> Result<Availability, ApiError> {
+	let (from, to) = query.interval()?;
+	let duration = query.duration()?;
+	let (pending, people) = (query.pending()?, query.people()?);
+	let requested = query.requested(from, to, duration)?;
+
+	let catalogue = Catalogue::load(&db::get_con())?;
+	let wanted = query.wanted(&catalogue)?;
+
+	let blocking: &[&str] = if pending { &[APPROVED, PENDING] } else { &[APPROVED] };
+	let busy = backend.filter(catalogue.all(), from, to)?;
+	let busy = busy.into_iter().filter(|r| blocking.contains(&r.status.as_str())).collect::<Vec<_>>();
+
+	let slots_of = |room: &RoomDetail| {
+		let taken = busy
+			.iter()
+			.filter(|r| rooms_intersect(r.rooms, room.mask))
+			.map(|r| Slot { begin_time: r.begin_time, end_time: r.end_time })
+			.collect::<Vec<_>>();
+		RoomSlots {
+			id:       room.room.id,
+			name:     room.room.name.clone(),
+			capacity: room.room.capacity,
+			free:     free_slots(hours, from, to, &taken, duration),
+		}
+	};
+	let (found, others): (Vec<_>, Vec<_>) = catalogue.rooms.iter().map(slots_of).partition(|r| wanted.contains(&r.id));
+
+	let requested = requested.map(|slot| suggest(slot, &found, &others, people));
+	Ok(Availability { rooms: found, requested })
+}
+
+/// vrací volné termíny
+///
+/// GET /availability?<parametry> application/json
+///
+/// parametry: [`AvailabilityQuery`], vrací [`Availability`], špatné parametry jsou 422
+#[get("/availability?<query..>")]
+pub fn availability(
+	query: Form<AvailabilityQuery>,
+	_u: AuthToken<Noob>,
+	backend: State<Backend>,
+	hours: State<OpeningHours>,
+) -> ApiResult<Availability> {
+	Ok(Json(search(&**backend, &hours, &query)?))
+}
+
+/// vrací routy hledání volných termínů
+pub fn routes() -> Vec<Route> {
+	routes![availability]
+}
@@ -0,0 +1,315 @@
+//! úložiště rezervací
+//!
+//! endpointy v [`super`] nepracují s databází ani s RGI přímo, ale přes
+//! [`BookingBackend`]. Implementace jsou dvě:
+//! - [`DieselBackend`] -> čistý Rust nad tabulkou `booking`, nepotřebuje Python
+//! - [`RgiBackend`] -> volá `rgi/booking/booking.py`
+//!
+//! kolize s jinými rezervacemi a stavy rezervací hlídá [`DieselBackend`] přes
+//! [`super::conflicts`] a [`super::approval`], `booking.py` stejná pravidla dodržuje sám.
+//!
+//! vybírá se v Rocket.toml:
+//! ```toml
+//! booking_backend = "diesel" # nebo "rgi"
+//! rgi_booking_db = "auditorium_booking.sqlite3" # databáze pro booking.py, jen u "rgi"
+//! ```
+//! `booking.py` dostane cestu k databázi v obálce (`db`), `DATABASE_URL` ze serveru
+//! do sandboxu nepropadne.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use rocket::config::Config;
+use rocket::http::Status;
+
+use super::approval::{self, Decision, Reason};
+use super::conflicts;
+use super::query::{self, EventQuery};
+use super::EventDetail;
+use crate::datetime;
+use crate::db;
+use crate::db::{InsertReservation, NewReservation, Reservation, ReservationChanges, UpdateReservation, User};
+use crate::error::ApiError;
+use crate::rgi::envelope::Envelope;
+use crate::rgi::response::{Done, Results, RgiResponse};
+use crate::rgi::runner::{RgiError, Runner};
+
+/// operace nad rezervacemi
+///
+/// chybějící rezervace je 404, kolize s jinou rezervací 409 s jejich id
+pub trait BookingBackend: Send + Sync {
+	/// všechny rezervace
+	fn list(&self) -> Result<Vec<Reservation>, ApiError>;
+
+	/// stránka rezervací podle filtru a jejich celkový počet
+	///
+	/// výchozí implementace načte [`BookingBackend::list`] a vyfiltruje ho v Rustu
+	fn search(&self, query: &EventQuery) -> Result<(Vec<Reservation>, i64), ApiError> {
+		Ok(query::in_memory(self.list()?, query))
+	}
+
+	/// jedna rezervace i se jménem autora
+	fn get(&self, id: i32) -> Result<EventDetail, ApiError>;
+
+	/// vytvoří neschválenou rezervaci, vrací její id
+	fn create(&self, input: &NewReservation, author: &User) -> Result<i32, ApiError>;
+
+	/// upraví rezervaci
+	fn update(&self, id: i32, input: &UpdateReservation, caller: &User) -> Result<(), ApiError>;
+
+	/// smaže rezervaci
+	fn delete(&self, id: i32, caller: &User) -> Result<(), ApiError>;
+
+	/// rezervace v místnostech `rooms`, které zasahují do daného intervalu `[begin_time, end_time)`
+	fn filter(
+		&self,
+		rooms: i32,
+		begin_time: NaiveDateTime,
+		end_time: NaiveDateTime,
+	) -> Result<Vec<Reservation>, ApiError>;
+
+	/// rozhodne o rezervaci (schválí, zamítne, zruší nebo stáhne), nepovolený přechod je 409
+	fn decide(&self, id: i32, decision: &Decision, caller: &User) -> Result<(), ApiError>;
+
+	/// schválí rezervaci
+	fn approve(&self, id: i32, caller: &User) -> Result<(), ApiError> {
+		self.decide(id, &Decision::Approve, caller)
+	}
+}
+
+/// backend tak, jak je uložený ve stavu Rocketu
+pub type Backend = Box<dyn BookingBackend>;
+
+/// vybere implementaci podle `booking_backend` v konfiguraci (výchozí `rgi`)
+pub fn from_config(config: &Config, runner: Runner) -> Result<Backend, String> {
+	match config.get_string("booking_backend").unwrap_or_else(|_| "rgi".to_string()).as_str() {
+		"diesel" => Ok(Box::new(DieselBackend)),
+		"rgi" => {
+			let path = config.get_string("rgi_booking_db").map_err(|_| "rgi_booking_db must be set".to_string())?;
+			Ok(Box::new(RgiBackend::new(runner).with_db(&path)))
+		}
+		other => Err(format!("unknown booking backend `{}`", other)),
+	}
+}
+
+/// rezervace přímo v databázi přes Diesel
+pub struct DieselBackend;
+
+impl DieselBackend {
+	/// načte rezervaci, 404 pokud není
+	fn find(con: &SqliteConnection, r_id: i32) -> Result<Reservation, ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		booking.find(r_id).first::<Reservation>(con).optional()?.ok_or_else(|| ApiError::not_found("no such event"))
+	}
+}
+
+impl BookingBackend for DieselBackend {
+	fn list(&self) -> Result<Vec<Reservation>, ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		Ok(booking.order(id).load::<Reservation>(&db::get_con())?)
+	}
+
+	fn search(&self, query: &EventQuery) -> Result<(Vec<Reservation>, i64), ApiError> {
+		query::load(&db::get_con(), query)
+	}
+
+	fn get(&self, r_id: i32) -> Result<EventDetail, ApiError> {
+		use crate::schema::users::dsl::{email, name, users};
+
+		let con = db::get_con();
+		let reservation = DieselBackend::find(&con, r_id)?;
+		let author_name =
+			users.filter(email.eq(&reservation.author)).select(name).first::<String>(&con).optional()?;
+
+		Ok(EventDetail { reservation, author_name, room_names: Vec::new() })
+	}
+
+	fn create(&self, input: &NewReservation, author: &User) -> Result<i32, ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		let new = InsertReservation::new(input, &author.email);
+		let con = db::get_con();
+
+		con.immediate_transaction(|| {
+			conflicts::check(&con, new.rooms, new.begin_time, new.end_time, None)?;
+
+			diesel::insert_into(booking).values(&new).execute(&con)?;
+			Ok(booking.select(id).order(id.desc()).first::<i32>(&con)?)
+		})
+	}
+
+	fn update(&self, r_id: i32, input: &UpdateReservation, caller: &User) -> Result<(), ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		let con = db::get_con();
+		let changes = ReservationChanges::from(input);
+
+		con.immediate_transaction(|| {
+			let current = DieselBackend::find(&con, r_id)?;
+			approval::check_editable(&current)?;
+			if changes.is_empty() {
+				return Ok(());
+			}
+
+			// nový čas nebo místnosti se kontrolují, jako by to byla nová rezervace
+			if changes.rooms.is_some() || changes.begin_time.is_some() || changes.end_time.is_some() {
+				let r_rooms = changes.rooms.unwrap_or(current.rooms);
+				let begin = changes.begin_time.unwrap_or(current.begin_time);
+				let end = changes.end_time.unwrap_or(current.end_time);
+				conflicts::check(&con, r_rooms, begin, end, Some(r_id))?;
+			}
+
+			diesel::update(booking.find(r_id)).set(&changes).execute(&con)?;
+			if approval::reopens(&current, &changes, caller) {
+				approval::reopen(&con, r_id)?;
+			}
+			Ok(())
+		})
+	}
+
+	fn delete(&self, r_id: i32, _caller: &User) -> Result<(), ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		match diesel::delete(booking.find(r_id)).execute(&db::get_con())? {
+			0 => Err(ApiError::not_found("no such event")),
+			_ => Ok(()),
+		}
+	}
+
+	fn filter(&self, r_rooms: i32, begin: NaiveDateTime, end: NaiveDateTime) -> Result<Vec<Reservation>, ApiError> {
+		use crate::schema::booking::dsl::*;
+
+		let found = booking
+			.filter(begin_time.lt(end))
+			.filter(end_time.gt(begin))
+			.order(begin_time)
+			.load::<Reservation>(&db::get_con())?;
+
+		// rezervace celého auditoria zabírá i obě poloviny
+		Ok(found.into_iter().filter(|r| conflicts::rooms_intersect(r.rooms, r_rooms)).collect())
+	}
+
+	fn decide(&self, r_id: i32, decision: &Decision, caller: &User) -> Result<(), ApiError> {
+		let con = db::get_con();
+		con.immediate_transaction(|| {
+			let r = DieselBackend::find(&con, r_id)?;
+			approval::decide(&con, &r, decision, caller)?;
+			Ok(())
+		})
+	}
+}
+
+/// rezervace přes `rgi/booking/booking.py`
+pub struct RgiBackend {
+	runner: Runner,
+	/// databáze, kterou `booking.py` dostane v obálce
+	db:     Option<String>,
+}
+
+impl RgiBackend {
+	/// backend volající RGI přes daný runner
+	pub fn new(runner: Runner) -> Self {
+		RgiBackend { runner, db: None }
+	}
+
+	/// nastaví databázi pro `booking.py`
+	pub fn with_db(mut self, path: &str) -> Self {
+		self.db = Some(path.to_string());
+		self
+	}
+
+	/// spustí `booking.py`, obálce doplní databázi
+	fn run(&self, script: &str, envelope: &Envelope) -> Result<String, RgiError> {
+		match &self.db {
+			Some(path) => self.runner.run(script, &envelope.clone().db(path)),
+			None => self.runner.run(script, envelope),
+		}
+	}
+}
+
+impl BookingBackend for RgiBackend {
+	fn list(&self) -> Result<Vec<Reservation>, ApiError> {
+		let backend = self;
+		let output = rgi! {
+			backend => LIST "rgi/booking/booking.py"
+		}?;
+
+		Ok(RgiResponse::parse(&output)?.into_result()?.into_inner())
+	}
+
+	fn get(&self, id: i32) -> Result<EventDetail, ApiError> {
+		let backend = self;
+		let output = rgi! {
+			backend => GET "rgi/booking/booking.py"
+			arg: id
+		}?;
+
+		Ok(RgiResponse::parse(&output)?.into_result()?.into_inner())
+	}
+
+	fn create(&self, input: &NewReservation, author: &User) -> Result<i32, ApiError> {
+		let backend = self;
+		let output = rgi! {
+			backend => POST "rgi/booking/booking.py"
+			data: input
+			caller: author
+		}?;
+
+		let done: Done = RgiResponse::parse(&output)?.into_result()?.into_inner();
+		done.id.ok_or_else(|| ApiError::new(Status::BadGateway, "RGI didn't return the new id"))
+	}
+
+	fn update(&self, id: i32, input: &UpdateReservation, caller: &User) -> Result<(), ApiError> {
+		let backend = self;
+		let output = rgi! {
+			backend => PATCH "rgi/booking/booking.py"
+			arg: id
+			data: input
+			caller: caller
+		}?;
+
+		RgiResponse::<Done>::parse(&output)?.into_result().map(|_| ())
+	}
+
+	fn delete(&self, id: i32, caller: &User) -> Result<(), ApiError> {
+		let backend = self;
+		let output = rgi! {
+			backend => DELETE "rgi/booking/booking.py"
+			arg: id
+			caller: caller
+		}?;
+
+		RgiResponse::<Done>::parse(&output)?.into_result().map(|_| ())
+	}
+
+	fn filter(
+		&self,
+		rooms: i32,
+		begin_time: NaiveDateTime,
+		end_time: NaiveDateTime,
+	) -> Result<Vec<Reservation>, ApiError> {
+		let (begin_time, end_time) = (datetime::format(begin_time), datetime::format(end_time));
+		let backend = self;
+		let output = rgi! {
+			backend => FILTER "rgi/booking/booking.py"
+			arg: rooms,
+			arg: begin_time,
+			arg: end_time
+		}?;
+
+		let found: Results<Reservation> = RgiResponse::parse(&output)?.into_result()?.into_inner();
+		Ok(found.results)
+	}
+
+	fn decide(&self, id: i32, decision: &Decision, caller: &User) -> Result<(), ApiError> {
+		// metoda závisí na rozhodnutí, takže obálka bez makra rgi!
+		let mut envelope = Envelope::new(decision.method()).arg("id", &id).caller(caller);
+		if let Some(reason) = decision.reason() {
+			envelope = envelope.data(&Reason { reason: Some(reason.to_string()) });
+		}
+		let output = self.run("rgi/booking/booking.py", &envelope)?;
+
+		RgiResponse::<Done>::parse(&output)?.into_result().map(|_| ())
+	}
+}
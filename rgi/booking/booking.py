@@ -4,22 +4,53 @@ from sqlalchemy.ext.declarative import DeclarativeMeta
 from sqlalchemy.ext.automap import automap_base
 from sqlalchemy.orm import Session
 from sqlalchemy import create_engine
+from sqlalchemy.dialects.sqlite import DATETIME
 from mail import send_request, send_approval, send_denial
+from datetime import datetime, timezone
 import sys
 import json
-import os
-import re
 
 
-Base = automap_base()
-engine = create_engine("sqlite:///" + os.getenv("DATABASE_URL"))
-Base.prepare(engine, reflect=True)
-Booking = Base.classes.booking
-User = Base.classes.users
-session = Session(engine)
+Booking = User = session = None
+database = None
+times = ("begin_time", "end_time")
+
+
+def connect(path):
+    """
+    opens the database the server sent in the envelope ("db", see rgi/envelope.rs),
+    it is never taken from the environment
+    """
+    global Booking, User, session, database
+    if path == database:
+        return
+    if not path:
+        raise ValueError("the envelope has no database, set rgi_booking_db")
+
+    base = automap_base()
+    engine = create_engine("sqlite:///" + path)
+    base.prepare(engine, reflect=True)
+    Booking = base.classes.booking
+    User = base.classes.users
+    session = Session(engine)
+    database = path
+
+    # times are TIMESTAMP in UTC, stored the same way as Diesel does it, see src/datetime.rs
+    for column in (Booking.__table__.c.begin_time, Booking.__table__.c.end_time):
+        column.type = DATETIME(storage_format="%(year)04d-%(month)02d-%(day)02d %(hour)02d:%(minute)02d:%(second)02d")
 
 approver = "xsicp01@gjk.cz"
 
+PENDING, APPROVED, REJECTED, CANCELLED, WITHDRAWN = "pending", "approved", "rejected", "cancelled", "withdrawn"
+
+# same transitions as rgi/booking/approval.rs: method -> (allowed states, new state)
+transitions = {
+    "approve": ([PENDING], APPROVED),
+    "reject": ([PENDING, APPROVED], REJECTED),
+    "cancel": ([PENDING, APPROVED], CANCELLED),
+    "withdraw": ([PENDING, APPROVED], WITHDRAWN),
+}
+
 
 class AlchemyEncoder(json.JSONEncoder):
 
@@ -31,6 +62,10 @@ class AlchemyEncoder(json.JSONEncoder):
                 if field == "classes" or field == "prepare":
                     continue
                 data = obj.__getattribute__(field)
+                if isinstance(data, datetime):
+                    # all times in the database are UTC
+                    fields[field] = data.isoformat() + "Z"
+                    continue
                 try:
                     json.dumps(data) # this will fail on non-encodable values, like other classes
                     fields[field] = data
@@ -42,6 +77,43 @@ class AlchemyEncoder(json.JSONEncoder):
         return json.JSONEncoder.default(self, obj)
 
 
+def parse_time(value):
+    """
+    Parses RFC 3339 time sent by rust
+    :param value: string like 2019-12-12T17:00:00Z
+    :return: naive datetime in UTC
+    """
+    parsed = datetime.fromisoformat(value.replace("Z", "+00:00"))
+    if parsed.tzinfo is not None:
+        parsed = parsed.astimezone(timezone.utc).replace(tzinfo=None)
+    return parsed
+
+
+def values(data):
+    """
+    Data of the envelope with times parsed
+    :param data: envelope
+    :return: dict
+    """
+    return {key: parse_time(value) if key in times and value is not None else value
+            for key, value in data["data"].items()}
+
+
+def find_conflicts(reservation):
+    """
+    Finds approved reservations colliding with the given one,
+    same rules as rgi/booking/conflicts.rs: [begin, end) intervals, rooms are bitflags
+    :param reservation: Booking
+    :return: list of ids
+    """
+    events = session.query(Booking).filter(Booking.status == APPROVED).\
+                                    filter(Booking.id != reservation.id).\
+                                    filter(Booking.begin_time < reservation.end_time).\
+                                    filter(Booking.end_time > reservation.begin_time).\
+                                    order_by(Booking.id)
+    return [event.id for event in events if event.rooms & reservation.rooms]
+
+
 def get(data):
     """
     Get data from the database
@@ -51,8 +123,8 @@ def get(data):
     results = session.query(Booking).filter(Booking.id == data["args"]["id"]).all()
     if len(results) == 1:
         result = results[0]
-        user = session.query(Booking).filter(User.email == result.author).all()
-        setattr(result, "author_name", user[0].name)
+        user = session.query(User).filter(User.email == result.author).first()
+        setattr(result, "author_name", user.name if user is not None else None)
         return json.dumps(result, cls=AlchemyEncoder)
     else:
         return json.dumps({"result": 1})
@@ -72,25 +144,21 @@ def post(data):
     """
     Adds new data to db
     :param data: Booking dictionary by it's id
-    :return: {result: number}
+    :return: {result: number, id: number} or {result: 2, conflicts: [id]}
     """
 
     result = Booking()
-    for key, value in data["data"].items():
+    for key, value in values(data).items():
         if value is None:
             continue
         setattr(result, key, value)
     setattr(result, "approved", False)
-    setattr(result, "author", data["args"]["email"])
+    setattr(result, "status", PENDING)
+    setattr(result, "author", data["caller"]["email"])
 
-    events = session.query(Booking).filter(Booking.approved == 1).\
-                                    filter(Booking.begin_time <= result.end_time).\
-                                    filter(Booking.end_time >= result.begin_time)
-    for event in events:
-        if event.rooms == 3:
-            return json.dumps({"result": 2})
-        elif event.rooms == result.rooms:
-            return json.dumps({"result": 2})
+    conflicts = find_conflicts(result)
+    if conflicts:
+        return json.dumps({"result": 2, "conflicts": conflicts})
 
     session.add(result)
     session.commit()
@@ -104,12 +172,11 @@ def filter(data):
     :return: {results: array of result}
     """
 
-    reservations = session.query(Booking).filter(Booking.begin_time <= data["args"]["end_time"]).\
-                                          filter(Booking.end_time >= data["args"]["begin_time"])
-    if data["args"]["rooms"] != 3:
-        reservations.filter(Booking.rooms == data["args"]["rooms"])
-
-    results = reservations.all()
+    reservations = session.query(Booking).filter(Booking.begin_time < parse_time(data["args"]["end_time"])).\
+                                          filter(Booking.end_time > parse_time(data["args"]["begin_time"])).\
+                                          order_by(Booking.begin_time)
+    # whole auditorium takes both halves too
+    results = [r for r in reservations.all() if r.rooms & data["args"]["rooms"]]
     return json.dumps({"results": results}, cls=AlchemyEncoder)
 
 
@@ -124,8 +191,34 @@ def patch(data):
     results = session.query(Booking).filter(Booking.id == data["args"]["id"]).all()
     if len(results) == 1:
         result = results[0]
-        for key, value in data["data"].items():
+        if result.status in (CANCELLED, WITHDRAWN):
+            return json.dumps({"result": 3, "status": result.status})
+
+        # the author moving an approved or rejected event sends it back for approval
+        changes = values(data)
+        reopens = result.author.strip() == data["caller"]["email"].strip() and \
+            result.status in (APPROVED, REJECTED) and \
+            any(changes.get(key) not in (None, getattr(result, key))
+                for key in ("rooms", "begin_time", "end_time", "layout"))
+
+        for key, value in changes.items():
+            if value is None:
+                continue
             setattr(result, key, value)
+
+        if reopens:
+            result.status = PENDING
+            result.approved = 0
+            result.reason = None
+            result.approved_by = None
+            result.decided_at = None
+
+        if any(changes.get(key) is not None for key in ("rooms", "begin_time", "end_time")):
+            conflicts = find_conflicts(result)
+            if conflicts:
+                session.rollback()
+                return json.dumps({"result": 2, "conflicts": conflicts})
+
         session.add(result)
         session.commit()
         return json.dumps({"result": 0})
@@ -147,27 +240,33 @@ def delete(data):
     else:
         return json.dumps({"result": 1})  # no result found by the id
 
-def approve(data):
+def decide(data):
     """
-    Approvs event by it's id
-    :param data: {id}
-    :return: ?
+    Approves, rejects, cancels or withdraws event by it's id, see transitions
+    :param data: {id}, data: {reason}
+    :return: {result: number} or {result: 2, conflicts: [id]} or {result: 3, status: current state}
     """
 
     results = session.query(Booking).filter(Booking.id == data["args"]["id"]).all()
     if len(results) == 1:
         result = results[0]
-        events = session.query(Booking).filter(Booking.approved == 1).\
-                                    filter(Booking.begin_time <= result.end_time).\
-                                    filter(Booking.end_time >= result.begin_time)
-        for event in events:
-            if event.rooms == 3:
-                return json.dumps({"result": 2})
-            elif event.rooms == result.rooms:
-                return json.dumps({"result": 2})
-
-        #send_approval("xsicp01@gjk.cz", "xsicp01@gjk.cz", result.rooms, result.begin_time, result.end_time)
-        result.approved = 1
+        method = data["method"].lower()
+        allowed, new_state = transitions[method]
+        if result.status not in allowed:
+            return json.dumps({"result": 3, "status": result.status})
+
+        if new_state == APPROVED:
+            conflicts = find_conflicts(result)
+            if conflicts:
+                return json.dumps({"result": 2, "conflicts": conflicts})
+            #send_approval("xsicp01@gjk.cz", "xsicp01@gjk.cz", result.rooms, result.begin_time, result.end_time)
+
+        result.status = new_state
+        result.approved = 1 if new_state == APPROVED else 0
+        result.reason = (data.get("data") or {}).get("reason")
+        if method != "withdraw":
+            result.approved_by = data["caller"]["email"]
+        result.decided_at = datetime.utcnow()
         session.add(result)
         session.commit()
         return json.dumps({"result": 0})
@@ -175,14 +274,46 @@ def approve(data):
         return json.dumps({"result": 1})  # no result found by the id
 
 
-methods = {"list": list_, "get": get, "post": post, "patch": patch, "delete": delete, "approve" : approve}
-txt = sys.stdin.read()
-txt = re.sub(",[ \t\r\n]+}", "}", txt)
-txt = re.sub(",[ \t\r\n]+\]", "]", txt)
-print(txt, file=sys.stderr)
-data = json.loads(txt)
-if len(sys.argv) < 2:
-    sys.stdout.write(methods["get"](data))
+methods = {"list": list_, "get": get, "post": post, "patch": patch, "delete": delete, "filter": filter,
+           "approve": decide, "reject": decide, "cancel": decide, "withdraw": decide}
+
+
+def worker():
+    """
+    Persistent worker, reads one envelope per line and answers {id, response} or {id, error}
+    """
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        data = json.loads(line)
+        method = data["method"].lower()
+        try:
+            if method == "ping":
+                response = "pong"
+            else:
+                connect(data.get("db"))
+                # the session outlives requests, don't serve stale data
+                session.expire_all()
+                response = json.loads(methods[method](data))
+            reply = {"id": data["id"], "response": response}
+        except Exception as e:
+            if session is not None:
+                session.rollback()
+            print("{} failed: {!r}".format(method, e), file=sys.stderr)
+            reply = {"id": data["id"], "error": str(e)}
+        sys.stdout.write(json.dumps(reply) + "\n")
+        sys.stdout.flush()
+
+
+if len(sys.argv) > 1 and sys.argv[1] == "--worker":
+    worker()
 else:
-    sys.stdout.write(methods[sys.argv[1].lower()](data))
-sys.stdout.flush()
+    txt = sys.stdin.read()
+    print(txt, file=sys.stderr)
+    data = json.loads(txt)
+    connect(data.get("db"))
+    if len(sys.argv) < 2:
+        sys.stdout.write(methods["get"](data))
+    else:
+        sys.stdout.write(methods[sys.argv[1].lower()](data))
+    sys.stdout.flush()
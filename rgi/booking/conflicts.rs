@@ -0,0 +1,89 @@
+//! kolize rezervací
+//!
+//! dvě rezervace kolidují, když:
+//! - se překrývají časy, rezervace jsou polootevřené intervaly `[begin_time, end_time)`,
+//!   takže navazující rezervace (jedna končí v 10:00, druhá v 10:00 začíná) nekolidují
+//! - sdílí aspoň jednu místnost, `rooms` jsou bitflagy z katalogu místností (`0b01` sever,
+//!   `0b10` jih, `0b11` celé auditorium, viz [`super::rooms`]), takže celé auditorium
+//!   koliduje s oběma polovinami
+//!
+//! kontroluje se proti schváleným rezervacím, neschválených žádostí může být
+//! na stejný čas víc. [`check`] se volá uvnitř transakce spolu se zápisem,
+//! při kolizi vrací 409 s id kolidujících rezervací v `conflicts`.
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use super::approval::APPROVED;
+use super::rooms::Catalogue;
+use crate::datetime;
+use crate::db::{Reservation, Rooms};
+use crate::error::ApiError;
+use crate::rgi::response::conflict;
+
+/// sdílí rezervace aspoň jednu místnost?
+pub fn rooms_intersect(a: i32, b: i32) -> bool {
+	a & b != 0
+}
+
+/// překrývají se intervaly `[a.0, a.1)` a `[b.0, b.1)`?
+pub fn times_overlap<T: PartialOrd>(a: (T, T), b: (T, T)) -> bool {
+	a.0 < b.1 && b.0 < a.1
+}
+
+/// zkontroluje, že rezervace má nějakou místnost a rozumné časy (viz [`datetime::check_span`]), jinak 422
+///
+/// jestli místnosti odpovídají katalogu, kontroluje až [`check`]
+pub fn validate(rooms: i32, begin_time: NaiveDateTime, end_time: NaiveDateTime) -> Result<(), ApiError> {
+	if rooms <= 0 {
+		Err(ApiError::invalid("a reservation needs at least one room"))?
+	}
+
+	datetime::check_span(begin_time, end_time)
+}
+
+/// id schválených rezervací, se kterými by rezervace kolidovala (kromě `except`)
+pub fn find(
+	con: &SqliteConnection,
+	r_rooms: i32,
+	begin: NaiveDateTime,
+	end: NaiveDateTime,
+	except: Option<i32>,
+) -> QueryResult<Vec<i32>> {
+	use crate::schema::booking::dsl::*;
+
+	// SQL hrubě vybere podle času, místnosti a přesné hranice dořeší Rust
+	let events = booking
+		.filter(status.eq(APPROVED))
+		.filter(id.ne(except.unwrap_or(-1)))
+		.filter(begin_time.le(end))
+		.filter(end_time.ge(begin))
+		.order(id)
+		.load::<Reservation>(con)?;
+
+	Ok(events
+		.into_iter()
+		.filter(|e| rooms_intersect(e.rooms, r_rooms))
+		.filter(|e| times_overlap((e.begin_time, e.end_time), (begin, end)))
+		.map(|e| e.id)
+		.collect())
+}
+
+/// zvaliduje rezervaci, ověří, že `rooms` je místnost z katalogu, a že s ničím nekoliduje, jinak 422 nebo 409
+pub fn check(
+	con: &SqliteConnection,
+	rooms: i32,
+	begin: NaiveDateTime,
+	end: NaiveDateTime,
+	except: Option<i32>,
+) -> Result<(), ApiError> {
+	validate(rooms, begin, end)?;
+	Catalogue::load(con)?.resolve(&Rooms::Mask(rooms))?;
+
+	let conflicts = find(con, rooms, begin, end, except)?;
+	if !conflicts.is_empty() {
+		Err(conflict(conflicts))?
+	}
+
+	Ok(())
+}
@@ -0,0 +1,297 @@
+//! katalog rozložení nábytku
+//!
+//! rozložení má název, popis, odkaz na nákres a kapacity: pro každou místnost z katalogu
+//! (bitflagy jako u rezervace, viz [`super::rooms`]), ve které jde rozložení použít, kolik lidí
+//! se do něj vejde. Kapacita rozložení nesmí přesáhnout kapacitu místnosti.
+//! `layout` rezervace je id rozložení. Rezervace musí mít rozložení, které existuje a jde
+//! použít v jejích místnostech, a `people` nesmí přesáhnout kapacitu, jinak 422, viz [`check`].
+//! Rozložení se ukládají přes Diesel bez ohledu na `booking_backend` a kontrolují se v routách
+//! ještě před zavoláním backendu.
+//!
+//! - `GET /layouts` -> všechna rozložení i s kapacitami
+//! - `GET /layouts/<id>` -> jedno rozložení
+//! - `POST /layouts` -> nové rozložení, data [`NewLayout`]
+//! - `PATCH /layouts/<id>` -> úprava, data [`UpdateLayout`], kapacity se nahradí všechny
+//! - `DELETE /layouts/<id>` -> smaže rozložení, které žádná rezervace nepoužívá, jinak 409
+//!
+//! úpravy vyžadují oprávnění [`LayoutManage`], už uložené rezervace se při změně kapacit
+//! nepřepočítávají.
+
+use diesel::prelude::*;
+use rocket::http::Status;
+use rocket::Route;
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+use super::rooms::Catalogue;
+use crate::audit::{self, RequestId, Target};
+use crate::auth::permissions::actions::LayoutManage;
+use crate::auth::permissions::Permitted;
+use crate::db;
+use crate::db::{Capacity, InsertCapacity, InsertLayout, Layout, LayoutChanges, NewLayout, Reservation};
+use crate::db::{ReservationChanges, UpdateLayout};
+use crate::error::{ApiError, ApiResult};
+use crate::rgi::response::Done;
+
+/// rozložení i s kapacitami, tak jak ho vrací GET
+#[derive(Serialize, Debug, Clone)]
+pub struct LayoutDetail {
+	/// rozložení
+	#[serde(flatten)]
+	pub layout:     Layout,
+	/// kapacity podle místností
+	pub capacities: Vec<Capacity>,
+}
+
+/// ověří, že rozložení existuje, jde použít v `rooms` a vejde se do něj `people`, jinak 422
+pub fn check(con: &SqliteConnection, l_id: i32, r_rooms: i32, r_people: i32) -> Result<(), ApiError> {
+	use crate::schema::layouts::dsl::layouts;
+
+	let found = layouts.find(l_id).first::<Layout>(con).optional()?;
+	let found = found.ok_or_else(|| ApiError::invalid(format!("unknown layout {}", l_id)))?;
+
+	let capacity = capacities(con, l_id)?.into_iter().find(|c| c.rooms == r_rooms).ok_or_else(|| {
+		ApiError::invalid(format!("layout `{}` can't be used in rooms {}", found.name, r_rooms)).with("layout", l_id)
+	})?;
+
+	if r_people > capacity.capacity {
+		Err(ApiError::invalid(format!("layout `{}` seats at most {} people", found.name, capacity.capacity))
+			.with("capacity", capacity.capacity))?
+	}
+
+	Ok(())
+}
+
+/// jako [`check`] pro upravenou rezervaci, kontroluje se jen při změně místností, rozložení nebo počtu lidí
+pub fn check_changes(
+	con: &SqliteConnection,
+	before: &Reservation,
+	change: &ReservationChanges,
+) -> Result<(), ApiError> {
+	if change.rooms.is_none() && change.layout.is_none() && change.people.is_none() {
+		return Ok(());
+	}
+
+	check(
+		con,
+		change.layout.unwrap_or(before.layout),
+		change.rooms.unwrap_or(before.rooms),
+		change.people.unwrap_or(before.people),
+	)
+}
+
+/// kapacity rozložení podle místností
+fn capacities(con: &SqliteConnection, l_id: i32) -> QueryResult<Vec<Capacity>> {
+	use crate::schema::layout_capacities::dsl::*;
+
+	layout_capacities.filter(layout_id.eq(l_id)).select((rooms, capacity)).order(rooms).load::<Capacity>(con)
+}
+
+/// načte rozložení i s kapacitami
+fn detail(con: &SqliteConnection, l_id: i32) -> Result<LayoutDetail, ApiError> {
+	use crate::schema::layouts::dsl::layouts;
+
+	let layout = layouts.find(l_id).first::<Layout>(con).optional()?;
+	let layout = layout.ok_or_else(|| ApiError::not_found("no such layout"))?;
+
+	Ok(LayoutDetail { capacities: capacities(con, l_id)?, layout })
+}
+
+/// zkontroluje název a kapacity podle katalogu místností, jinak 422
+fn validate(con: &SqliteConnection, l_name: &str, caps: &[Capacity]) -> Result<(), ApiError> {
+	if l_name.is_empty() {
+		Err(ApiError::invalid("a layout needs a name"))?
+	}
+
+	let catalogue = Catalogue::load(con)?;
+	for (i, c) in caps.iter().enumerate() {
+		let room = catalogue.by_mask(c.rooms);
+		let room = room.ok_or_else(|| ApiError::invalid(format!("unknown rooms {}", c.rooms)).with("rooms", c.rooms))?;
+		if c.capacity <= 0 {
+			Err(ApiError::invalid("capacity must be a positive number"))?
+		}
+		if c.capacity > room.room.capacity {
+			Err(ApiError::invalid(format!("`{}` seats at most {} people", room.room.name, room.room.capacity))
+				.with("capacity", room.room.capacity))?
+		}
+		if caps[..i].iter().any(|other| other.rooms == c.rooms) {
+			Err(ApiError::invalid(format!("rooms {} are listed twice", c.rooms)))?
+		}
+	}
+
+	Ok(())
+}
+
+/// 409, pokud už jiné rozložení tenhle název má
+fn check_unique(con: &SqliteConnection, l_name: &str, except: Option<i32>) -> Result<(), ApiError> {
+	use crate::schema::layouts::dsl::*;
+
+	let taken = layouts.filter(name.eq(l_name)).filter(id.ne(except.unwrap_or(-1))).select(id).first::<i32>(con);
+	if taken.optional()?.is_some() {
+		Err(ApiError::new(Status::Conflict, format!("layout `{}` already exists", l_name)))?
+	}
+
+	Ok(())
+}
+
+/// nahradí kapacity rozložení
+fn set_capacities(con: &SqliteConnection, l_id: i32, caps: &[Capacity]) -> QueryResult<()> {
+	use crate::schema::layout_capacities::dsl::*;
+
+	diesel::delete(layout_capacities.filter(layout_id.eq(l_id))).execute(con)?;
+	let rows = caps
+		.iter()
+		.map(|c| InsertCapacity { layout_id: l_id, rooms: c.rooms, capacity: c.capacity })
+		.collect::<Vec<_>>();
+	diesel::insert_into(layout_capacities).values(&rows).execute(con)?;
+
+	Ok(())
+}
+
+/// vrací všechna rozložení i s kapacitami
+///
+/// GET /layouts application/json
+#[get("/layouts")]
+pub fn list() -> ApiResult<Vec<LayoutDetail>> {
+	use crate::schema::layout_capacities::dsl::{layout_capacities, layout_id, rooms};
+	use crate::schema::layouts::dsl::*;
+
+	let con = db::get_con();
+	let all = layouts.order(id).load::<Layout>(&con)?;
+	let caps = layout_capacities.order((layout_id, rooms)).load::<(i32, i32, i32)>(&con)?;
+
+	Ok(Json(
+		all.into_iter()
+			.map(|layout| LayoutDetail {
+				capacities: caps
+					.iter()
+					.filter(|(l, _, _)| *l == layout.id)
+					.map(|&(_, r, c)| Capacity { rooms: r, capacity: c })
+					.collect(),
+				layout,
+			})
+			.collect(),
+	))
+}
+
+/// vrací jedno rozložení
+///
+/// GET /layouts/<id> application/json
+#[get("/layouts/<l_id>")]
+pub fn get(l_id: i32) -> ApiResult<LayoutDetail> {
+	Ok(Json(detail(&db::get_con(), l_id)?))
+}
+
+/// vytvoří nové rozložení
+///
+/// POST /layouts application/json
+///
+/// data: [`NewLayout`], vrací [`LayoutDetail`], název musí být unikátní (jinak 409)
+#[post("/layouts", data = "<input>")]
+pub fn create(input: Json<NewLayout>, usr: Permitted<LayoutManage>, req_id: RequestId) -> ApiResult<LayoutDetail> {
+	use crate::schema::layouts::dsl::*;
+
+	let input = input.into_inner();
+	let new_name = input.name.trim().to_string();
+
+	let con = db::get_con();
+	validate(&con, &new_name, &input.capacities)?;
+	let created = con.immediate_transaction(|| {
+		check_unique(&con, &new_name, None)?;
+
+		diesel::insert_into(layouts)
+			.values(InsertLayout {
+				name:        new_name.clone(),
+				description: input.description.clone(),
+				diagram:     input.diagram.clone().filter(|d| !d.trim().is_empty()),
+			})
+			.execute(&con)?;
+		let l_id = layouts.filter(name.eq(&new_name)).select(id).first::<i32>(&con)?;
+		set_capacities(&con, l_id, &input.capacities)?;
+
+		detail(&con, l_id)
+	})?;
+
+	let target = Target::Layout(created.layout.id);
+	audit::record(&usr.user, "layout.create", target, None, audit::snapshot(&created), &req_id);
+	Ok(Json(created))
+}
+
+/// upraví rozložení
+///
+/// PATCH /layouts/<id> application/json
+///
+/// data: [`UpdateLayout`], vrací [`LayoutDetail`]
+#[patch("/layouts/<l_id>", data = "<input>")]
+pub fn update(
+	l_id: i32,
+	input: Json<UpdateLayout>,
+	usr: Permitted<LayoutManage>,
+	req_id: RequestId,
+) -> ApiResult<LayoutDetail> {
+	use crate::schema::layouts::dsl::layouts;
+
+	let change = LayoutChanges {
+		name:        input.name.as_ref().map(|n| n.trim().to_string()),
+		description: input.description.clone(),
+		diagram:     input.diagram.as_ref().map(|d| Some(d.trim().to_string()).filter(|d| !d.is_empty())),
+	};
+
+	let con = db::get_con();
+	let (before, updated) = con.immediate_transaction(|| {
+		let before = detail(&con, l_id)?;
+		let caps = input.capacities.as_ref().unwrap_or(&before.capacities);
+		validate(&con, change.name.as_ref().unwrap_or(&before.layout.name), caps)?;
+
+		if let Some(new_name) = &change.name {
+			check_unique(&con, new_name, Some(l_id))?;
+		}
+		if !change.is_empty() {
+			diesel::update(layouts.find(l_id)).set(&change).execute(&con)?;
+		}
+		if let Some(caps) = &input.capacities {
+			set_capacities(&con, l_id, caps)?;
+		}
+
+		Ok::<_, ApiError>((before, detail(&con, l_id)?))
+	})?;
+
+	let (before, after) = (audit::snapshot(&before), audit::snapshot(&updated));
+	audit::record(&usr.user, "layout.update", Target::Layout(l_id), before, after, &req_id);
+	Ok(Json(updated))
+}
+
+/// smaže rozložení
+///
+/// DELETE /layouts/<id>
+///
+/// rozložení, které používá nějaká rezervace, smazat nejde, vrací 409 s jejich id v `events`
+#[delete("/layouts/<l_id>")]
+pub fn delete(l_id: i32, usr: Permitted<LayoutManage>, req_id: RequestId) -> ApiResult<Done> {
+	use crate::schema::booking::dsl::{booking, id, layout};
+	use crate::schema::layout_capacities::dsl::{layout_capacities, layout_id};
+	use crate::schema::layouts::dsl::layouts;
+
+	let con = db::get_con();
+	let before = con.immediate_transaction(|| {
+		let before = detail(&con, l_id)?;
+
+		let used = booking.filter(layout.eq(l_id)).select(id).order(id).load::<i32>(&con)?;
+		if !used.is_empty() {
+			Err(ApiError::new(Status::Conflict, "layout is used by some events").with("events", used))?
+		}
+
+		diesel::delete(layout_capacities.filter(layout_id.eq(l_id))).execute(&con)?;
+		diesel::delete(layouts.find(l_id)).execute(&con)?;
+
+		Ok::<_, ApiError>(before)
+	})?;
+
+	audit::record(&usr.user, "layout.delete", Target::Layout(l_id), audit::snapshot(&before), None, &req_id);
+	Ok(Json(Done { result: 0, id: None }))
+}
+
+/// vrací routy rozložení
+pub fn routes() -> Vec<Route> {
+	routes![list, get, create, update, delete]
+}
@@ -1,26 +1,78 @@
+use rocket::request::Form;
 use rocket::Route;
+use rocket::State;
 use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
 
 use crate::auth::AuthToken;
-use crate::auth::roles::{Noob, Approver, Role};
+use crate::auth::roles::Noob;
+use crate::auth::permissions::{Permissions, Permitted};
+use crate::auth::permissions::actions::{BookingApprove, BookingCreate};
 
+use crate::audit::{self, RequestId, Target};
+use crate::datetime;
 use crate::db;
-use crate::db::{NewReservation, UpdateReservation, Reservation};
+use crate::db::{NewReservation, UpdateReservation, Reservation, ReservationChanges, User};
+use crate::error::{ApiError, ApiResult};
+use crate::rgi::response::{Done, Page, Results};
 
-use diesel::prelude::*;
+use self::approval::{Decision, Reason};
+use self::backend::Backend;
+use self::query::EventQuery;
 
-/*
-** TODO proper type for response, handle RGI responses
-*/
+/// schvalování rezervací
+pub mod approval;
+/// hledání volných termínů
+pub mod availability;
+/// úložiště rezervací (Diesel nebo RGI)
+pub mod backend;
+/// kolize rezervací
+pub mod conflicts;
+/// katalog rozložení nábytku
+pub mod layouts;
+/// výpis rezervací po stránkách
+pub mod query;
+/// opakování rezervací podle RFC 5545
+pub mod recurrence;
+/// katalog místností
+pub mod rooms;
+/// opakované rezervace (série)
+pub mod series;
 
-/// vrací všechny rezervace
+/// rezervace i se jménem autora, tak jak ji vrací GET
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventDetail {
+	/// rezervace
+	#[serde(flatten)]
+	pub reservation: Reservation,
+	/// jméno autora
+	pub author_name: Option<String>,
+	/// jména zabraných místností, viz [`rooms::names`]
+	#[serde(default)]
+	pub room_names:  Vec<String>,
+}
+
+/// vrací stránku rezervací
+///
+/// GET /events?<filtr> "application/json"
+///
+/// parametry: [`EventQuery`], vrací [`Page`] s celkovým počtem a odkazy na sousední stránky
 ///
-/// GET /events "application/json"
-#[get("/events")]
-pub fn list() -> String {
-	rgi! {
-		LIST "rgi/booking/booking.py"
+/// jen pro přihlášené, filtr podle autora by jinak prozradil, kdo má rezervace
+#[get("/events?<filter..>")]
+pub fn list(filter: Form<EventQuery>, _u: AuthToken<Noob>, backend: State<Backend>) -> ApiResult<Page<Reservation>> {
+	let filter = filter.into_inner();
+
+	// backend dostane bitflagy, odkazy na stránky si nechají místnosti tak, jak přišly
+	let mut resolved = filter.clone();
+	if let Some(raw) = &filter.rooms {
+		let mask = rooms::Catalogue::load(&db::get_con())?.union(&rooms::parse(raw))?;
+		resolved.rooms = Some(mask.to_string());
 	}
+	resolved.validate()?;
+
+	let (results, total) = backend.search(&resolved)?;
+	Ok(Json(filter.page_of(results, total)))
 }
 
 /// vrátí JSON dané rezervace
@@ -30,35 +82,48 @@ pub fn list() -> String {
 /// parametry:
 /// - `id`: identifikátor dané rezervace
 #[get("/events/<id>")]
-pub fn get(id: i32, _u: AuthToken) -> Option<String> {
+pub fn get(id: i32, _u: AuthToken<Noob>, backend: State<Backend>) -> ApiResult<EventDetail> {
 	if id < 0 {
-		None?
+		Err(ApiError::not_found("no such event"))?
 	}
 
-	Some(rgi! {
-		GET "rgi/booking/booking.py"
-		arg: id
-	})
+	let mut detail = backend.get(id)?;
+	detail.room_names = rooms::names(&db::get_con(), id, detail.reservation.rooms)?;
+
+	Ok(Json(detail))
 }
 
-/// vrátí JSON dané rezervace
+/// vytvoří novou rezervaci
 ///
 /// POST /events application/json
 ///
-/// data: [`NewReservation`]
-#[post("/events", data = "<_input>")]
-pub fn post(_input: Json<NewReservation>, usr: AuthToken) -> String {
-	let name = usr.user.name.clone();
-	let user_id = usr.user.id.clone();
-	let email = usr.user.email.clone();
-
-	rgi! {
-		POST "rgi/booking/booking.py"
-		arg: user_id,
-		arg: email,
-		arg: name
-		data: (&_input.into_inner())
+/// vrací [`Done`] s id nové rezervace, při kolizi 409, při špatných časech, místnostech
+/// (viz [`rooms::resolve`]) nebo rozložení (viz [`layouts::check`]) 422
+///
+/// data: [`NewReservation`], časy viz [`crate::datetime`]
+#[post("/events", data = "<input>")]
+pub fn post(
+	input: Json<NewReservation>,
+	usr: Permitted<BookingCreate>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let mut input = input.into_inner();
+	datetime::check_span(input.begin_time, input.end_time)?;
+
+	let con = db::get_con();
+	input.rooms = rooms::resolve(&con, &input.rooms)?;
+	let mask = input.rooms.mask().unwrap_or(0);
+	layouts::check(&con, input.layout as i32, mask, input.people as i32)?;
+
+	let id = backend.create(&input, &usr.user)?;
+	rooms::refresh(&con, id, mask);
+
+	if let Some(created) = find(&backend, id) {
+		audit::record(&usr.user, "booking.create", Target::Booking(id), None, audit::snapshot(&created), &req_id);
 	}
+
+	Ok(Json(Done { result: 0, id: Some(id) }))
 }
 
 /// upraví danou rezervaci
@@ -69,106 +134,197 @@ pub fn post(_input: Json<NewReservation>, usr: AuthToken) -> String {
 /// - `id`: identifikátor dané rezervace
 ///
 /// data:[`UpdateReservation`]
-#[patch("/events/<r_id>", data = "<_input>")]
-pub fn patch(r_id: i32, _input: Json<UpdateReservation>, usr: AuthToken) -> Option<String> {
-	let name = usr.user.name.clone();
-	let user_id = usr.user.id.clone();
-	let email = usr.user.email.clone();
-
-	// TODO return error instead of None on invalid states
-	if r_id < 0 {
-		None?
+#[patch("/events/<r_id>", data = "<input>")]
+pub fn patch(
+	r_id: i32,
+	input: Json<UpdateReservation>,
+	usr: AuthToken<Noob>,
+	perms: State<Permissions>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let mut input = input.into_inner();
+	let before = find(&backend, r_id).ok_or_else(|| ApiError::not_found("no such event"))?;
+	if !perms.may_edit_booking(&usr.user, &before.author) {
+		Err(ApiError::not_found("no such event"))? // you shouldn't be able to edit others' events
+	}
+	if input.begin_time.is_some() || input.end_time.is_some() {
+		let begin = input.begin_time.unwrap_or(before.begin_time);
+		datetime::check_span(begin, input.end_time.unwrap_or(before.end_time))?;
 	}
 
-	if usr.user.role.to_lowercase() != Approver::name() {
-		use crate::schema::booking::dsl::*;
+	let con = db::get_con();
+	if let Some(r) = &input.rooms {
+		input.rooms = Some(rooms::resolve(&con, r)?);
+	}
+	let change = ReservationChanges::from(&input);
+	layouts::check_changes(&con, &before, &change)?;
 
-		let con = db::get_con();
-		let reservation = booking.filter(id.eq(r_id)).first::<Reservation>(&con).ok()?;
+	backend.update(r_id, &input, &usr.user)?;
+	if let Some(mask) = change.rooms {
+		rooms::refresh(&con, r_id, mask);
+	}
 
-		if reservation.author.trim() != usr.user.email.trim() {
-			None? // you shouldn't be able to edit others' events
-		}
+	let (before, after) = (audit::snapshot(&before), find(&backend, r_id).and_then(|r| audit::snapshot(&r)));
+	if before != after {
+		audit::record(&usr.user, "booking.update", Target::Booking(r_id), before, after, &req_id);
 	}
 
-	let id = r_id;
-	Some(rgi! {
-		PATCH "rgi/booking/booking.py"
-		arg: id,
-		arg: user_id,
-		arg: name,
-		arg: email
-		data: (&_input.into_inner())
-	})
+	Ok(Json(Done { result: 0, id: None }))
 }
 
 /// vymaže danou rezervaci
 ///
-/// DEL///
+/// DELETE /events/<id>
+///
 /// parametry:
 /// - `id`: identifikátor dané rezervace
 #[delete("/events/<r_id>")]
-pub fn delete(r_id: i32, usr: AuthToken) -> Option<String> {
-	// TODO return error instead of None on invalid states
-	if r_id < 0 {
-		None?
+pub fn delete(
+	r_id: i32,
+	usr: AuthToken<Noob>,
+	perms: State<Permissions>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let before = find(&backend, r_id).ok_or_else(|| ApiError::not_found("no such event"))?;
+	if !perms.may_edit_booking(&usr.user, &before.author) {
+		Err(ApiError::not_found("no such event"))? // you shouldn't be able to delete others' either
 	}
 
-	if usr.user.role.to_lowercase() != Approver::name() {
-		use crate::schema::booking::dsl::*;
-
-		let con = db::get_con();
-		let reservation = booking.filter(id.eq(r_id)).first::<Reservation>(&con).ok()?;
+	backend.delete(r_id, &usr.user)?;
+	rooms::refresh(&db::get_con(), r_id, 0);
 
-		/*if reservation.author.trim() != usr.user.email.trim() {
-			println!("fuck");
-			None? // you shouldn't be able to delete others' either
-		}*/
-	}
-
-	let id = r_id;
-	Some(rgi! {
-		DELETE "rgi/booking/booking.py"
-		arg: id
-	})
+	audit::record(&usr.user, "booking.delete", Target::Booking(r_id), audit::snapshot(&before), None, &req_id);
+	Ok(Json(Done { result: 0, id: None }))
 }
 
 /// filtruje podle data
 ///
 /// GET /events/filter/<rooms>/<begin_time>/end_time>
 ///
+/// totéž umí i `GET /events?rooms=&from=&to=`, viz [`query`], volné termíny hledá [`availability`]
+///
 /// parametry:
-/// - `rooms`:  bitflagy pro místnosti, viz [`Reservation`]
-/// - `begin_time`: počáteční čas
+/// - `rooms`:  bitflagy pro místnosti (viz [`Reservation`]), nebo id či jména místností oddělená čárkou
+/// - `begin_time`: počáteční čas, viz [`crate::datetime`]
 /// - `end_time`: čas konce
 #[get("/events/filter/<rooms>/<begin_time>/<end_time>")]
-pub fn date_filter(rooms: i32, begin_time: String, end_time: String, _u: AuthToken) -> String {
-	rgi! {
-		FILTER "rgi/booking/booking.py"
-		arg: rooms,
-		arg: begin_time,
-		arg: end_time
-	}
+pub fn date_filter(
+	rooms: String,
+	begin_time: String,
+	end_time: String,
+	_u: AuthToken<Noob>,
+	backend: State<Backend>,
+) -> ApiResult<Results<Reservation>> {
+	let begin_time = datetime::parse(&begin_time).map_err(ApiError::invalid)?;
+	let end_time = datetime::parse(&end_time).map_err(ApiError::invalid)?;
+
+	let rooms = self::rooms::Catalogue::load(&db::get_con())?.union(&self::rooms::parse(&rooms))?;
+
+	Ok(Json(Results { results: backend.filter(rooms, begin_time, end_time)? }))
 }
 
-/// schválí endpoint
+/// schválí rezervaci
 ///
 /// POST /events/<id>/approve
 ///
+/// vyžaduje oprávnění [`BookingApprove`], schválit jde jen rezervaci čekající na schválení,
+/// při kolizi se schválenou rezervací vrací 409
+///
 /// parametry:
 /// - `id`: id rezervace
 #[post("/events/<id>/approve")]
-pub fn approve(id: i32, _u: AuthToken) -> Option<String> {
-	if _u.user.role.to_lowercase() != Approver::name() {
-		 None?
+pub fn approve(id: i32, usr: Permitted<BookingApprove>, backend: State<Backend>, req_id: RequestId) -> ApiResult<Done> {
+	decide(&backend, id, Decision::Approve, &usr.user, &req_id)
+}
+
+/// zamítne rezervaci
+///
+/// POST /events/<id>/reject application/json
+///
+/// vyžaduje oprávnění [`BookingApprove`], data: [`Reason`], důvod je povinný
+#[post("/events/<id>/reject", data = "<input>")]
+pub fn reject(
+	id: i32,
+	input: Json<Reason>,
+	usr: Permitted<BookingApprove>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let reason = input.reason.as_ref().map(|r| r.trim()).filter(|r| !r.is_empty());
+	let reason = reason.ok_or_else(|| ApiError::invalid("a reason is required to reject an event"))?;
+
+	decide(&backend, id, Decision::Reject(reason.to_string()), &usr.user, &req_id)
+}
+
+/// zruší rezervaci
+///
+/// POST /events/<id>/cancel application/json
+///
+/// vyžaduje oprávnění [`BookingApprove`], data: nepovinně [`Reason`]
+#[post("/events/<id>/cancel", data = "<input>")]
+pub fn cancel(
+	id: i32,
+	input: Option<Json<Reason>>,
+	usr: Permitted<BookingApprove>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let reason = input.and_then(|i| i.into_inner().reason).filter(|r| !r.trim().is_empty());
+
+	decide(&backend, id, Decision::Cancel(reason), &usr.user, &req_id)
+}
+
+/// stáhne rezervaci, smí ten, kdo ji smí upravit
+///
+/// POST /events/<id>/withdraw
+#[post("/events/<r_id>/withdraw")]
+pub fn withdraw(
+	r_id: i32,
+	usr: AuthToken<Noob>,
+	perms: State<Permissions>,
+	backend: State<Backend>,
+	req_id: RequestId,
+) -> ApiResult<Done> {
+	let before = find(&backend, r_id).ok_or_else(|| ApiError::not_found("no such event"))?;
+	if !perms.may_edit_booking(&usr.user, &before.author) {
+		Err(ApiError::not_found("no such event"))?
 	}
-	Some(rgi! {
-		APPROVE "rgi/booking/booking.py"
-		arg: id
-	})
+
+	decide(&backend, r_id, Decision::Withdraw, &usr.user, &req_id)
+}
+
+/// provede rozhodnutí o rezervaci a zapíše ho do audit logu
+fn decide(backend: &Backend, id: i32, decision: Decision, caller: &User, req_id: &RequestId) -> ApiResult<Done> {
+	let before = find(backend, id).ok_or_else(|| ApiError::not_found("no such event"))?;
+
+	backend.decide(id, &decision, caller)?;
+
+	if let Some(after) = find(backend, id) {
+		let (before, after) = (audit::snapshot(&before), audit::snapshot(&after));
+		audit::record(caller, decision.action(), Target::Booking(id), before, after, req_id);
+	}
+
+	Ok(Json(Done { result: 0, id: None }))
+}
+
+/// načte rezervaci z backendu, `None` pokud neexistuje
+fn find(backend: &Backend, r_id: i32) -> Option<Reservation> {
+	if r_id < 0 {
+		return None;
+	}
+
+	backend.get(r_id).ok().map(|e| e.reservation)
 }
 
 /// vrací seznam endpointů pro nabindování do Rocketu
 pub fn routes() -> Vec<Route> {
-	routes![date_filter, list, approve, get, post, patch, delete,]
+	let mut routes = routes![date_filter, list, approve, reject, cancel, withdraw, get, post, patch, delete,];
+	routes.extend(series::routes());
+	routes.extend(layouts::routes());
+	routes.extend(rooms::routes());
+	routes.extend(availability::routes());
+
+	routes
 }
@@ -0,0 +1,325 @@
+//! výpis rezervací po stránkách
+//!
+//! `GET /events` bere nepovinné parametry [`EventQuery`]:
+//! - `page`, `limit` -> stránka od 1 a počet rezervací na ni (výchozí 50, nejvýš 500)
+//! - `sort` -> `begin_time` (výchozí), `created` (pořadí vzniku) nebo `name`, s `-` sestupně
+//! - `status` -> stavy oddělené čárkou (`pending,approved`), viz [`super::approval`]
+//! - `author`, `layout` -> přesná shoda
+//! - `rooms` -> bitflagy jako u kolizí, nebo id či jména místností oddělená čárkou (viz
+//!   [`super::rooms::parse`]), celé auditorium patří k oběma polovinám
+//! - `people_min`, `people_max` -> rozsah počtu lidí včetně hranic
+//! - `from`, `to` -> rezervace, které do intervalu `[from, to)` zasahují, časy jako u rezervací
+//!   (RFC 3339 nebo místní čas auditoria, viz [`crate::datetime`])
+//!
+//! ```text
+//! GET /rgi/events?status=approved&rooms=1&from=2019-12-01T00:00&sort=-begin_time&limit=20
+//! ```
+//!
+//! odpověď je [`Page`] s `total` a odkazy `next`/`prev`, které zachovávají filtr.
+//! číselné parametry, které nejdou přečíst, a stránka za [`MAX_PAGE`] jsou 422.
+//! [`DieselBackend`](super::backend::DieselBackend) filtruje v SQL, ostatní backendy
+//! přes [`in_memory`] nad celým seznamem.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use super::approval::STATES;
+use super::conflicts::rooms_intersect;
+use super::rooms::Catalogue;
+use crate::datetime;
+use crate::db::Reservation;
+use crate::error::ApiError;
+use crate::rgi::response::Page;
+use crate::schema::booking;
+
+/// výchozí počet rezervací na stránku
+pub const DEFAULT_LIMIT: i64 = 50;
+/// nejvyšší počet rezervací na stránku
+pub const MAX_LIMIT: i64 = 500;
+/// nejvyšší číslo stránky, aby se posun vešel do `i64`
+pub const MAX_PAGE: i64 = i64::MAX / MAX_LIMIT;
+
+/// podle čeho se řadí
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+	/// začátek rezervace
+	BeginTime,
+	/// pořadí vzniku (id)
+	Created,
+	/// název
+	Name,
+}
+
+/// parametry výpisu rezervací, všechny jsou nepovinné
+#[derive(FromForm, Debug, Clone, Default, PartialEq)]
+pub struct EventQuery {
+	/// stránka od 1
+	pub page:       Option<String>,
+	/// rezervací na stránku
+	pub limit:      Option<String>,
+	/// `begin_time`, `created` nebo `name`, s `-` sestupně
+	pub sort:       Option<String>,
+	/// stavy oddělené čárkou
+	pub status:     Option<String>,
+	/// email autora
+	pub author:     Option<String>,
+	/// místnosti (bitflagy, id nebo jména)
+	pub rooms:      Option<String>,
+	/// rozložení nábytku
+	pub layout:     Option<String>,
+	/// nejméně lidí
+	pub people_min: Option<String>,
+	/// nejvíc lidí
+	pub people_max: Option<String>,
+	/// rezervace končící po tomto čase
+	pub from:       Option<String>,
+	/// rezervace začínající před tímto časem
+	pub to:         Option<String>,
+}
+
+impl EventQuery {
+	/// zkontroluje čísla, řazení, stavy a časy, jinak 422
+	pub fn validate(&self) -> Result<(), ApiError> {
+		if number::<i64>("page", &self.page)?.unwrap_or(1) > MAX_PAGE {
+			Err(ApiError::invalid(format!("page must be at most {}", MAX_PAGE)))?
+		}
+		number::<i64>("limit", &self.limit)?;
+		self.rooms()?;
+		self.layout()?;
+		self.people()?;
+		self.sort_by()?;
+		self.statuses()?;
+		self.interval()?;
+
+		Ok(())
+	}
+
+	/// číslo stránky
+	pub fn page(&self) -> i64 {
+		number("page", &self.page).ok().and_then(|p| p).unwrap_or(1).max(1).min(MAX_PAGE)
+	}
+
+	/// rezervací na stránku
+	pub fn limit(&self) -> i64 {
+		number("limit", &self.limit).ok().and_then(|l| l).unwrap_or(DEFAULT_LIMIT).max(1).min(MAX_LIMIT)
+	}
+
+	/// místnosti jako bitflagy, id a jména na ně převádí už endpoint
+	pub fn rooms(&self) -> Result<Option<i32>, ApiError> {
+		number("rooms", &self.rooms)
+	}
+
+	/// rozložení nábytku
+	pub fn layout(&self) -> Result<Option<i32>, ApiError> {
+		number("layout", &self.layout)
+	}
+
+	/// nejméně a nejvíc lidí
+	pub fn people(&self) -> Result<(Option<i32>, Option<i32>), ApiError> {
+		Ok((number("people_min", &self.people_min)?, number("people_max", &self.people_max)?))
+	}
+
+	/// kolik rezervací přeskočit
+	pub fn offset(&self) -> i64 {
+		(self.page() - 1) * self.limit()
+	}
+
+	/// klíč řazení a jestli sestupně
+	pub fn sort_by(&self) -> Result<(SortKey, bool), ApiError> {
+		let raw = self.sort.as_ref().map(|s| s.trim()).unwrap_or("begin_time");
+		let (key, desc) = if raw.starts_with('-') { (&raw[1..], true) } else { (raw, false) };
+
+		match key {
+			"begin_time" => Ok((SortKey::BeginTime, desc)),
+			"created" => Ok((SortKey::Created, desc)),
+			"name" => Ok((SortKey::Name, desc)),
+			_ => Err(ApiError::invalid(format!("can't sort by `{}`, use begin_time, created or name", key))),
+		}
+	}
+
+	/// požadované stavy, `None` = všechny
+	pub fn statuses(&self) -> Result<Option<Vec<String>>, ApiError> {
+		let raw = match &self.status {
+			Some(raw) => raw,
+			None => return Ok(None),
+		};
+
+		let statuses = raw.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+		if let Some(unknown) = statuses.iter().find(|s| !STATES.contains(&s.as_str())) {
+			Err(ApiError::invalid(format!("unknown status `{}`", unknown)))?
+		}
+
+		Ok(Some(statuses))
+	}
+
+	/// `from` a `to` v UTC
+	pub fn interval(&self) -> Result<(Option<NaiveDateTime>, Option<NaiveDateTime>), ApiError> {
+		let parse = |t: &Option<String>| t.as_ref().map(|t| datetime::parse(t)).transpose().map_err(ApiError::invalid);
+
+		Ok((parse(&self.from)?, parse(&self.to)?))
+	}
+
+	/// odpovídá rezervace filtru?
+	pub fn matches(&self, r: &Reservation) -> bool {
+		let statuses = self.statuses().ok().and_then(|s| s);
+		let (rooms, layout) = (self.rooms().ok().and_then(|r| r), self.layout().ok().and_then(|l| l));
+		let (people_min, people_max) = self.people().unwrap_or((None, None));
+		let (from, to) = self.interval().unwrap_or((None, None));
+
+		statuses.map(|s| s.contains(&r.status)).unwrap_or(true)
+			&& self.author.as_ref().map(|a| *a == r.author).unwrap_or(true)
+			&& rooms.map(|rooms| rooms_intersect(rooms, r.rooms)).unwrap_or(true)
+			&& layout.map(|l| l == r.layout).unwrap_or(true)
+			&& people_min.map(|p| r.people >= p).unwrap_or(true)
+			&& people_max.map(|p| r.people <= p).unwrap_or(true)
+			&& from.map(|f| r.end_time > f).unwrap_or(true)
+			&& to.map(|t| r.begin_time < t).unwrap_or(true)
+	}
+
+	/// porovnání dvou rezervací podle `sort`
+	pub fn compare(&self, a: &Reservation, b: &Reservation) -> Ordering {
+		let (key, desc) = self.sort_by().unwrap_or((SortKey::BeginTime, false));
+		let ordering = match key {
+			SortKey::BeginTime => a.begin_time.cmp(&b.begin_time),
+			SortKey::Created => Ordering::Equal,
+			SortKey::Name => a.name.cmp(&b.name),
+		}
+		.then(a.id.cmp(&b.id));
+
+		if desc {
+			ordering.reverse()
+		} else {
+			ordering
+		}
+	}
+
+	/// obalí výsledky do stránky s odkazy
+	pub fn page_of<T>(&self, results: Vec<T>, total: i64) -> Page<T> {
+		let (page, limit) = (self.page(), self.limit());
+
+		Page {
+			results,
+			total,
+			page,
+			limit,
+			next: if page * limit < total { Some(self.link(page + 1)) } else { None },
+			prev: if page > 1 { Some(self.link(page - 1)) } else { None },
+		}
+	}
+
+	/// odkaz na danou stránku se stejným filtrem
+	pub fn link(&self, page: i64) -> String {
+		let params = vec![
+			("sort", self.sort.clone()),
+			("status", self.status.clone()),
+			("author", self.author.clone()),
+			("rooms", self.rooms.clone()),
+			("layout", self.layout.clone()),
+			("people_min", self.people_min.clone()),
+			("people_max", self.people_max.clone()),
+			("from", self.from.clone()),
+			("to", self.to.clone()),
+			("page", Some(page.to_string())),
+			("limit", Some(self.limit().to_string())),
+		];
+
+		let query = params
+			.into_iter()
+			.filter_map(|(key, value)| value.map(|v| format!("{}={}", key, encode(&v))))
+			.collect::<Vec<_>>();
+
+		format!("/rgi/events?{}", query.join("&"))
+	}
+}
+
+/// číselný parametr, hodnota, která nejde přečíst, je 422
+pub(crate) fn number<T: FromStr>(name: &str, raw: &Option<String>) -> Result<Option<T>, ApiError> {
+	let invalid = || ApiError::invalid(format!("`{}` must be a number", name));
+	raw.as_ref().map(|v| v.trim().parse::<T>().map_err(|_| invalid())).transpose()
+}
+
+/// zakóduje hodnotu do query stringu, nechá jen znaky, které nic neznamenají
+fn encode(value: &str) -> String {
+	value
+		.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b',' | b':' => (b as char).to_string(),
+			_ => format!("%{:02X}", b),
+		})
+		.collect()
+}
+
+/// stránka z už načtených rezervací
+pub fn in_memory(events: Vec<Reservation>, query: &EventQuery) -> (Vec<Reservation>, i64) {
+	let mut found = events.into_iter().filter(|r| query.matches(r)).collect::<Vec<_>>();
+	found.sort_by(|a, b| query.compare(a, b));
+
+	let total = found.len() as i64;
+	let page = found.into_iter().skip(query.offset() as usize).take(query.limit() as usize).collect();
+
+	(page, total)
+}
+
+/// filtr jako SQL dotaz
+fn filtered(con: &SqliteConnection, query: &EventQuery) -> Result<booking::BoxedQuery<'static, Sqlite>, ApiError> {
+	use crate::schema::booking::dsl::*;
+
+	let mut sql = booking.into_boxed();
+
+	if let Some(s) = query.statuses()? {
+		sql = sql.filter(status.eq_any(s));
+	}
+	if let Some(a) = &query.author {
+		sql = sql.filter(author.eq(a.clone()));
+	}
+	if let Some(r) = query.rooms()? {
+		// bitový and Diesel neumí, rezervace ale můžou mít jen bitflagy místností z katalogu
+		let catalogue = Catalogue::load(con)?;
+		let intersecting =
+			catalogue.rooms.iter().map(|room| room.mask).filter(|other| rooms_intersect(r, *other)).collect::<Vec<_>>();
+		sql = sql.filter(rooms.eq_any(intersecting));
+	}
+	if let Some(l) = query.layout()? {
+		sql = sql.filter(layout.eq(l));
+	}
+	let (people_min, people_max) = query.people()?;
+	if let Some(p) = people_min {
+		sql = sql.filter(people.ge(p));
+	}
+	if let Some(p) = people_max {
+		sql = sql.filter(people.le(p));
+	}
+	let (from, to) = query.interval()?;
+	if let Some(f) = from {
+		sql = sql.filter(end_time.gt(f));
+	}
+	if let Some(t) = to {
+		sql = sql.filter(begin_time.lt(t));
+	}
+
+	Ok(sql)
+}
+
+/// stránka přímo z databáze, vrací rezervace a jejich celkový počet
+pub fn load(con: &SqliteConnection, query: &EventQuery) -> Result<(Vec<Reservation>, i64), ApiError> {
+	use crate::schema::booking::dsl::*;
+
+	let total = filtered(con, query)?.count().get_result::<i64>(con)?;
+
+	let sql = filtered(con, query)?;
+	let sql = match query.sort_by()? {
+		(SortKey::BeginTime, false) => sql.order((begin_time.asc(), id.asc())),
+		(SortKey::BeginTime, true) => sql.order((begin_time.desc(), id.desc())),
+		(SortKey::Created, false) => sql.order(id.asc()),
+		(SortKey::Created, true) => sql.order(id.desc()),
+		(SortKey::Name, false) => sql.order((name.asc(), id.asc())),
+		(SortKey::Name, true) => sql.order((name.desc(), id.desc())),
+	};
+	let page = sql.limit(query.limit()).offset(query.offset()).load::<Reservation>(con)?;
+
+	Ok((page, total))
+}
@@ -0,0 +1,301 @@
+//! opakování rezervací podle RFC 5545
+//!
+//! podporovaná část `RRULE`:
+//! - `FREQ` -> `DAILY`, `WEEKLY` nebo `MONTHLY`
+//! - `INTERVAL` -> každý kolikátý den, týden nebo měsíc (výchozí 1)
+//! - `BYDAY` -> dny v týdnu (`MO,WE`), jen s `FREQ=WEEKLY`, bez něj den prvního výskytu
+//! - `UNTIL` -> nejpozdější začátek výskytu v místním čase (`20200131`, `20200131T120000`), s `Z` v UTC
+//! - `COUNT` -> počet výskytů
+//!
+//! ```no_run
+//! // každé úterý a čtvrtek, deset výskytů
+//! let rule: Rule = "FREQ=WEEKLY;BYDAY=TU,TH;COUNT=10".parse()?;
+//! let occurrences = expand(&rule, datetime::parse("2020-02-04T14:00")?, datetime::parse("2020-02-04T15:30")?, &[])?;
+//! ```
+//!
+//! první výskyt je vždy začátek série (jako `DTSTART`), `COUNT` ho započítává.
+//! Výskyty z `exdates` se vynechají až po započítání do `COUNT`, stejně jako `EXDATE`.
+//! `UNTIL` nebo `COUNT` je povinné a výskytů smí být nejvýš [`MAX_OCCURRENCES`].
+//! Časy jsou v UTC jako u rezervací, opakuje se ale v místním čase auditoria (viz [`crate::datetime`]),
+//! takže seminář v 10:00 zůstane v 10:00 i po změně letního času. Výskyt, jehož začátek
+//! kvůli letnímu času neexistuje, se posune o hodinu později, stejně jako v RFC 5545.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use serde::Serialize;
+
+use crate::datetime;
+
+/// nejvyšší počet výskytů jedné série
+pub const MAX_OCCURRENCES: usize = 500;
+
+/// formát `UNTIL` při výpisu pravidla
+const UNTIL_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// jak často se rezervace opakuje
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freq {
+	/// denně
+	Daily,
+	/// týdně
+	Weekly,
+	/// měsíčně, ve stejný den v měsíci (měsíce bez toho dne se přeskočí)
+	Monthly,
+}
+
+/// pravidlo opakování
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+	/// frekvence
+	pub freq:     Freq,
+	/// každý kolikátý den/týden/měsíc
+	pub interval: u32,
+	/// dny v týdnu pro `FREQ=WEEKLY`
+	pub by_day:   Vec<Weekday>,
+	/// nejpozdější začátek výskytu (místní čas)
+	pub until:    Option<NaiveDateTime>,
+	/// počet výskytů
+	pub count:    Option<u32>,
+}
+
+/// jeden výskyt série
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Occurrence {
+	/// začátek (UTC)
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub begin_time: NaiveDateTime,
+	/// konec (UTC)
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub end_time:   NaiveDateTime,
+}
+
+impl FromStr for Rule {
+	type Err = String;
+
+	fn from_str(src: &str) -> Result<Self, Self::Err> {
+		let src = src.trim();
+		let src = if src.to_uppercase().starts_with("RRULE:") { &src[6..] } else { src };
+
+		let (mut freq, mut interval, mut by_day, mut until, mut count) = (None, 1, vec![], None, None);
+		for part in src.split(';').filter(|p| !p.is_empty()) {
+			let mut kv = part.splitn(2, '=');
+			let (key, value) = match (kv.next(), kv.next()) {
+				(Some(key), Some(value)) => (key.trim().to_uppercase(), value.trim().to_uppercase()),
+				_ => Err(format!("invalid RRULE part `{}`", part))?,
+			};
+
+			match key.as_str() {
+				"FREQ" => {
+					freq = Some(match value.as_str() {
+						"DAILY" => Freq::Daily,
+						"WEEKLY" => Freq::Weekly,
+						"MONTHLY" => Freq::Monthly,
+						_ => Err(format!("unsupported FREQ `{}`", value))?,
+					})
+				}
+				"INTERVAL" => {
+					interval = value.parse::<u32>().ok().filter(|i| *i > 0).ok_or("INTERVAL must be a positive number")?
+				}
+				"COUNT" => count = Some(value.parse::<u32>().ok().filter(|c| *c > 0).ok_or("COUNT must be a positive number")?),
+				"UNTIL" => until = Some(parse_until(&value).ok_or_else(|| format!("invalid UNTIL `{}`", value))?),
+				"BYDAY" => {
+					by_day = value
+						.split(',')
+						.map(|d| parse_weekday(d.trim()).ok_or_else(|| format!("unsupported BYDAY `{}`", d)))
+						.collect::<Result<Vec<_>, _>>()?
+				}
+				_ => Err(format!("unsupported RRULE part `{}`", key))?,
+			}
+		}
+
+		let freq = freq.ok_or("RRULE needs FREQ")?;
+		match (until, count) {
+			(Some(_), Some(_)) => Err("RRULE can't have both UNTIL and COUNT")?,
+			(None, None) => Err("RRULE needs UNTIL or COUNT")?,
+			_ => (),
+		}
+		if !by_day.is_empty() && freq != Freq::Weekly {
+			Err("BYDAY is only supported with FREQ=WEEKLY")?
+		}
+		by_day.sort_by_key(|d| d.num_days_from_monday());
+		by_day.dedup();
+
+		Ok(Rule { freq, interval, by_day, until, count })
+	}
+}
+
+impl fmt::Display for Rule {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let freq = match self.freq {
+			Freq::Daily => "DAILY",
+			Freq::Weekly => "WEEKLY",
+			Freq::Monthly => "MONTHLY",
+		};
+		write!(f, "FREQ={}", freq)?;
+
+		if self.interval != 1 {
+			write!(f, ";INTERVAL={}", self.interval)?;
+		}
+		if !self.by_day.is_empty() {
+			let days = self.by_day.iter().map(|d| weekday_code(*d)).collect::<Vec<_>>();
+			write!(f, ";BYDAY={}", days.join(","))?;
+		}
+		if let Some(until) = self.until {
+			write!(f, ";UNTIL={}", until.format(UNTIL_FORMAT))?;
+		}
+		if let Some(count) = self.count {
+			write!(f, ";COUNT={}", count)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Rule {
+	/// začátky výskytů série, která začíná v `start` (vše v místním čase)
+	pub fn occurrences(&self, start: NaiveDateTime) -> Result<Vec<NaiveDateTime>, String> {
+		let limit = self.count.map(|c| c as usize).unwrap_or(MAX_OCCURRENCES + 1);
+		let mut found = vec![start];
+
+		for step in 1.. {
+			let candidates = match self.freq {
+				Freq::Daily => vec![start + Duration::days(step * self.interval as i64)],
+				Freq::Weekly => self.week(start, step),
+				Freq::Monthly => add_months(start.date(), step * self.interval as i64)
+					.map(|date| vec![date.and_time(start.time())])
+					.unwrap_or_default(),
+			};
+
+			for candidate in candidates.into_iter().filter(|c| *c > start) {
+				if found.len() >= limit || self.until.map(|u| candidate > u).unwrap_or(false) {
+					return finish(found);
+				}
+				found.push(candidate);
+			}
+
+			// 31. v měsíci může chybět, ale ne déle než pár měsíců za sebou
+			if found.len() > MAX_OCCURRENCES || step > 10 * MAX_OCCURRENCES as i64 {
+				break;
+			}
+		}
+
+		finish(found)
+	}
+
+	/// výskyty v `step`-tém týdnu série (počítáno od týdne prvního výskytu)
+	fn week(&self, start: NaiveDateTime, step: i64) -> Vec<NaiveDateTime> {
+		let monday = start.date() - Duration::days(start.weekday().num_days_from_monday() as i64);
+		let week = monday + Duration::weeks(step * self.interval as i64);
+		let days = if self.by_day.is_empty() { vec![start.weekday()] } else { self.by_day.clone() };
+
+		// první týden série může mít i výskyty po prvním výskytu
+		let mut weeks = vec![week];
+		if step == 1 {
+			weeks.insert(0, monday);
+		}
+
+		weeks
+			.into_iter()
+			.flat_map(|w| days.iter().map(move |d| w + Duration::days(d.num_days_from_monday() as i64)))
+			.map(|date| date.and_time(start.time()))
+			.collect()
+	}
+}
+
+/// zkontroluje počet výskytů
+fn finish(found: Vec<NaiveDateTime>) -> Result<Vec<NaiveDateTime>, String> {
+	if found.len() > MAX_OCCURRENCES {
+		Err(format!("series can't have more than {} occurrences", MAX_OCCURRENCES))
+	} else {
+		Ok(found)
+	}
+}
+
+/// rozvine sérii na výskyty, `begin_time` a `end_time` jsou první výskyt v UTC
+///
+/// `exdates` jsou začátky vynechaných výskytů ve stejném tvaru, jaký posílá klient
+pub fn expand(
+	rule: &Rule,
+	begin_time: NaiveDateTime,
+	end_time: NaiveDateTime,
+	exdates: &[String],
+) -> Result<Vec<Occurrence>, String> {
+	if end_time <= begin_time {
+		Err("begin_time must be before end_time")?
+	}
+
+	let skipped = exdates.iter().map(|e| datetime::parse(e)).collect::<Result<Vec<_>, _>>()?;
+	let length = end_time - begin_time;
+
+	let occurrences = rule
+		.occurrences(datetime::to_local(begin_time))?
+		.into_iter()
+		.map(to_utc)
+		.filter(|start| !skipped.contains(start))
+		.map(|start| Occurrence { begin_time: start, end_time: start + length })
+		.collect::<Vec<_>>();
+
+	if occurrences.is_empty() {
+		Err("series has no occurrences")?
+	}
+
+	Ok(occurrences)
+}
+
+/// posune čas v UTC o `delta` v místním čase, posun o týden tak nezmění hodinu ani přes změnu letního času
+pub fn shift(utc: NaiveDateTime, delta: Duration) -> NaiveDateTime {
+	to_utc(datetime::to_local(utc) + delta)
+}
+
+/// místní čas výskytu -> UTC, neexistující čas se posune o hodinu
+fn to_utc(local: NaiveDateTime) -> NaiveDateTime {
+	datetime::from_local(local)
+		.or_else(|| datetime::from_local(local + Duration::hours(1)))
+		.unwrap_or(local - Duration::hours(1))
+}
+
+/// `UNTIL` ve tvaru `YYYYMMDD` (celý den) nebo `YYYYMMDDTHHMMSS[Z]`, vrací místní čas
+fn parse_until(src: &str) -> Option<NaiveDateTime> {
+	if src.ends_with('Z') {
+		return NaiveDateTime::parse_from_str(src.trim_end_matches('Z'), UNTIL_FORMAT).ok().map(datetime::to_local);
+	}
+
+	NaiveDateTime::parse_from_str(src, UNTIL_FORMAT)
+		.ok()
+		.or_else(|| NaiveDate::parse_from_str(src, "%Y%m%d").ok().map(|d| d.and_hms(23, 59, 59)))
+}
+
+/// den v týdnu podle RFC 5545 (`MO`, `TU`...)
+fn parse_weekday(src: &str) -> Option<Weekday> {
+	match src {
+		"MO" => Some(Weekday::Mon),
+		"TU" => Some(Weekday::Tue),
+		"WE" => Some(Weekday::Wed),
+		"TH" => Some(Weekday::Thu),
+		"FR" => Some(Weekday::Fri),
+		"SA" => Some(Weekday::Sat),
+		"SU" => Some(Weekday::Sun),
+		_ => None,
+	}
+}
+
+/// kód dne podle RFC 5545
+fn weekday_code(day: Weekday) -> &'static str {
+	match day {
+		Weekday::Mon => "MO",
+		Weekday::Tue => "TU",
+		Weekday::Wed => "WE",
+		Weekday::Thu => "TH",
+		Weekday::Fri => "FR",
+		Weekday::Sat => "SA",
+		Weekday::Sun => "SU",
+	}
+}
+
+/// datum o `months` měsíců později, `None` pokud ten den v měsíci není
+fn add_months(date: NaiveDate, months: i64) -> Option<NaiveDate> {
+	let total = date.year() as i64 * 12 + date.month0() as i64 + months;
+	NaiveDate::from_ymd_opt((total / 12) as i32, (total % 12) as u32 + 1, date.day())
+}
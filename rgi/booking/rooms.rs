@@ -0,0 +1,247 @@
+//! katalog místností
+//!
+//! místnosti jsou v tabulce `rooms` se jménem a kapacitou. Místnost, která není složená
+//! z jiných, má vlastní bit, `rooms` rezervace jsou bitflagy zabraných místností
+//! (podle nich se hledají kolize, viz [`super::conflicts`]). Složená místnost (celé
+//! auditorium) se v `room_parts` skládá z částí (sever a jih) a zabírá bity všech částí.
+//!
+//! rezervovat jde jednu místnost z katalogu, nebo několik místností, které dohromady dají
+//! některou místnost z katalogu (`[sever, jih]` je celé auditorium), viz [`Catalogue::resolve`].
+//! Endpointy místnosti z požadavku (bitflagy, id nebo jména, viz [`Rooms`]) převedou na
+//! bitflagy ještě před zavoláním backendu, takže `booking.py` i starší klienti dál pracují
+//! s bitflagy.
+//!
+//! `reservation_rooms` (zabrané místnosti po řádcích, pro dotazy v SQL) je jen odvozená kopie
+//! bitflagů `rooms`. Rezervaci ukládá backend a `booking.py` o tabulce neví, endpointy ji proto
+//! obnoví až po uložení rezervace (viz [`refresh`]) a chyba přitom request neshodí. Zastaralé
+//! řádky opraví [`names`], jména místností bere vždy z bitflagů.
+//!
+//! - `GET /rooms` -> katalog místností
+//! - `GET /rooms/<id>` -> jedna místnost
+
+use std::collections::BTreeMap;
+
+use diesel::prelude::*;
+use rocket::Route;
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+use crate::db;
+use crate::db::{InsertReservationRoom, Room, RoomRef, Rooms};
+use crate::error::{ApiError, ApiResult};
+
+/// nejvíc úrovní skládání místností
+const MAX_DEPTH: usize = 8;
+
+/// místnost i s bity, které zabírá
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RoomDetail {
+	/// místnost
+	#[serde(flatten)]
+	pub room:  Room,
+	/// bitflagy, které místnost zabírá (u složené místnosti bity všech částí)
+	pub mask:  i32,
+	/// id částí složené místnosti
+	pub parts: Vec<i32>,
+}
+
+/// katalog místností
+#[derive(Debug, Clone)]
+pub struct Catalogue {
+	/// místnosti podle id
+	pub rooms: Vec<RoomDetail>,
+}
+
+impl Catalogue {
+	/// katalog z místností a dvojic (složená místnost, část)
+	pub fn new(rooms: Vec<Room>, parts: &[(i32, i32)]) -> Self {
+		let mut by_id = BTreeMap::new();
+		for (room, part) in parts {
+			by_id.entry(*room).or_insert_with(Vec::new).push(*part);
+		}
+
+		let bits = rooms.iter().map(|r| (r.id, r.bit.unwrap_or(0))).collect::<BTreeMap<_, _>>();
+		let rooms = rooms
+			.into_iter()
+			.map(|room| {
+				let parts = by_id.get(&room.id).cloned().unwrap_or_default();
+				let mask = mask_of(room.id, &bits, &by_id, 0);
+				RoomDetail { room, mask, parts }
+			})
+			.collect();
+
+		Catalogue { rooms }
+	}
+
+	/// načte katalog z databáze
+	pub fn load(con: &SqliteConnection) -> QueryResult<Self> {
+		use crate::schema::room_parts::dsl::{part_id, room_id, room_parts};
+		use crate::schema::rooms::dsl::{id, rooms};
+
+		let all = rooms.order(id).load::<Room>(con)?;
+		let parts = room_parts.select((room_id, part_id)).order((room_id, part_id)).load::<(i32, i32)>(con)?;
+
+		Ok(Catalogue::new(all, &parts))
+	}
+
+	/// bitflagy všech místností
+	pub fn all(&self) -> i32 {
+		self.rooms.iter().fold(0, |all, r| all | r.mask)
+	}
+
+	/// najde místnost podle id nebo jména (na velikosti písmen nezáleží)
+	pub fn find(&self, room: &RoomRef) -> Option<&RoomDetail> {
+		match room {
+			RoomRef::Id(r_id) => self.rooms.iter().find(|r| r.room.id == *r_id),
+			RoomRef::Name(r_name) => {
+				let r_name = r_name.trim().to_lowercase();
+				self.rooms.iter().find(|r| r.room.name.to_lowercase() == r_name)
+			}
+		}
+	}
+
+	/// místnost, která zabírá přesně dané bitflagy
+	pub fn by_mask(&self, mask: i32) -> Option<&RoomDetail> {
+		self.rooms.iter().find(|r| r.mask == mask)
+	}
+
+	/// bitflagy všech místností z požadavku, neznámá místnost je 422
+	///
+	/// místnosti nemusí jít zarezervovat spolu, hodí se pro filtry
+	pub fn union(&self, rooms: &Rooms) -> Result<i32, ApiError> {
+		let refs = match rooms {
+			Rooms::Mask(mask) if *mask <= 0 || mask & !self.all() != 0 => {
+				Err(ApiError::invalid(format!("unknown rooms {}", mask)).with("rooms", self.all()))?
+			}
+			Rooms::Mask(mask) => return Ok(*mask),
+			Rooms::Name(r_name) => vec![RoomRef::Name(r_name.clone())],
+			Rooms::List(refs) => refs.clone(),
+		};
+		if refs.is_empty() {
+			Err(ApiError::invalid("at least one room is required"))?
+		}
+
+		refs.iter().try_fold(0, |mask, r| match self.find(r) {
+			Some(found) => Ok(mask | found.mask),
+			None => Err(ApiError::invalid(format!("unknown room {}", describe(r)))),
+		})
+	}
+
+	/// místnost z katalogu, kterou místnosti z požadavku dohromady tvoří, jinak 422
+	pub fn resolve(&self, rooms: &Rooms) -> Result<&RoomDetail, ApiError> {
+		let mask = self.union(rooms)?;
+
+		self.by_mask(mask).ok_or_else(|| {
+			let bookable = self.rooms.iter().map(|r| r.room.name.clone()).collect::<Vec<_>>();
+			ApiError::invalid("these rooms can't be booked together").with("bookable", bookable)
+		})
+	}
+
+	/// místnosti s vlastním bitem, které bitflagy zabírají
+	pub fn occupied(&self, mask: i32) -> Vec<&RoomDetail> {
+		self.rooms.iter().filter(|r| r.room.bit.map(|bit| mask & bit != 0).unwrap_or(false)).collect()
+	}
+}
+
+/// bitflagy místnosti, složená místnost zabírá bity všech částí
+fn mask_of(r_id: i32, bits: &BTreeMap<i32, i32>, parts: &BTreeMap<i32, Vec<i32>>, depth: usize) -> i32 {
+	let own = bits.get(&r_id).cloned().unwrap_or(0);
+	if depth > MAX_DEPTH {
+		return own;
+	}
+
+	let nested = parts.get(&r_id).map(Vec::as_slice).unwrap_or(&[]);
+	nested.iter().fold(own, |mask, part| mask | mask_of(*part, bits, parts, depth + 1))
+}
+
+/// odkaz na místnost pro chybovou hlášku
+fn describe(room: &RoomRef) -> String {
+	match room {
+		RoomRef::Id(r_id) => r_id.to_string(),
+		RoomRef::Name(r_name) => format!("`{}`", r_name),
+	}
+}
+
+/// místnosti z parametru URL: číslo jsou bitflagy, jinak id nebo jména oddělená čárkou
+pub fn parse(src: &str) -> Rooms {
+	match src.trim().parse::<i32>() {
+		Ok(mask) => Rooms::Mask(mask),
+		Err(_) => Rooms::List(
+			src.split(',')
+				.map(|r| r.trim().parse::<i32>().map(RoomRef::Id).unwrap_or_else(|_| RoomRef::Name(r.to_string())))
+				.collect(),
+		),
+	}
+}
+
+/// převede místnosti z požadavku na bitflagy místnosti z katalogu, viz [`Catalogue::resolve`]
+pub fn resolve(con: &SqliteConnection, rooms: &Rooms) -> Result<Rooms, ApiError> {
+	Ok(Rooms::Mask(Catalogue::load(con)?.resolve(rooms)?.mask))
+}
+
+/// zapíše do `reservation_rooms`, které místnosti rezervace zabírá, s `0` je smaže
+pub fn assign(con: &SqliteConnection, r_id: i32, mask: i32) -> QueryResult<()> {
+	use crate::schema::reservation_rooms::dsl::*;
+
+	let rows = Catalogue::load(con)?
+		.occupied(mask)
+		.into_iter()
+		.map(|r| InsertReservationRoom { reservation_id: r_id, room_id: r.room.id })
+		.collect::<Vec<_>>();
+
+	diesel::delete(reservation_rooms.filter(reservation_id.eq(r_id))).execute(con)?;
+	diesel::insert_into(reservation_rooms).values(&rows).execute(con)?;
+
+	Ok(())
+}
+
+/// jako [`assign`] v transakci, chyba se jen zaloguje
+///
+/// pro endpointy, kde už backend rezervaci uložil, `reservation_rooms` jde kdykoli odvodit znovu
+pub fn refresh(con: &SqliteConnection, r_id: i32, mask: i32) {
+	if let Err(e) = con.transaction(|| assign(con, r_id, mask)) {
+		eprintln!("can't update rooms of reservation {}: {}", r_id, e);
+	}
+}
+
+/// jména místností, které rezervace zabírá, podle bitflagů
+///
+/// když `reservation_rooms` s bitflagy nesouhlasí (rezervace uložená přes `booking.py`,
+/// selhaný [`refresh`]), přepíše se
+pub fn names(con: &SqliteConnection, r_id: i32, mask: i32) -> QueryResult<Vec<String>> {
+	use crate::schema::reservation_rooms::dsl::{reservation_id, reservation_rooms, room_id};
+
+	let catalogue = Catalogue::load(con)?;
+	let occupied = catalogue.occupied(mask);
+
+	let assigned = reservation_rooms.filter(reservation_id.eq(r_id)).select(room_id).order(room_id).load::<i32>(con)?;
+	if assigned != occupied.iter().map(|r| r.room.id).collect::<Vec<_>>() {
+		refresh(con, r_id, mask);
+	}
+
+	Ok(occupied.into_iter().map(|r| r.room.name.clone()).collect())
+}
+
+/// vrací katalog místností
+///
+/// GET /rooms application/json
+#[get("/rooms")]
+pub fn list() -> ApiResult<Vec<RoomDetail>> {
+	Ok(Json(Catalogue::load(&db::get_con())?.rooms))
+}
+
+/// vrací jednu místnost
+///
+/// GET /rooms/<id> application/json
+#[get("/rooms/<r_id>")]
+pub fn get(r_id: i32) -> ApiResult<RoomDetail> {
+	let catalogue = Catalogue::load(&db::get_con())?;
+	let found = catalogue.find(&RoomRef::Id(r_id)).cloned();
+
+	Ok(Json(found.ok_or_else(|| ApiError::not_found("no such room"))?))
+}
+
+/// vrací routy místností
+pub fn routes() -> Vec<Route> {
+	routes![list, get]
+}
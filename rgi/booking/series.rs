@@ -0,0 +1,397 @@
+//! opakované rezervace (série)
+//!
+//! série je pravidlo opakování (viz [`super::recurrence`]) a k němu řádky v `booking`
+//! propojené přes `series_id`. Výskyty se rozvinou hned při vytvoření, každý se pak
+//! schvaluje, upravuje a maže i samostatně jako běžná rezervace přes `/events/<id>`.
+//! Pravidlo v `booking_series` popisuje, jak série vznikla, skutečné výskyty jsou řádky
+//! v `booking`. Série se ukládají přes Diesel do stejné databáze bez ohledu na
+//! `booking_backend`, `booking.py` o nich neví.
+//!
+//! - `POST /events/series` -> vytvoří sérii, data [`NewSeries`]; když některý výskyt koliduje
+//!   se schválenou rezervací, neuloží nic a vrátí 409 s výskyty a jejich kolizemi v `occurrences`,
+//!   s `"dry_run": true` jen vrátí výskyty a kolize
+//! - `GET /events/series/<id>` -> série a její výskyty
+//! - `PATCH /events/<id>/series?scope=` -> upraví výskyt `id` a podle `scope` i další:
+//!   `this` (jen tento), `following` (tento a následující) nebo `all` (celou sérii),
+//!   data [`UpdateReservation`]; nový čas se u ostatních výskytů projeví jako posun o stejný rozdíl
+//! - `DELETE /events/<id>/series?scope=` -> zruší výskyty, u `this` se výskyt přidá do `exdates`,
+//!   u `following` se pravidlo ukončí (`UNTIL`) před ním, u `all` zmizí celá série
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use rocket::http::{RawStr, Status};
+use rocket::request::FromFormValue;
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+use super::approval;
+use super::conflicts;
+use super::layouts;
+use super::recurrence::{self, Rule};
+use super::rooms;
+use crate::audit::{self, RequestId, Target};
+use crate::auth::permissions::actions::BookingCreate;
+use crate::auth::permissions::{Permissions, Permitted};
+use crate::auth::roles::Noob;
+use crate::auth::AuthToken;
+use crate::datetime;
+use crate::db;
+use crate::db::{InsertReservation, InsertSeries, NewSeries, Reservation, ReservationChanges, Series, UpdateReservation};
+use crate::error::{ApiError, ApiResult};
+
+/// kterých výskytů se úprava týká
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+	/// jen tohoto
+	This,
+	/// tohoto a všech následujících
+	Following,
+	/// celé série
+	All,
+}
+
+impl<'v> FromFormValue<'v> for Scope {
+	type Error = &'v RawStr;
+
+	fn from_form_value(value: &'v RawStr) -> Result<Self, Self::Error> {
+		match value.as_str() {
+			"this" => Ok(Scope::This),
+			"following" => Ok(Scope::Following),
+			"all" => Ok(Scope::All),
+			_ => Err(value),
+		}
+	}
+}
+
+/// výskyt série s kolizemi
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Planned {
+	/// id rezervace, pokud už je uložená
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id:         Option<i32>,
+	/// začátek (UTC)
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub begin_time: NaiveDateTime,
+	/// konec (UTC)
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub end_time:   NaiveDateTime,
+	/// schválené rezervace, se kterými výskyt koliduje
+	pub conflicts:  Vec<i32>,
+}
+
+/// výsledek operace nad sérií
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesDone {
+	/// vždy 0
+	pub result:      i32,
+	/// id série (u `dry_run` chybí)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id:          Option<i32>,
+	/// dotčené výskyty
+	pub occurrences: Vec<Planned>,
+}
+
+/// série i s výskyty, tak jak ji vrací GET
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesDetail {
+	/// série
+	#[serde(flatten)]
+	pub series:      Series,
+	/// výskyty podle času
+	pub occurrences: Vec<Reservation>,
+}
+
+/// 409, pokud některý výskyt koliduje
+fn check_planned(planned: &[Planned]) -> Result<(), ApiError> {
+	let colliding = planned.iter().filter(|p| !p.conflicts.is_empty()).cloned().collect::<Vec<_>>();
+	if colliding.is_empty() {
+		return Ok(());
+	}
+
+	let mut ids = colliding.iter().flat_map(|p| p.conflicts.iter().cloned()).collect::<Vec<_>>();
+	ids.sort();
+	ids.dedup();
+
+	Err(ApiError::new(Status::Conflict, "some occurrences collide with approved reservations")
+		.with("conflicts", ids)
+		.with("occurrences", colliding))
+}
+
+/// výskyty série podle času
+fn occurrences(con: &SqliteConnection, s_id: i32) -> QueryResult<Vec<Reservation>> {
+	use crate::schema::booking::dsl::*;
+
+	booking.filter(series_id.eq(s_id)).order((begin_time, id)).load::<Reservation>(con)
+}
+
+/// načte výskyt série a ověří, že ho uživatel smí měnit, vrací ho i se sérií
+fn anchor(
+	con: &SqliteConnection,
+	r_id: i32,
+	usr: &AuthToken<Noob>,
+	perms: &Permissions,
+) -> Result<(Reservation, Series), ApiError> {
+	use crate::schema::booking::dsl::*;
+	use crate::schema::booking_series::dsl::booking_series;
+
+	let r = booking.find(r_id).first::<Reservation>(con).optional()?;
+	let r = r.ok_or_else(|| ApiError::not_found("no such event"))?;
+	if !perms.may_edit_booking(&usr.user, &r.author) {
+		Err(ApiError::not_found("no such event"))?
+	}
+
+	let s_id = r.series_id.ok_or_else(|| ApiError::invalid("event is not part of a series"))?;
+	let s = booking_series.find(s_id).first::<Series>(con)?;
+
+	Ok((r, s))
+}
+
+/// výskyty, kterých se `scope` týká
+fn in_scope(con: &SqliteConnection, first: &Reservation, scope: Scope) -> QueryResult<Vec<Reservation>> {
+	let all = occurrences(con, first.series_id.unwrap_or(-1))?;
+
+	Ok(match scope {
+		Scope::This => vec![first.clone()],
+		Scope::Following => all.into_iter().filter(|r| r.begin_time >= first.begin_time).collect(),
+		Scope::All => all,
+	})
+}
+
+/// chybějící nebo neznámý `scope`
+fn bad_scope() -> ApiError {
+	ApiError::invalid("scope must be `this`, `following` or `all`")
+}
+
+/// vytvoří sérii rezervací
+///
+/// POST /events/series application/json
+///
+/// data: [`NewSeries`]
+///
+/// vrací [`SeriesDone`] s id série a výskytů, při kolizi 409 a nic se neuloží
+#[post("/events/series", data = "<input>")]
+pub fn create(input: Json<NewSeries>, usr: Permitted<BookingCreate>, req_id: RequestId) -> ApiResult<SeriesDone> {
+	use crate::schema::booking::dsl::booking;
+	use crate::schema::booking_series::dsl::*;
+
+	let mut input = input.into_inner();
+	let con = db::get_con();
+	input.reservation.rooms = rooms::resolve(&con, &input.reservation.rooms)?;
+
+	let first = &input.reservation;
+	let mask = first.rooms.mask().unwrap_or(0);
+	let rule = input.rrule.parse::<Rule>().map_err(ApiError::invalid)?;
+	let expanded =
+		recurrence::expand(&rule, first.begin_time, first.end_time, &input.exdates).map_err(ApiError::invalid)?;
+	conflicts::validate(mask, first.begin_time, first.end_time)?;
+	layouts::check(&con, first.layout as i32, mask, first.people as i32)?;
+	let done = con.immediate_transaction(|| {
+		let mut planned = expanded
+			.into_iter()
+			.map(|o| {
+				let found = conflicts::find(&con, mask, o.begin_time, o.end_time, None)?;
+				Ok(Planned { id: None, begin_time: o.begin_time, end_time: o.end_time, conflicts: found })
+			})
+			.collect::<QueryResult<Vec<_>>>()?;
+
+		if input.dry_run {
+			return Ok(SeriesDone { result: 0, id: None, occurrences: planned });
+		}
+		check_planned(&planned)?;
+
+		let new = InsertSeries {
+			rrule:   rule.to_string(),
+			exdates: serde_json::to_string(&input.exdates).unwrap_or_else(|_| "[]".to_string()),
+			author:  usr.user.email.clone(),
+		};
+		diesel::insert_into(booking_series).values(&new).execute(&con)?;
+		let s_id = booking_series.select(id).order(id.desc()).first::<i32>(&con)?;
+
+		let rows = planned
+			.iter()
+			.map(|p| InsertReservation {
+				begin_time: p.begin_time,
+				end_time: p.end_time,
+				series_id: Some(s_id),
+				..InsertReservation::new(first, &usr.user.email)
+			})
+			.collect::<Vec<_>>();
+		diesel::insert_into(booking).values(&rows).execute(&con)?;
+
+		for (p, r) in planned.iter_mut().zip(occurrences(&con, s_id)?) {
+			rooms::assign(&con, r.id, mask)?;
+			p.id = Some(r.id);
+		}
+
+		Ok::<_, ApiError>(SeriesDone { result: 0, id: Some(s_id), occurrences: planned })
+	})?;
+
+	if let Some(s_id) = done.id {
+		audit::record(&usr.user, "series.create", Target::Series(s_id), None, audit::snapshot(&done), &req_id);
+	}
+
+	Ok(Json(done))
+}
+
+/// vrátí sérii i s výskyty
+///
+/// GET /events/series/<id> application/json
+#[get("/events/series/<s_id>")]
+pub fn get(s_id: i32, _u: AuthToken<Noob>) -> ApiResult<SeriesDetail> {
+	use crate::schema::booking_series::dsl::*;
+
+	let con = db::get_con();
+	let series = booking_series.find(s_id).first::<Series>(&con).optional()?;
+	let series = series.ok_or_else(|| ApiError::not_found("no such series"))?;
+
+	Ok(Json(SeriesDetail { occurrences: occurrences(&con, s_id)?, series }))
+}
+
+/// upraví výskyt série a podle `scope` i další
+///
+/// PATCH /events/<id>/series?scope=this|following|all application/json
+///
+/// data: [`UpdateReservation`]
+///
+/// při kolizi kteréhokoli výskytu vrací 409 a nic nezmění
+#[patch("/events/<r_id>/series?<scope>", data = "<input>")]
+pub fn update(
+	r_id: i32,
+	scope: Option<Scope>,
+	input: Json<UpdateReservation>,
+	usr: AuthToken<Noob>,
+	perms: State<Permissions>,
+	req_id: RequestId,
+) -> ApiResult<SeriesDone> {
+	use crate::schema::booking::dsl::booking;
+
+	let scope = scope.ok_or_else(bad_scope)?;
+	let con = db::get_con();
+	let mut input = input.into_inner();
+	if let Some(r) = &input.rooms {
+		input.rooms = Some(rooms::resolve(&con, r)?);
+	}
+
+	let (s_id, before, done) = con.immediate_transaction(|| {
+		let (first, series) = anchor(&con, r_id, &usr, &perms)?;
+		let targets = in_scope(&con, &first, scope)?;
+
+		// posun a délka podle upraveného výskytu
+		let (old_begin, old_end) = (first.begin_time, first.end_time);
+		let new_begin = input.begin_time.unwrap_or(old_begin);
+		let new_end = input.end_time.unwrap_or(new_begin + (old_end - old_begin));
+		let (delta, length) = (new_begin - old_begin, new_end - new_begin);
+		let moves = delta != Duration::zero() || length != old_end - old_begin;
+
+		let mut planned = vec![];
+		let mut changes = vec![];
+		for target in &targets {
+			approval::check_editable(target)?;
+
+			// časy z dat platí jen pro upravený výskyt, ostatní se posunou (v místním čase)
+			let mut change = ReservationChanges::from(&input);
+			change.begin_time = None;
+			change.end_time = None;
+			if moves {
+				let begin = recurrence::shift(target.begin_time, delta);
+				change.begin_time = Some(begin);
+				change.end_time = Some(begin + length);
+			}
+
+			layouts::check_changes(&con, target, &change)?;
+
+			let r_rooms = change.rooms.unwrap_or(target.rooms);
+			let begin = change.begin_time.unwrap_or(target.begin_time);
+			let end = change.end_time.unwrap_or(target.end_time);
+			let found = if moves || change.rooms.is_some() {
+				conflicts::validate(r_rooms, begin, end)?;
+				conflicts::find(&con, r_rooms, begin, end, Some(target.id))?
+			} else {
+				vec![]
+			};
+
+			planned.push(Planned { id: Some(target.id), begin_time: begin, end_time: end, conflicts: found });
+			changes.push((target, change));
+		}
+		check_planned(&planned)?;
+
+		for (target, change) in changes.iter().filter(|(_, c)| !c.is_empty()) {
+			diesel::update(booking.find(target.id)).set(change).execute(&con)?;
+			if let Some(mask) = change.rooms {
+				rooms::assign(&con, target.id, mask)?;
+			}
+			if approval::reopens(target, change, &usr.user) {
+				approval::reopen(&con, target.id)?;
+			}
+		}
+
+		Ok::<_, ApiError>((series.id, targets, SeriesDone { result: 0, id: Some(series.id), occurrences: planned }))
+	})?;
+
+	let after = occurrences(&db::get_con(), s_id).unwrap_or_default();
+	let after = after.into_iter().filter(|r| before.iter().any(|b| b.id == r.id)).collect::<Vec<_>>();
+	let (before, after) = (audit::snapshot(&before), audit::snapshot(&after));
+	audit::record(&usr.user, "series.update", Target::Series(s_id), before, after, &req_id);
+
+	Ok(Json(done))
+}
+
+/// zruší výskyt série a podle `scope` i další
+///
+/// DELETE /events/<id>/series?scope=this|following|all
+#[delete("/events/<r_id>/series?<scope>")]
+pub fn cancel(
+	r_id: i32,
+	scope: Option<Scope>,
+	usr: AuthToken<Noob>,
+	perms: State<Permissions>,
+	req_id: RequestId,
+) -> ApiResult<SeriesDone> {
+	use crate::schema::booking::dsl::{booking, id};
+	use crate::schema::booking_series::dsl::*;
+
+	let scope = scope.ok_or_else(bad_scope)?;
+	let con = db::get_con();
+
+	let (s_id, removed) = con.immediate_transaction(|| {
+		let (first, series) = anchor(&con, r_id, &usr, &perms)?;
+		let removed = in_scope(&con, &first, scope)?;
+
+		let ids = removed.iter().map(|r| r.id).collect::<Vec<_>>();
+		diesel::delete(booking.filter(id.eq_any(&ids))).execute(&con)?;
+		for gone in &ids {
+			rooms::assign(&con, *gone, 0)?;
+		}
+
+		if occurrences(&con, series.id)?.is_empty() {
+			diesel::delete(booking_series.find(series.id)).execute(&con)?;
+		} else if scope == Scope::This {
+			let mut skipped = serde_json::from_str::<Vec<String>>(&series.exdates).unwrap_or_default();
+			skipped.push(datetime::format(first.begin_time));
+			let skipped = serde_json::to_string(&skipped).unwrap_or_else(|_| "[]".to_string());
+			diesel::update(booking_series.find(series.id)).set(exdates.eq(skipped)).execute(&con)?;
+		} else if let Ok(mut rule) = series.rrule.parse::<Rule>() {
+			// série teď končí před zrušeným výskytem, `UNTIL` je v místním čase
+			rule.until = Some(datetime::to_local(first.begin_time) - Duration::seconds(1));
+			rule.count = None;
+			diesel::update(booking_series.find(series.id)).set(rrule.eq(rule.to_string())).execute(&con)?;
+		}
+
+		Ok::<_, ApiError>((series.id, removed))
+	})?;
+
+	audit::record(&usr.user, "series.cancel", Target::Series(s_id), audit::snapshot(&removed), None, &req_id);
+
+	let occurrences = removed
+		.into_iter()
+		.map(|r| Planned { id: Some(r.id), begin_time: r.begin_time, end_time: r.end_time, conflicts: vec![] })
+		.collect();
+
+	Ok(Json(SeriesDone { result: 0, id: Some(s_id), occurrences }))
+}
+
+/// vrací routy sérií
+pub fn routes() -> Vec<Route> {
+	routes![create, get, update, cancel]
+}
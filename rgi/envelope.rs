@@ -0,0 +1,110 @@
+//! obálka požadavku, kterou RGI dostává na stdin
+//!
+//! serializuje se celá najednou přes serde, takže argumenty si drží svůj typ
+//! (číslo zůstane číslem) a uvozovky ani nové řádky v datech protokol nerozbijí.
+//!
+//! formát popisuje JSON Schema v `rgi/envelope.schema.json`:
+//! ```json
+//! {
+//!     "method": "PATCH",
+//!     "args": { "id": 3 },
+//!     "data": { "name": "koncert" },
+//!     "caller": { "id": 1, "name": "Jan \"Honza\" Novák", "email": "novak@gjk.cz", "role": "noob" }
+//! }
+//! ```
+//! `data` a `caller` chybí, pokud je endpoint nemá, `id` mají jen požadavky pro
+//! perzistentní workery. `db` (cesta k databázi) dostávají jen skripty, které ji
+//! potřebují, místo `DATABASE_URL` v prostředí, viz [`super::sandbox`].
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::db::User;
+
+/// obálka požadavku pro RGI
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+	/// korelační id, jen pro perzistentní workery, viz [`super::pool`]
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id:     Option<u64>,
+	/// HTTP metoda (stejná jako první argument procesu)
+	pub method: String,
+	/// parametry z URL, jméno -> hodnota
+	pub args:   Map<String, Value>,
+	/// tělo požadavku
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub data:   Option<Value>,
+	/// kdo požadavek poslal
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub caller: Option<Caller>,
+	/// cesta k databázi
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub db:     Option<String>,
+}
+
+/// identita volajícího
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Caller {
+	/// id uživatele (API klíče mají záporné)
+	pub id:    i32,
+	/// jméno
+	pub name:  String,
+	/// email
+	pub email: String,
+	/// role
+	pub role:  String,
+}
+
+impl From<&User> for Caller {
+	fn from(user: &User) -> Self {
+		Caller { id: user.id, name: user.name.clone(), email: user.email.clone(), role: user.role.clone() }
+	}
+}
+
+impl Envelope {
+	/// prázdná obálka pro danou metodu
+	pub fn new(method: &str) -> Self {
+		Envelope {
+			id:     None,
+			method: method.to_string(),
+			args:   Map::new(),
+			data:   None,
+			caller: None,
+			db:     None,
+		}
+	}
+
+	/// přidá argument
+	pub fn arg<T: Serialize + ?Sized>(mut self, name: &str, value: &T) -> Self {
+		self.args.insert(name.to_string(), to_value(value));
+		self
+	}
+
+	/// nastaví tělo požadavku
+	pub fn data<T: Serialize + ?Sized>(mut self, data: &T) -> Self {
+		self.data = Some(to_value(data));
+		self
+	}
+
+	/// nastaví volajícího
+	pub fn caller(mut self, user: &User) -> Self {
+		self.caller = Some(Caller::from(user));
+		self
+	}
+
+	/// nastaví cestu k databázi
+	pub fn db(mut self, path: &str) -> Self {
+		self.db = Some(path.to_string());
+		self
+	}
+
+	/// celá obálka jako jeden řádek JSONu
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).expect("envelope is always serializable")
+	}
+}
+
+/// převede hodnotu na JSON, to selže jen u map s nestringovými klíči
+fn to_value<T: Serialize + ?Sized>(value: &T) -> Value {
+	serde_json::to_value(value).expect("RGI arguments must serialize to JSON")
+}
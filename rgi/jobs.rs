@@ -0,0 +1,313 @@
+//! asynchronní RGI úlohy
+//!
+//! dlouho běžící RGI (exporty, reporty, hromadné importy) nemusí blokovat request.
+//! Endpoint z manifestu s `mode = "async"` (viz [`super::manifest`]) jen založí
+//! úlohu v tabulce `rgi_jobs` a hned vrátí `202 Accepted` s hlavičkou
+//! `Location: /rgi/jobs/<id>`:
+//! ```json
+//! { "job": 12, "status": "queued" }
+//! ```
+//! skript pak běží na pozadí stejně jako synchronní RGI (timeouty, sandbox, pooly).
+//!
+//! - `GET /rgi/jobs/<id>` -> stav úlohy, viz [`Job`]; úlohu vidí jen ten, kdo ji
+//!   založil, nebo role s oprávněním `job.view_any` (úlohy z veřejných endpointů
+//!   vidí každý)
+//!
+//! stavy: `queued` -> `running` -> `done` nebo `failed`. U `done` je v `result`
+//! odpověď skriptu, u `failed` je v `error` zpráva a v `code` HTTP status, který
+//! by vrátilo synchronní volání. Úlohy, které nedoběhly před restartem serveru,
+//! se při startu označí jako `failed`.
+//!
+//! každý skript má vlastní frontu a nejvýše `rgi_job_limit` vláken, která z ní
+//! úlohy berou. Fronta je omezená, když je plná, založení úlohy vrátí 503:
+//! ```toml
+//! rgi_job_limit = 2
+//! rgi_job_limits = { "rgi/reports/export.py" = 1 }
+//! rgi_job_queue = 100 # nejvíc čekajících úloh jednoho skriptu
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use diesel::dsl::now;
+use diesel::prelude::*;
+use rocket::config::Config;
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::{Route, State};
+use rocket_contrib::json::Json;
+use serde_json::Value;
+
+use super::envelope::Envelope;
+use super::response::RgiResponse;
+use super::runner::Runner;
+use crate::auth::permissions::actions::{Action, JobViewAny};
+use crate::auth::permissions::Permissions;
+use crate::auth::AuthToken;
+use crate::db;
+use crate::db::{Job, NewJob, User};
+use crate::error::{ApiError, ApiResult};
+
+/// úloha čeká na volné místo
+pub const QUEUED: &str = "queued";
+/// skript běží
+pub const RUNNING: &str = "running";
+/// skript doběhl, výsledek je v `result`
+pub const DONE: &str = "done";
+/// skript selhal, zpráva je v `error`
+pub const FAILED: &str = "failed";
+
+/// výchozí počet současně běžících úloh jednoho skriptu
+const DEFAULT_LIMIT: usize = 2;
+
+/// výchozí nejvyšší počet čekajících úloh jednoho skriptu
+const DEFAULT_QUEUE: usize = 100;
+
+/// fronta asynchronních úloh
+///
+/// klony sdílí fronty i vlákna
+#[derive(Clone)]
+pub struct Jobs {
+	runner:        Runner,
+	default_limit: usize,
+	/// skript -> nejvyšší počet současně běžících úloh
+	limits:        HashMap<String, usize>,
+	/// nejvyšší počet čekajících úloh jednoho skriptu
+	queue_cap:     usize,
+	queues:        Arc<Mutex<HashMap<String, Queue>>>,
+}
+
+/// fronta jednoho skriptu
+#[derive(Default)]
+struct Queue {
+	/// úlohy, které čekají na vlákno
+	pending: VecDeque<(i32, Envelope)>,
+	/// počet vláken, která frontu zpracovávají
+	workers: usize,
+}
+
+/// vlákno zpracovávající frontu skriptu, po panice se při dropu odhlásí
+struct Worker<'a> {
+	jobs:     &'a Jobs,
+	script:   &'a str,
+	/// už se odhlásilo samo
+	finished: bool,
+}
+
+impl Drop for Worker<'_> {
+	fn drop(&mut self) {
+		if self.finished {
+			return;
+		}
+		let mut queues = self.jobs.queues.lock().unwrap_or_else(|e| e.into_inner());
+		if let Some(queue) = queues.get_mut(self.script) {
+			queue.workers = queue.workers.saturating_sub(1);
+		}
+	}
+}
+
+impl Jobs {
+	/// fronta nad daným runnerem se stejným limitem pro všechny skripty
+	pub fn new(runner: Runner, default_limit: usize) -> Self {
+		Jobs {
+			runner,
+			default_limit,
+			limits: HashMap::new(),
+			queue_cap: DEFAULT_QUEUE,
+			queues: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	/// nastaví limit pro jeden skript
+	pub fn with_limit(mut self, script: &str, limit: usize) -> Self {
+		self.limits.insert(script.to_string(), limit);
+		self
+	}
+
+	/// nastaví nejvyšší počet čekajících úloh jednoho skriptu
+	pub fn with_queue(mut self, cap: usize) -> Self {
+		self.queue_cap = cap;
+		self
+	}
+
+	/// načte `rgi_job_limit`, `rgi_job_limits` a `rgi_job_queue` z konfigurace Rocketu
+	pub fn from_config(config: &Config, runner: Runner) -> Result<Self, String> {
+		let default = config.get_int("rgi_job_limit").unwrap_or(DEFAULT_LIMIT as i64);
+		if default <= 0 {
+			Err("rgi_job_limit must be positive".to_string())?
+		}
+
+		let mut jobs = Jobs::new(runner, default as usize);
+		if let Ok(table) = config.get_table("rgi_job_limits") {
+			for (script, limit) in table {
+				let limit = limit
+					.as_integer()
+					.filter(|l| *l > 0)
+					.ok_or_else(|| format!("job limit of `{}` must be a positive number", script))?;
+				jobs = jobs.with_limit(script, limit as usize);
+			}
+		}
+
+		let cap = config.get_int("rgi_job_queue").unwrap_or(DEFAULT_QUEUE as i64);
+		if cap <= 0 {
+			Err("rgi_job_queue must be positive".to_string())?
+		}
+
+		Ok(jobs.with_queue(cap as usize))
+	}
+
+	/// limit pro daný skript
+	pub fn limit(&self, script: &str) -> usize {
+		self.limits.get(script).cloned().unwrap_or(self.default_limit)
+	}
+
+	/// založí úlohu a zařadí ji do fronty skriptu, vrací její id
+	///
+	/// plná fronta je 503, úloha se pak vůbec nezaloží
+	pub fn submit(&self, script: &str, envelope: Envelope, author: Option<&User>) -> Result<i32, ApiError> {
+		use crate::schema::rgi_jobs::dsl::*;
+
+		let new = NewJob {
+			script:     script.to_string(),
+			method:     envelope.method.clone(),
+			status:     QUEUED.to_string(),
+			created_by: author.map(|u| u.email.clone()),
+		};
+
+		// zámek se drží i přes insert, aby fronta nepřetekla
+		let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+		let queue = queues.entry(script.to_string()).or_default();
+		if queue.pending.len() >= self.queue_cap {
+			Err(ApiError::new(Status::ServiceUnavailable, "too many queued jobs, try again later"))?
+		}
+
+		let con = db::get_con();
+		let job_id = con.immediate_transaction(|| {
+			diesel::insert_into(rgi_jobs).values(&new).execute(&con)?;
+			rgi_jobs.select(id).order(id.desc()).first::<i32>(&con)
+		})?;
+
+		queue.pending.push_back((job_id, envelope));
+		if queue.workers < self.limit(script) {
+			queue.workers += 1;
+			let jobs = self.clone();
+			let script = script.to_string();
+			thread::spawn(move || jobs.work(&script));
+		}
+
+		Ok(job_id)
+	}
+
+	/// bere úlohy z fronty skriptu, dokud nějaké jsou
+	fn work(&self, script: &str) {
+		let mut worker = Worker { jobs: self, script, finished: false };
+
+		loop {
+			let next = {
+				let mut queues = self.queues.lock().unwrap_or_else(|e| e.into_inner());
+				let queue = queues.entry(script.to_string()).or_default();
+				let next = queue.pending.pop_front();
+				// odhlásit se musí pod stejným zámkem, jinak by nová úloha mohla zůstat bez vlákna
+				if next.is_none() {
+					queue.workers = queue.workers.saturating_sub(1);
+					worker.finished = true;
+				}
+				next
+			};
+
+			match next {
+				Some((job_id, envelope)) => self.execute(job_id, script, &envelope),
+				None => return,
+			}
+		}
+	}
+
+	/// spustí skript a uloží výsledek
+	fn execute(&self, job_id: i32, script: &str, envelope: &Envelope) {
+		use crate::schema::rgi_jobs::dsl::*;
+
+		let con = db::get_con();
+		let _ = diesel::update(rgi_jobs.find(job_id)).set((status.eq(RUNNING), started.eq(now))).execute(&con);
+
+		let outcome = self
+			.runner
+			.run(script, envelope)
+			.map_err(ApiError::from)
+			.and_then(|output| RgiResponse::<Value>::parse(&output)?.into_result());
+
+		let saved = match outcome {
+			Ok(Json(value)) => diesel::update(rgi_jobs.find(job_id))
+				.set((status.eq(DONE), result.eq(value.to_string()), code.eq(200), finished.eq(now)))
+				.execute(&con),
+			Err(e) => diesel::update(rgi_jobs.find(job_id))
+				.set((
+					status.eq(FAILED),
+					result.eq(Some(Value::Object(e.details.clone()).to_string()).filter(|_| !e.details.is_empty())),
+					error.eq(&e.message),
+					code.eq(e.status.code as i32),
+					finished.eq(now),
+				))
+				.execute(&con),
+		};
+
+		if let Err(e) = saved {
+			eprintln!("can't save RGI job {}: {}", job_id, e);
+		}
+	}
+}
+
+/// označí úlohy, které nedoběhly před restartem, jako `failed`
+pub fn recover() -> QueryResult<usize> {
+	use crate::schema::rgi_jobs::dsl::*;
+
+	diesel::update(rgi_jobs.filter(status.eq_any(vec![QUEUED, RUNNING])))
+		.set((status.eq(FAILED), error.eq("server restarted"), finished.eq(now)))
+		.execute(&db::get_con())
+}
+
+/// odpověď `202 Accepted` na založení úlohy
+#[derive(Debug, Clone, Copy)]
+pub struct Accepted(pub i32);
+
+impl<'r> Responder<'r> for Accepted {
+	fn respond_to(self, _: &Request) -> response::Result<'r> {
+		let body = json!({ "job": self.0, "status": QUEUED }).to_string();
+
+		Response::build()
+			.status(Status::Accepted)
+			.header(ContentType::JSON)
+			.raw_header("Location", format!("/rgi/jobs/{}", self.0))
+			.sized_body(Cursor::new(body))
+			.ok()
+	}
+}
+
+/// vrátí stav úlohy
+///
+/// GET /rgi/jobs/<id> application/json
+///
+/// cizí úloha je 404, aby nešlo zjišťovat, které existují
+#[get("/jobs/<job_id>")]
+pub fn job(job_id: i32, usr: Option<AuthToken>, perms: State<Permissions>) -> ApiResult<Job> {
+	use crate::schema::rgi_jobs::dsl::*;
+
+	let found = rgi_jobs.find(job_id).first::<Job>(&db::get_con()).optional()?;
+	let visible = |j: &Job| match (&j.created_by, &usr) {
+		(None, _) => true,
+		(Some(_), None) => false,
+		(Some(author), Some(usr)) => author == &usr.user.email || usr.may(&perms, JobViewAny::name()),
+	};
+
+	match found {
+		Some(j) if visible(&j) => Ok(Json(j)),
+		_ => Err(ApiError::not_found("no such job")),
+	}
+}
+
+/// vrací routy úloh
+pub fn routes() -> Vec<Route> {
+	routes![job]
+}
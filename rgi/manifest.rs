@@ -0,0 +1,384 @@
+//! deklarativní RGI endpointy
+//!
+//! nové RGI (třeba reporty) nepotřebuje vlastní Rust modul, stačí ho popsat
+//! v manifestu (Rocket.toml: `rgi_manifest`, výchozí `rgi/manifest.toml`),
+//! který se načte při startu a [`super::routes`] z něj udělá routy:
+//! ```toml
+//! [[endpoint]]
+//! path = "/reports/usage/<from>/<to>"   # pod /rgi/
+//! method = "GET"
+//! script = "rgi/reports/usage.py"
+//! verb = "USAGE"                        # první argument skriptu a `method` v obálce
+//! role = "approver"                     # bez role je endpoint veřejný
+//! body = "none"                         # none, json, new_reservation, update_reservation
+//! mode = "sync"                         # sync nebo async, viz níže
+//! args = [
+//!     { name = "from", source = "url" },
+//!     { name = "to", source = "url" },
+//!     { name = "room", source = "query", type = "int" },
+//!     { name = "email", source = "user" },
+//! ]
+//! ```
+//! argumenty:
+//! - `source` -> `url` (segment `<name>` v `path`), `query` (`?name=`, chybí -> `null`)
+//!   nebo `user` (`id`, `name`, `email` nebo `role` přihlášeného uživatele)
+//! - `type` -> `string` (výchozí), `int`, `float` nebo `bool`, jinak 422
+//! - `field` -> u `source = "user"` které pole, výchozí je `name`
+//!
+//! odpověď skriptu se zpracuje stejně jako u ručně psaných endpointů, viz
+//! [`super::response`], timeout se nastavuje v `rgi_timeouts` jako `"skript VERB"`.
+//! Endpoint s `mode = "async"` na skript nečeká, vrátí `202 Accepted` s id úlohy,
+//! viz [`super::jobs`].
+
+use std::fs;
+use std::io::Read;
+use std::str::FromStr;
+
+use rocket::config::Config;
+use rocket::handler::{self, Handler};
+use rocket::http::uri::Origin;
+use rocket::http::{Method, Status};
+use rocket::{Data, Request, Route, State};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::envelope::Envelope;
+use super::jobs::{Accepted, Jobs};
+use super::response::RgiResponse;
+use super::runner::Runner;
+use crate::auth::{roles, AuthToken};
+use crate::db::{NewReservation, UpdateReservation, User};
+use crate::error::{ApiError, ApiResult};
+
+/// největší přijaté tělo požadavku
+const BODY_LIMIT: u64 = 1 << 20;
+
+/// obsah manifestu
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+	/// endpointy
+	#[serde(default)]
+	pub endpoint: Vec<Endpoint>,
+}
+
+/// jeden endpoint z manifestu
+#[derive(Deserialize, Debug, Clone)]
+pub struct Endpoint {
+	/// cesta pod `/rgi/`, dynamické segmenty jako v Rocketu (`<id>`)
+	pub path:   String,
+	/// HTTP metoda
+	pub method: String,
+	/// cesta ke skriptu
+	pub script: String,
+	/// metoda předaná skriptu
+	pub verb:   String,
+	/// nejnižší role, která smí endpoint volat
+	#[serde(default)]
+	pub role:   Option<String>,
+	/// typ těla požadavku
+	#[serde(default)]
+	pub body:   Body,
+	/// argumenty pro skript
+	#[serde(default)]
+	pub args:   Vec<Arg>,
+	/// čekat na skript, nebo z volání udělat úlohu
+	#[serde(default)]
+	pub mode:   Mode,
+}
+
+/// typ těla požadavku
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+	/// bez těla
+	None,
+	/// libovolný JSON
+	Json,
+	/// [`NewReservation`]
+	NewReservation,
+	/// [`UpdateReservation`]
+	UpdateReservation,
+}
+
+impl Default for Body {
+	fn default() -> Self {
+		Body::None
+	}
+}
+
+/// jak se skript volá
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+	/// request čeká na odpověď skriptu
+	Sync,
+	/// request hned vrátí id úlohy, viz [`super::jobs`]
+	Async,
+}
+
+impl Default for Mode {
+	fn default() -> Self {
+		Mode::Sync
+	}
+}
+
+/// odkud se bere argument
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+	/// dynamický segment cesty
+	Url,
+	/// query string
+	Query,
+	/// přihlášený uživatel
+	User,
+}
+
+/// typ argumentu
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgType {
+	/// řetězec
+	String,
+	/// celé číslo
+	Int,
+	/// desetinné číslo
+	Float,
+	/// `true`/`false`
+	Bool,
+}
+
+impl Default for ArgType {
+	fn default() -> Self {
+		ArgType::String
+	}
+}
+
+/// argument pro skript
+#[derive(Deserialize, Debug, Clone)]
+pub struct Arg {
+	/// jméno v `args` obálky
+	pub name:   String,
+	/// odkud se bere
+	pub source: Source,
+	/// typ
+	#[serde(default, rename = "type")]
+	pub kind:   ArgType,
+	/// pole uživatele pro `source = "user"`
+	#[serde(default)]
+	pub field:  Option<String>,
+}
+
+impl Manifest {
+	/// načte manifest z TOML a zkontroluje ho
+	pub fn from_toml(src: &str) -> Result<Self, String> {
+		let manifest: Manifest = toml::from_str(src).map_err(|e| format!("invalid RGI manifest: {}", e))?;
+
+		for endpoint in &manifest.endpoint {
+			endpoint.check().map_err(|e| format!("RGI endpoint {} {}: {}", endpoint.method, endpoint.path, e))?;
+		}
+
+		Ok(manifest)
+	}
+
+	/// načte soubor z `rgi_manifest` v konfiguraci Rocketu, chybějící soubor je prázdný manifest
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let path = config.get_string("rgi_manifest").unwrap_or_else(|_| "rgi/manifest.toml".to_string());
+
+		match fs::read_to_string(&path) {
+			Ok(src) => Manifest::from_toml(&src),
+			Err(_) if config.get_string("rgi_manifest").is_err() => Ok(Manifest::default()),
+			Err(e) => Err(format!("can't read {}: {}", path, e)),
+		}
+	}
+
+	/// routy pro všechny endpointy
+	pub fn routes(&self) -> Vec<Route> {
+		self.endpoint
+			.iter()
+			.map(|e| {
+				let method = Method::from_str(&e.method.to_uppercase()).expect("checked when loading");
+				Route::new(method, e.path.as_str(), RgiHandler { endpoint: e.clone(), params: e.params() })
+			})
+			.collect()
+	}
+}
+
+impl Endpoint {
+	/// jména dynamických segmentů cesty v pořadí
+	fn params(&self) -> Vec<String> {
+		self.path
+			.split('/')
+			.filter(|s| s.starts_with('<') && s.ends_with('>'))
+			.map(|s| s.trim_start_matches('<').trim_end_matches('>').to_string())
+			.collect()
+	}
+
+	/// zkontroluje, že endpoint dává smysl
+	fn check(&self) -> Result<(), String> {
+		Method::from_str(&self.method.to_uppercase()).map_err(|_| format!("unknown method `{}`", self.method))?;
+
+		if !self.path.starts_with('/') {
+			Err("path must start with `/`")?
+		}
+		Origin::parse_route(&self.path).map_err(|e| format!("invalid path: {}", e))?;
+		if self.params().iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')) {
+			Err("path parameters must be plain identifiers")?
+		}
+		if let Some(role) = &self.role {
+			roles::canonical(role).ok_or_else(|| format!("unknown role `{}`", role))?;
+		}
+
+		let params = self.params();
+		for arg in &self.args {
+			match arg.source {
+				Source::Url if !params.contains(&arg.name) => Err(format!("`{}` is not in the path", arg.name))?,
+				Source::User => match arg.field.as_ref().unwrap_or(&arg.name).as_str() {
+					"id" | "name" | "email" | "role" => (),
+					f => Err(format!("unknown user field `{}`", f))?,
+				},
+				_ => (),
+			}
+		}
+
+		if self.role.is_none() && self.args.iter().any(|a| a.source == Source::User) {
+			Err("user arguments need a role")?
+		}
+
+		Ok(())
+	}
+}
+
+/// handler endpointu z manifestu
+#[derive(Clone)]
+struct RgiHandler {
+	endpoint: Endpoint,
+	params:   Vec<String>,
+}
+
+impl Handler for RgiHandler {
+	fn handle<'r>(&self, req: &'r Request, data: Data) -> handler::Outcome<'r> {
+		let user = match &self.endpoint.role {
+			None => None,
+			Some(role) => match req.guard::<AuthToken>() {
+				rocket::Outcome::Success(token) => {
+					if !roles::ancestors(&token.user.role).iter().any(|r| r.eq_ignore_ascii_case(role)) {
+						return handler::Outcome::from(req, ApiError::new(Status::Forbidden, "insufficient role"));
+					}
+					Some(token.user)
+				}
+				rocket::Outcome::Failure((status, _)) => return handler::Outcome::Failure(status),
+				rocket::Outcome::Forward(_) => return handler::Outcome::Failure(Status::Unauthorized),
+			},
+		};
+
+		let envelope = match self.envelope(req, data, user.as_ref()) {
+			Ok(envelope) => envelope,
+			Err(e) => return handler::Outcome::from(req, e),
+		};
+
+		if self.endpoint.mode == Mode::Async {
+			return match req.guard::<State<Jobs>>() {
+				rocket::Outcome::Success(jobs) => handler::Outcome::from(
+					req,
+					jobs.submit(&self.endpoint.script, envelope, user.as_ref()).map(Accepted),
+				),
+				_ => handler::Outcome::Failure(Status::InternalServerError),
+			};
+		}
+
+		let runner = match req.guard::<State<Runner>>() {
+			rocket::Outcome::Success(runner) => runner,
+			_ => return handler::Outcome::Failure(Status::InternalServerError),
+		};
+
+		handler::Outcome::from(req, self.call(&envelope, &runner))
+	}
+}
+
+impl RgiHandler {
+	/// zavolá skript a zpracuje odpověď
+	fn call(&self, envelope: &Envelope, runner: &Runner) -> ApiResult<Value> {
+		let output = runner.run(&self.endpoint.script, envelope)?;
+		RgiResponse::parse(&output)?.into_result()
+	}
+
+	/// sestaví obálku z požadavku
+	fn envelope(&self, req: &Request, data: Data, user: Option<&User>) -> Result<Envelope, ApiError> {
+		let mut envelope = Envelope::new(&self.endpoint.verb);
+
+		for arg in &self.endpoint.args {
+			let value = match arg.source {
+				Source::Url => {
+					let index = self.params.iter().position(|p| p == &arg.name).expect("checked when loading");
+					match req.get_param::<String>(index) {
+						Some(Ok(raw)) => convert(arg, &raw)?,
+						_ => Err(ApiError::invalid(format!("invalid `{}`", arg.name)))?,
+					}
+				}
+				Source::Query => match req.get_query_value::<String>(&arg.name) {
+					Some(Ok(raw)) => convert(arg, &raw)?,
+					Some(Err(_)) => Err(ApiError::invalid(format!("invalid `{}`", arg.name)))?,
+					None => Value::Null,
+				},
+				Source::User => {
+					let user = user.expect("checked when loading");
+					match arg.field.as_ref().unwrap_or(&arg.name).as_str() {
+						"id" => Value::from(user.id),
+						"email" => Value::from(user.email.clone()),
+						"role" => Value::from(user.role.clone()),
+						_ => Value::from(user.name.clone()),
+					}
+				}
+			};
+
+			envelope = envelope.arg(&arg.name, &value);
+		}
+
+		if let Some(body) = self.body(data)? {
+			envelope = envelope.data(&body);
+		}
+		if let Some(user) = user {
+			envelope = envelope.caller(user);
+		}
+
+		Ok(envelope)
+	}
+
+	/// načte a zvaliduje tělo požadavku
+	fn body(&self, data: Data) -> Result<Option<Value>, ApiError> {
+		if self.endpoint.body == Body::None {
+			return Ok(None);
+		}
+
+		let mut raw = String::new();
+		data.open()
+			.take(BODY_LIMIT)
+			.read_to_string(&mut raw)
+			.map_err(|e| ApiError::new(Status::BadRequest, format!("can't read body: {}", e)))?;
+
+		let invalid = |e: serde_json::Error| ApiError::invalid(format!("invalid body: {}", e));
+		let body = match self.endpoint.body {
+			Body::NewReservation => serde_json::to_value(serde_json::from_str::<NewReservation>(&raw).map_err(invalid)?),
+			Body::UpdateReservation => {
+				serde_json::to_value(serde_json::from_str::<UpdateReservation>(&raw).map_err(invalid)?)
+			}
+			_ => serde_json::from_str::<Value>(&raw),
+		}
+		.map_err(invalid)?;
+
+		Ok(Some(body))
+	}
+}
+
+/// převede hodnotu z URL na typ argumentu
+fn convert(arg: &Arg, raw: &str) -> Result<Value, ApiError> {
+	let invalid = || ApiError::invalid(format!("`{}` must be {}", arg.name, format!("{:?}", arg.kind).to_lowercase()));
+
+	Ok(match arg.kind {
+		ArgType::String => Value::from(raw),
+		ArgType::Int => Value::from(raw.parse::<i64>().map_err(|_| invalid())?),
+		ArgType::Float => Value::from(raw.parse::<f64>().map_err(|_| invalid())?),
+		ArgType::Bool => Value::from(raw.parse::<bool>().map_err(|_| invalid())?),
+	})
+}
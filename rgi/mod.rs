@@ -6,60 +6,67 @@
 
 use rocket::Route;
 
+use self::manifest::Manifest;
+
 /// makro, které vygeneruje boilerplate pro volání daného rgi
 ///
 /// syntaxe (hranaté závorky značí, že parametr není povinný):
 /// ```no_run
 /// rgi! {
-///     HTTP_METODA "rgi/cesta/k/rgi/binarce"
+///     runner => HTTP_METODA "rgi/cesta/k/rgi/binarce"
 ///     [arg: identifikátor]* // argumentem se myslí parametr z URL
 ///     [data: <vyraz>]? // někdy je potřeba obalit do závorek ()
+///     [caller: <vyraz>]? // &User, kdo požadavek poslal
 /// }
 /// ```
 /// (všechny argumenty a data musí implementovat `serialize` ze `serde`)
 ///
+/// `runner` je [`runner::Runner`], typicky parametr endpointu `runner: State<Runner>`.
+/// RGI dostane na stdin [`envelope::Envelope`] jako jeden řádek JSONu, makro vrací
+/// `Result<String, RgiError>` se stdoutem skriptu, viz [`runner::Runner::run`],
+/// který se dál parsuje přes [`response::RgiResponse`]
+///
 /// příklad:
 /// rgi! {
-///     GET "rgi/lol/lol.py"
-///     arg: name
+///     runner => GET "rgi/lol/lol.py"
+///     arg: name,
 ///     arg: password
 ///     data: (Objekt)
+///     caller: (&usr.user)
 /// }
 #[macro_export]
 macro_rules! rgi {
-	{$method:ident $name:literal $(arg: $arg:ident),*  $(data: $data:tt)? } => {
+	{$runner:ident => $method:ident $name:literal $(arg: $arg:ident),* $(data: $data:tt)? $(caller: $caller:tt)? } => {
 		{
 			// we need this lint to avoid warnings caused by extra parens
 			// which are not extra since they help the parser
 			#![allow(unused_parens)]
-			#[allow(unused_imports)]
-			use std::process::{Command, Stdio};
-			#[allow(unused_imports)]
-			use std::io::{Read, Write};
-
-			let mut cmd = Command::new($name)
-				.stdin(Stdio::piped())
-				.stdout(Stdio::piped())
-				.arg(stringify!($method))
-				.spawn()
-				.expect("kinda gay");
 
-			if let Some(ref mut stdin) = &mut cmd.stdin {
-				let _ = writeln!(stdin, "{{");
-				let _ = writeln!(stdin, "\t\"args\": {{");
-				$(let _ = writeln!(stdin, "\t\t\"{}\": \"{}\",", stringify!($arg), ($arg).to_string());)*
-				let _ = writeln!(stdin, "\t}},");
-				$(let _ = writeln!(stdin, "\t\"data\": {}", serde_json::to_string($data).unwrap());)?
-				let _ = writeln!(stdin, "}}");
-			}
+			let envelope = $crate::rgi::envelope::Envelope::new(stringify!($method))
+				$(.arg(stringify!($arg), &$arg))*
+				$(.data($data))?
+				$(.caller($caller))?;
 
-			let cmd = cmd.wait_with_output().unwrap();
-
-			String::from_utf8_lossy(&cmd.stdout).to_string()
+			$runner.run($name, &envelope)
 		}
 	}
 }
 
+/// obálka požadavku pro RGI
+pub mod envelope;
+/// spouštění RGI procesů
+pub mod runner;
+/// perzistentní RGI workery
+pub mod pool;
+/// omezení RGI procesů
+pub mod sandbox;
+/// deklarativní RGI endpointy
+pub mod manifest;
+/// asynchronní RGI úlohy
+pub mod jobs;
+/// typované odpovědi RGI
+pub mod response;
+
 /// modul obsahující endpointy pro CRUD na rezervaci
 pub mod booking;
 
@@ -73,11 +80,14 @@ pub mod booking;
 /// // v routes..
 /// routes.extend(self::muj_modul::routes());
 /// ```
-pub fn routes() -> Vec<Route> {
+/// nebo bez Rustu přes manifest, viz [`manifest`]
+pub fn routes(manifest: &Manifest) -> Vec<Route> {
 	let mut routes = vec![];
 
 	/*routes() funkce volat tady*/
 	routes.extend(self::booking::routes());
+	routes.extend(self::jobs::routes());
+	routes.extend(manifest.routes());
 
 	routes
 }
@@ -0,0 +1,333 @@
+//! perzistentní RGI workery
+//!
+//! místo nového procesu pro každý request může RGI běžet jako několik dlouho
+//! žijících workerů (`skript --worker`). Worker čte ze stdinu požadavky, jeden
+//! řádek JSONu = jedna [`Envelope`] s korelačním `id`, a na každý odpoví řádkem
+//! ```json
+//! {"id": 7, "response": <to, co by skript jinak vypsal na stdout>}
+//! {"id": 7, "error": "popis chyby"}
+//! ```
+//! metoda `PING` slouží jako health check, odpověď může být cokoliv.
+//!
+//! zapíná se pro každé RGI zvlášť v Rocket.toml:
+//! ```toml
+//! [global.rgi_workers]
+//! "rgi/booking/booking.py" = { size = 4, max_requests = 1000, health_interval = 30 }
+//! ```
+//! - `size` -> nejvýše tolik workerů najednou (výchozí 2)
+//! - `max_requests` -> po tolika requestech se worker vymění za nový (výchozí 1000)
+//! - `health_interval` -> worker, který tolik sekund nic nedělal, se před použitím pingne (výchozí 30)
+//!
+//! worker, který spadne, nestihne odpovědět nebo neprojde health checkem, se zabije
+//! a při dalším requestu se spustí nový. Workery běží ve stejném sandboxu jako
+//! ostatní RGI, jen bez limitu času CPU (ten by platil na celý život workeru),
+//! limit výstupu platí pro každou odpověď zvlášť.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::config::Value;
+use serde::Deserialize;
+use wait_timeout::ChildExt;
+
+use super::envelope::Envelope;
+use super::runner::RgiError;
+use super::sandbox::Sandbox;
+
+/// nastavení poolu jednoho RGI
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+	/// maximální počet workerů
+	pub size:            usize,
+	/// po kolika requestech worker vyměnit
+	pub max_requests:    u64,
+	/// po jak dlouhé nečinnosti worker před použitím pingnout
+	pub health_interval: Duration,
+}
+
+impl Default for PoolConfig {
+	fn default() -> Self {
+		PoolConfig { size: 2, max_requests: 1000, health_interval: Duration::from_secs(30) }
+	}
+}
+
+impl PoolConfig {
+	/// načte nastavení z tabulky v Rocket.toml, chybějící hodnoty jsou výchozí
+	pub fn from_toml(script: &str, value: &Value) -> Result<Self, String> {
+		let table = value.as_table().ok_or_else(|| format!("workers of `{}` must be a table", script))?;
+		let mut config = PoolConfig::default();
+
+		let positive = |key: &str| -> Result<Option<u64>, String> {
+			match table.get(key) {
+				None => Ok(None),
+				Some(v) => v
+					.as_integer()
+					.filter(|v| *v > 0)
+					.map(|v| Some(v as u64))
+					.ok_or_else(|| format!("`{}` of `{}` workers must be a positive number", key, script)),
+			}
+		};
+
+		if let Some(size) = positive("size")? {
+			config.size = size as usize;
+		}
+		if let Some(max) = positive("max_requests")? {
+			config.max_requests = max;
+		}
+		if let Some(secs) = positive("health_interval")? {
+			config.health_interval = Duration::from_secs(secs);
+		}
+
+		Ok(config)
+	}
+}
+
+/// odpověď workeru
+#[derive(Deserialize)]
+struct Reply {
+	id:       u64,
+	#[serde(default)]
+	response: Option<serde_json::Value>,
+	#[serde(default)]
+	error:    Option<String>,
+}
+
+/// jeden běžící worker
+struct Worker {
+	child:      Child,
+	stdin:      ChildStdin,
+	/// řádky ze stdoutu, čte je samostatné vlákno
+	lines:      Receiver<String>,
+	served:     u64,
+	last_used:  Instant,
+	max_output: usize,
+}
+
+impl Worker {
+	/// spustí nový worker
+	fn spawn(script: &str, sandbox: &Sandbox) -> Result<Self, RgiError> {
+		let mut child = sandbox
+			.worker_command(script)
+			.arg("--worker")
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|e| RgiError::Spawn(format!("{}: {}", script, e)))?;
+
+		let stdin = child.stdin.take().expect("stdin is piped");
+		let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+		let stderr = BufReader::new(child.stderr.take().expect("stderr is piped"));
+
+		let (tx, lines) = mpsc::channel();
+		thread::spawn(move || {
+			for line in stdout.lines() {
+				match line {
+					Ok(line) if tx.send(line).is_ok() => (),
+					_ => break,
+				}
+			}
+		});
+
+		let name = script.to_string();
+		thread::spawn(move || {
+			for line in stderr.lines().filter_map(Result::ok).filter(|l| !l.trim().is_empty()) {
+				eprintln!("RGI worker {}: {}", name, line);
+			}
+		});
+
+		Ok(Worker { child, stdin, lines, served: 0, last_used: Instant::now(), max_output: sandbox.max_output })
+	}
+
+	/// pošle požadavek a počká na odpověď se stejným id
+	fn call(&mut self, envelope: &Envelope, timeout: Duration) -> Result<String, RgiError> {
+		let id = envelope.id.expect("pooled requests have an id");
+		writeln!(self.stdin, "{}", envelope.to_json())
+			.and_then(|_| self.stdin.flush())
+			.map_err(|e| RgiError::Io(e.to_string()))?;
+
+		let deadline = Instant::now() + timeout;
+		loop {
+			let left = deadline.checked_duration_since(Instant::now()).ok_or(RgiError::Timeout(timeout))?;
+			let line = match self.lines.recv_timeout(left) {
+				Ok(line) => line,
+				Err(RecvTimeoutError::Timeout) => return Err(RgiError::Timeout(timeout)),
+				Err(RecvTimeoutError::Disconnected) => return Err(self.exit_error()),
+			};
+
+			if line.len() > self.max_output {
+				return Err(RgiError::OutputTooLarge(self.max_output));
+			}
+
+			let reply = serde_json::from_str::<Reply>(&line)
+				.map_err(|e| RgiError::Io(format!("invalid reply `{}`: {}", line, e)))?;
+
+			// odpověď na jiný (starší) požadavek, ten už nikoho nezajímá
+			if reply.id != id {
+				continue;
+			}
+
+			self.served += 1;
+			self.last_used = Instant::now();
+
+			return match (reply.response, reply.error) {
+				(_, Some(e)) => Err(RgiError::Worker(e)),
+				(Some(response), None) => Ok(response.to_string()),
+				(None, None) => Err(RgiError::Worker("empty reply".to_string())),
+			};
+		}
+	}
+
+	/// chyba pro worker, který zavřel stdout
+	fn exit_error(&mut self) -> RgiError {
+		// stdout se zavírá těsně před koncem procesu, chvíli na něj počkáme
+		match self.child.wait_timeout(Duration::from_millis(500)) {
+			Ok(Some(status)) => RgiError::Failed(status.code()),
+			_ => RgiError::Io("worker closed its output".to_string()),
+		}
+	}
+
+	/// běží ještě?
+	fn alive(&mut self) -> bool {
+		match self.child.try_wait() {
+			Ok(None) => true,
+			_ => false,
+		}
+	}
+
+	/// zabije worker
+	fn kill(mut self) {
+		let _ = self.child.kill();
+		let _ = self.child.wait();
+	}
+}
+
+/// pool workerů jednoho RGI
+pub struct Pool {
+	script:  String,
+	config:  PoolConfig,
+	/// volní workeři a počet všech živých
+	state:   Mutex<(Vec<Worker>, usize)>,
+	freed:   Condvar,
+	next_id: AtomicU64,
+}
+
+impl Pool {
+	/// prázdný pool, workery spouští až při prvních requestech
+	pub fn new(script: &str, config: PoolConfig) -> Self {
+		Pool {
+			script: script.to_string(),
+			config,
+			state: Mutex::new((vec![], 0)),
+			freed: Condvar::new(),
+			next_id: AtomicU64::new(1),
+		}
+	}
+
+	/// nastavení poolu
+	pub fn config(&self) -> &PoolConfig {
+		&self.config
+	}
+
+	/// pošle obálku volnému workeru a vrátí jeho odpověď
+	pub fn run(&self, envelope: &Envelope, timeout: Duration, sandbox: &Sandbox) -> Result<String, RgiError> {
+		let mut envelope = envelope.clone();
+		envelope.id = Some(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+		let mut worker = self.checkout(timeout, sandbox)?;
+		let result = worker.call(&envelope, timeout);
+
+		match &result {
+			// chyba skriptu v rámci jednoho requestu worker nerozbije
+			Ok(_) | Err(RgiError::Worker(_)) => self.checkin(worker),
+			Err(e) => {
+				eprintln!("RGI worker {} {}: {}, restarting", self.script, envelope.method, e);
+				self.discard(worker);
+			}
+		}
+
+		result
+	}
+
+	/// vezme volný worker, případně spustí nový nebo počká, až se nějaký uvolní
+	fn checkout(&self, timeout: Duration, sandbox: &Sandbox) -> Result<Worker, RgiError> {
+		let deadline = Instant::now() + timeout;
+
+		loop {
+			let mut state = self.state.lock().expect("RGI pool poisoned");
+
+			if let Some(mut worker) = state.0.pop() {
+				// health check může chvíli trvat, ostatní kvůli němu čekat nemusí
+				drop(state);
+				if self.healthy(&mut worker) {
+					return Ok(worker);
+				}
+
+				eprintln!("RGI worker {} failed a health check, restarting", self.script);
+				self.discard(worker);
+				continue;
+			}
+
+			if state.1 < self.config.size {
+				state.1 += 1;
+				drop(state);
+
+				return Worker::spawn(&self.script, sandbox).map_err(|e| {
+					self.state.lock().expect("RGI pool poisoned").1 -= 1;
+					self.freed.notify_one();
+					e
+				});
+			}
+
+			let left = deadline.checked_duration_since(Instant::now()).ok_or(RgiError::Timeout(timeout))?;
+			let _ = self.freed.wait_timeout(state, left).expect("RGI pool poisoned");
+		}
+	}
+
+	/// vrátí worker do poolu, pokud už neobsloužil dost requestů
+	fn checkin(&self, worker: Worker) {
+		if worker.served >= self.config.max_requests {
+			self.discard(worker);
+			return;
+		}
+
+		self.state.lock().expect("RGI pool poisoned").0.push(worker);
+		self.freed.notify_one();
+	}
+
+	/// zabije worker a uvolní jeho místo
+	fn discard(&self, worker: Worker) {
+		worker.kill();
+		self.state.lock().expect("RGI pool poisoned").1 -= 1;
+		self.freed.notify_one();
+	}
+
+	/// zkontroluje, že worker běží, a pokud dlouho nic nedělal, pingne ho
+	fn healthy(&self, worker: &mut Worker) -> bool {
+		if !worker.alive() {
+			return false;
+		}
+		if worker.last_used.elapsed() < self.config.health_interval {
+			return true;
+		}
+
+		let mut ping = Envelope::new("PING");
+		ping.id = Some(self.next_id.fetch_add(1, Ordering::Relaxed));
+		worker.call(&ping, Duration::from_secs(5)).is_ok()
+	}
+}
+
+impl Drop for Pool {
+	fn drop(&mut self) {
+		if let Ok(mut state) = self.state.lock() {
+			for worker in state.0.drain(..) {
+				worker.kill();
+			}
+		}
+	}
+}
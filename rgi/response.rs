@@ -0,0 +1,136 @@
+//! typované odpovědi RGI
+//!
+//! RGI vrací buď přímo data (rezervaci, seznam...), nebo objekt s atributem `result`:
+//! - `result: 0` -> všechno fungovalo, zbytek objektu jsou data
+//! - `result: 1` -> nenašlo to rezervaci podle ID -> 404
+//! - `result: 2` -> už existuje rezervace ve stejném čase a místnosti -> 409,
+//!   id kolidujících rezervací jsou v `conflicts`
+//! - `result: 3` -> rezervace je ve stavu, ze kterého akce nevede -> 409,
+//!   současný stav je v `status`
+//!
+//! ```no_run
+//! let output = rgi! { runner => GET "rgi/booking/booking.py" arg: id }?;
+//! RgiResponse::<Reservation>::parse(&output)?.into_result()
+//! ```
+
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{ApiError, ApiResult};
+
+/// výsledek volání RGI podle tabulky `result`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RgiResponse<T> {
+	/// všechno fungovalo
+	Ok(T),
+	/// nenašlo to rezervaci podle ID
+	NotFound,
+	/// kolize s jinými rezervacemi (jejich id)
+	Conflict(Vec<i32>),
+	/// nepovolený přechod ze současného stavu
+	WrongState(String),
+}
+
+/// odpověď RGI, které jen něco provedlo, např. `{"result": 0, "id": 3}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Done {
+	/// vždy 0
+	pub result: i32,
+	/// id vytvořeného záznamu
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub id:     Option<i32>,
+}
+
+/// seznam výsledků ve tvaru `{"results": [...]}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Results<T> {
+	/// výsledky
+	pub results: Vec<T>,
+}
+
+/// jedna stránka výsledků, viz [`crate::rgi::booking::query`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T> {
+	/// výsledky na stránce
+	pub results: Vec<T>,
+	/// počet všech výsledků napříč stránkami
+	pub total:   i64,
+	/// číslo stránky (od 1)
+	pub page:    i64,
+	/// výsledků na stránku
+	pub limit:   i64,
+	/// odkaz na další stránku, pokud je
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub next:    Option<String>,
+	/// odkaz na předchozí stránku, pokud je
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub prev:    Option<String>,
+}
+
+/// chybový objekt s kódem `result`
+#[derive(Deserialize)]
+struct Failure {
+	result:    i64,
+	#[serde(default)]
+	conflicts: Vec<i32>,
+	#[serde(default)]
+	status:    String,
+}
+
+impl<T: DeserializeOwned> RgiResponse<T> {
+	/// načte stdout RGI, nevalidní výstup je 502
+	pub fn parse(output: &str) -> Result<Self, ApiError> {
+		let value = serde_json::from_str::<Value>(output)
+			.map_err(|e| bad_gateway(format!("RGI returned invalid JSON: {}", e)))?;
+
+		if let Some(result) = value.get("result").and_then(Value::as_i64) {
+			if result != 0 {
+				let failure = serde_json::from_value::<Failure>(value)
+					.map_err(|e| bad_gateway(format!("RGI returned an invalid error: {}", e)))?;
+
+				return match failure.result {
+					1 => Ok(RgiResponse::NotFound),
+					2 => Ok(RgiResponse::Conflict(failure.conflicts)),
+					3 => Ok(RgiResponse::WrongState(failure.status)),
+					code => Err(bad_gateway(format!("RGI returned unknown result {}", code))),
+				};
+			}
+		}
+
+		serde_json::from_value(value)
+			.map(RgiResponse::Ok)
+			.map_err(|e| bad_gateway(format!("RGI returned unexpected data: {}", e)))
+	}
+}
+
+impl<T> RgiResponse<T> {
+	/// převede výsledek na odpověď endpointu
+	pub fn into_result(self) -> ApiResult<T> {
+		match self {
+			RgiResponse::Ok(data) => Ok(Json(data)),
+			RgiResponse::NotFound => Err(ApiError::not_found("no such event")),
+			RgiResponse::Conflict(ids) => Err(conflict(ids)),
+			RgiResponse::WrongState(status) => Err(wrong_state(&status)),
+		}
+	}
+}
+
+/// 409 pro kolizi s rezervacemi `ids`
+pub fn conflict(ids: Vec<i32>) -> ApiError {
+	ApiError::new(Status::Conflict, "there already is a reservation in the same room at the same time")
+		.with("conflicts", ids)
+}
+
+/// 409 pro rezervaci ve stavu `status`, ze kterého akce nevede
+pub fn wrong_state(status: &str) -> ApiError {
+	ApiError::new(Status::Conflict, format!("event is {}", status)).with("status", status)
+}
+
+/// chyba na straně RGI
+fn bad_gateway(message: String) -> ApiError {
+	eprintln!("{}", message);
+	ApiError::new(Status::BadGateway, message)
+}
@@ -0,0 +1,292 @@
+//! spouštění RGI procesů
+//!
+//! [`Runner`] spustí skript, pošle mu [`Envelope`] na stdin a počká na výsledek,
+//! nejdéle však timeout daného endpointu, pak zabije skript i procesy, které
+//! spustil. Procesy, které skript nechal běžet na pozadí, se zabijí i po jeho
+//! řádném skončení, jinak by držely roury a request by nikdy neskončil. Stderr
+//! skriptu jde do logu serveru, nenulový exit code je chyba.
+//!
+//! timeouty (v sekundách) se nastavují v Rocket.toml:
+//! ```toml
+//! rgi_timeout = 10
+//! # konkrétní skript nebo skript + metoda
+//! rgi_timeouts = { "rgi/booking/booking.py FILTER" = 30 }
+//! ```
+//!
+//! RGI s nastavenými workery (`rgi_workers`, viz [`super::pool`]) se místo
+//! spouštění nového procesu pošlou do poolu. Všechny procesy běží omezené,
+//! viz [`super::sandbox`]. Testy můžou skript nahradit Rust funkcí, viz
+//! [`Runner::with_stub`].
+//!
+//! chyby se klientovi vrací jako JSON, viz [`RgiError`]:
+//! - skript nejde spustit, spadl, vrátil nenulový exit code nebo moc dlouhý výstup -> 502
+//! - skript nestihl odpovědět -> 504
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::config::Config;
+use rocket::http::Status;
+use wait_timeout::ChildExt;
+
+use super::envelope::Envelope;
+use super::pool::{Pool, PoolConfig};
+use super::sandbox::Sandbox;
+use crate::error::ApiError;
+
+/// náhrada skriptu: dostane obálku a vrátí to, co by skript vypsal na stdout
+pub type Stub = Arc<dyn Fn(&Envelope) -> Result<String, RgiError> + Send + Sync>;
+
+/// výchozí timeout, pokud není v konfiguraci
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// proč volání RGI selhalo
+#[derive(Debug, Clone, PartialEq)]
+pub enum RgiError {
+	/// proces nejde spustit (chybí skript, práva...)
+	Spawn(String),
+	/// chyba při komunikaci s procesem
+	Io(String),
+	/// proces nestihl doběhnout a byl zabit
+	Timeout(Duration),
+	/// proces skončil nenulovým exit code (`None` = zabit signálem)
+	Failed(Option<i32>),
+	/// perzistentní worker ohlásil chybu, viz [`super::pool`]
+	Worker(String),
+	/// výstup byl delší než povolený počet bajtů
+	OutputTooLarge(usize),
+}
+
+impl fmt::Display for RgiError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RgiError::Spawn(e) => write!(f, "can't start RGI: {}", e),
+			RgiError::Io(e) => write!(f, "can't talk to RGI: {}", e),
+			RgiError::Timeout(t) => write!(f, "RGI timed out after {}s", t.as_secs_f32()),
+			RgiError::Failed(Some(code)) => write!(f, "RGI exited with code {}", code),
+			RgiError::Failed(None) => write!(f, "RGI was killed by a signal"),
+			RgiError::Worker(e) => write!(f, "RGI worker failed: {}", e),
+			RgiError::OutputTooLarge(limit) => write!(f, "RGI output exceeded {} bytes", limit),
+		}
+	}
+}
+
+impl From<RgiError> for ApiError {
+	fn from(e: RgiError) -> Self {
+		match e {
+			RgiError::Timeout(_) => ApiError::new(Status::GatewayTimeout, e.to_string()),
+			e => ApiError::new(Status::BadGateway, e.to_string()),
+		}
+	}
+}
+
+/// spouštěč RGI s nastavenými timeouty
+///
+/// klony sdílí pooly workerů
+#[derive(Clone)]
+pub struct Runner {
+	default_timeout: Duration,
+	/// `"skript"` nebo `"skript METODA"` -> timeout
+	timeouts:        HashMap<String, Duration>,
+	/// skript -> pool perzistentních workerů
+	pools:           HashMap<String, Arc<Pool>>,
+	/// omezení procesů
+	sandbox:         Arc<Sandbox>,
+	/// skript -> funkce, která se zavolá místo něj
+	stubs:           HashMap<String, Stub>,
+}
+
+impl Runner {
+	/// spouštěč s jedním timeoutem pro všechno
+	pub fn new(default_timeout: Duration) -> Self {
+		Runner {
+			default_timeout,
+			timeouts: HashMap::new(),
+			pools: HashMap::new(),
+			sandbox: Arc::new(Sandbox::default()),
+			stubs: HashMap::new(),
+		}
+	}
+
+	/// nastaví timeout pro skript (`"rgi/x.py"`) nebo jednu jeho metodu (`"rgi/x.py GET"`)
+	pub fn with_timeout(mut self, endpoint: &str, timeout: Duration) -> Self {
+		self.timeouts.insert(endpoint.to_string(), timeout);
+		self
+	}
+
+	/// nastaví omezení procesů
+	pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+		self.sandbox = Arc::new(sandbox);
+		self
+	}
+
+	/// skript poběží jako pool perzistentních workerů
+	pub fn with_workers(mut self, script: &str, config: PoolConfig) -> Self {
+		self.pools.insert(script.to_string(), Arc::new(Pool::new(script, config)));
+		self
+	}
+
+	/// místo skriptu se zavolá `stub` (proces se nespouští), hlavně pro testy
+	pub fn with_stub<F>(mut self, script: &str, stub: F) -> Self
+	where
+		F: Fn(&Envelope) -> Result<String, RgiError> + Send + Sync + 'static,
+	{
+		self.stubs.insert(script.to_string(), Arc::new(stub));
+		self
+	}
+
+	/// načte `rgi_timeout`, `rgi_timeouts`, `rgi_workers` a `rgi_sandbox` z konfigurace Rocketu
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let default = config.get_int("rgi_timeout").unwrap_or(DEFAULT_TIMEOUT as i64);
+		if default <= 0 {
+			Err("rgi_timeout must be positive".to_string())?
+		}
+
+		let mut runner = Runner::new(Duration::from_secs(default as u64)).with_sandbox(Sandbox::from_config(config)?);
+		if let Ok(table) = config.get_table("rgi_timeouts") {
+			for (endpoint, secs) in table {
+				let secs = secs
+					.as_integer()
+					.filter(|s| *s > 0)
+					.ok_or_else(|| format!("timeout of `{}` must be a positive number of seconds", endpoint))?;
+				runner = runner.with_timeout(endpoint, Duration::from_secs(secs as u64));
+			}
+		}
+
+		if let Ok(table) = config.get_table("rgi_workers") {
+			for (script, workers) in table {
+				runner = runner.with_workers(script, PoolConfig::from_toml(script, workers)?);
+			}
+		}
+
+		Ok(runner)
+	}
+
+	/// timeout pro danou metodu skriptu
+	pub fn timeout(&self, script: &str, method: &str) -> Duration {
+		self.timeouts
+			.get(&format!("{} {}", script, method))
+			.or_else(|| self.timeouts.get(script))
+			.cloned()
+			.unwrap_or(self.default_timeout)
+	}
+
+	/// spustí skript s obálkou na stdinu (nebo ji pošle workeru) a vrátí jeho stdout
+	pub fn run(&self, script: &str, envelope: &Envelope) -> Result<String, RgiError> {
+		if let Some(stub) = self.stubs.get(script) {
+			return stub(envelope);
+		}
+
+		let timeout = self.timeout(script, &envelope.method);
+		if let Some(pool) = self.pools.get(script) {
+			return pool.run(envelope, timeout, &self.sandbox);
+		}
+
+		let started = Instant::now();
+
+		let mut command = self.sandbox.command(script);
+		// vlastní skupina procesů, aby šly při timeoutu zabít i procesy, které skript spustil
+		unsafe {
+			command.pre_exec(|| if libc::setsid() < 0 { Err(io::Error::last_os_error()) } else { Ok(()) });
+		}
+		let mut child = command
+			.arg(&envelope.method)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|e| RgiError::Spawn(format!("{}: {}", script, e)))?;
+
+		// stdin, stdout i stderr obsluhujeme zvlášť, jinak se při plné rouře zasekneme
+		let mut stdin = child.stdin.take().expect("stdin is piped");
+		let input = envelope.to_json();
+		let writer = thread::spawn(move || writeln!(stdin, "{}", input));
+		let limit = self.sandbox.max_output;
+		let stdout = drain(child.stdout.take().expect("stdout is piped"), limit);
+		let stderr = drain(child.stderr.take().expect("stderr is piped"), limit);
+
+		let status = match child.wait_timeout(timeout) {
+			Ok(Some(status)) => status,
+			Ok(None) => return Err(abandon(script, &envelope.method, child, RgiError::Timeout(timeout), started)),
+			Err(e) => return Err(abandon(script, &envelope.method, child, RgiError::Io(e.to_string()), started)),
+		};
+		// potomci na pozadí by drželi roury otevřené a čtení výstupu by nikdy neskončilo
+		kill_group(&child);
+
+		// skript nemusí stdin vůbec číst, to chyba není
+		let _ = writer.join();
+		let (stdout, truncated) = stdout.join().unwrap_or_default();
+		let (stderr, _) = stderr.join().unwrap_or_default();
+
+		log_stderr(script, &envelope.method, &stderr);
+		check(status)
+			.and_then(|_| if truncated { Err(RgiError::OutputTooLarge(limit)) } else { Ok(()) })
+			.map_err(|e| {
+				eprintln!("RGI {} {}: {}", script, envelope.method, e);
+				e
+			})?;
+
+		Ok(String::from_utf8_lossy(&stdout).to_string())
+	}
+}
+
+/// zabije celou skupinu procesů skriptu a vrátí chybu
+///
+/// na vlákna čtoucí výstup se nečeká, roury může pořád držet otevřené proces,
+/// který z naší skupiny utekl. Vlákna skončí sama, až se roury zavřou.
+fn abandon(script: &str, method: &str, mut child: Child, error: RgiError, started: Instant) -> RgiError {
+	kill_group(&child);
+	let _ = child.kill();
+	let _ = child.wait();
+
+	eprintln!("RGI {} {}: {} ({:?})", script, method, error, started.elapsed());
+	error
+}
+
+/// zabije skupinu procesů skriptu (skript je jejím vůdcem, viz `setsid` v [`Runner::run`])
+fn kill_group(child: &Child) {
+	unsafe {
+		libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+	}
+}
+
+/// přečte celou rouru ve vlastním vlákně, nechá si nejvýše `limit` bajtů
+///
+/// vrací i to, jestli se něco zahodilo. Zbytek se čte dál, aby se skript nezasekl na plné rouře.
+fn drain<R: Read + Send + 'static>(pipe: R, limit: usize) -> thread::JoinHandle<(Vec<u8>, bool)> {
+	thread::spawn(move || {
+		let mut buf = vec![];
+		let mut pipe = pipe.take(limit as u64 + 1);
+		let _ = pipe.read_to_end(&mut buf);
+
+		let truncated = buf.len() > limit;
+		if truncated {
+			buf.truncate(limit);
+			let _ = io::copy(&mut pipe.into_inner(), &mut io::sink());
+		}
+
+		(buf, truncated)
+	})
+}
+
+/// převede exit status na výsledek
+fn check(status: ExitStatus) -> Result<(), RgiError> {
+	if status.success() {
+		Ok(())
+	} else {
+		Err(RgiError::Failed(status.code()))
+	}
+}
+
+/// vypíše stderr skriptu do logu serveru, řádek po řádku
+fn log_stderr(script: &str, method: &str, stderr: &[u8]) {
+	for line in String::from_utf8_lossy(stderr).lines().filter(|l| !l.trim().is_empty()) {
+		eprintln!("RGI {} {}: {}", script, method, line);
+	}
+}
@@ -0,0 +1,185 @@
+//! omezení RGI procesů
+//!
+//! každý RGI proces (i perzistentní worker) se spouští:
+//! - s prázdným prostředím, projdou jen proměnné ze seznamu `env`
+//! - v pracovním adresáři `cwd`
+//! - s limity na čas CPU, paměť a počet otevřených souborů
+//! - volitelně pod jiným uživatelem (`uid`, `gid`)
+//!
+//! limit času CPU platí na celý život procesu, proto ho perzistentní workery
+//! nedostávají, jinak by po čase umřely uprostřed requestu. Hlídá je timeout
+//! každého požadavku, viz [`super::pool`].
+//!
+//! výstup delší než `max_output` bajtů je chyba (502).
+//!
+//! nastavení v Rocket.toml:
+//! ```toml
+//! [global.rgi_sandbox]
+//! env = ["PATH", "LANG"]
+//! cwd = "."
+//! cpu = 10             # sekund
+//! memory = 512         # MiB
+//! open_files = 64
+//! max_output = 1048576 # bajtů
+//! # uid = 1000
+//! # gid = 1000
+//! ```
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rocket::config::Config;
+
+/// proměnné prostředí, které projdou vždy, když nejsou nastavené jinak
+const DEFAULT_ENV: &[&str] = &["PATH", "LANG", "LC_ALL", "TZ"];
+
+/// omezení pro RGI procesy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandbox {
+	/// jména proměnných prostředí, které se předají skriptu
+	pub env:        Vec<String>,
+	/// pracovní adresář, `None` = adresář serveru
+	pub cwd:        Option<PathBuf>,
+	/// limit času CPU v sekundách, jen pro jednorázové procesy
+	pub cpu:        Option<u64>,
+	/// limit adresního prostoru v bajtech
+	pub memory:     Option<u64>,
+	/// limit počtu otevřených souborů
+	pub open_files: Option<u64>,
+	/// nejdelší povolený výstup v bajtech
+	pub max_output: usize,
+	/// uživatel, pod kterým skript poběží
+	pub uid:        Option<u32>,
+	/// skupina, pod kterou skript poběží
+	pub gid:        Option<u32>,
+}
+
+impl Default for Sandbox {
+	fn default() -> Self {
+		Sandbox {
+			env:        DEFAULT_ENV.iter().map(|v| v.to_string()).collect(),
+			cwd:        None,
+			cpu:        Some(10),
+			memory:     Some(512 << 20),
+			open_files: Some(64),
+			max_output: 1 << 20,
+			uid:        None,
+			gid:        None,
+		}
+	}
+}
+
+impl Sandbox {
+	/// přidá proměnnou prostředí, která se předá skriptu
+	pub fn pass_env(mut self, name: &str) -> Self {
+		self.env.push(name.to_string());
+		self
+	}
+
+	/// načte `rgi_sandbox` z konfigurace Rocketu, chybějící hodnoty jsou výchozí
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let mut sandbox = Sandbox::default();
+		let table = match config.get_table("rgi_sandbox") {
+			Ok(table) => table,
+			Err(_) => return Ok(sandbox),
+		};
+
+		let positive = |key: &str| -> Result<Option<u64>, String> {
+			match table.get(key) {
+				None => Ok(None),
+				Some(v) => v
+					.as_integer()
+					.filter(|v| *v > 0)
+					.map(|v| Some(v as u64))
+					.ok_or_else(|| format!("rgi_sandbox.{} must be a positive number", key)),
+			}
+		};
+
+		if let Some(env) = table.get("env") {
+			sandbox.env = env
+				.as_array()
+				.and_then(|vars| vars.iter().map(|v| v.as_str().map(String::from)).collect::<Option<Vec<_>>>())
+				.ok_or_else(|| "rgi_sandbox.env must be a list of variable names".to_string())?;
+		}
+		if let Some(cwd) = table.get("cwd") {
+			let cwd = cwd.as_str().ok_or_else(|| "rgi_sandbox.cwd must be a path".to_string())?;
+			sandbox.cwd = Some(fs::canonicalize(cwd).map_err(|e| format!("rgi_sandbox.cwd `{}`: {}", cwd, e))?);
+		}
+		if let Some(cpu) = positive("cpu")? {
+			sandbox.cpu = Some(cpu);
+		}
+		if let Some(memory) = positive("memory")? {
+			sandbox.memory = Some(memory << 20);
+		}
+		if let Some(files) = positive("open_files")? {
+			sandbox.open_files = Some(files);
+		}
+		if let Some(max) = positive("max_output")? {
+			sandbox.max_output = max as usize;
+		}
+		sandbox.uid = positive("uid")?.map(|u| u as u32);
+		sandbox.gid = positive("gid")?.map(|g| g as u32);
+
+		Ok(sandbox)
+	}
+
+	/// připraví příkaz pro spuštění skriptu v sandboxu
+	pub fn command(&self, script: &str) -> Command {
+		self.limited(script, self.cpu)
+	}
+
+	/// jako [`Sandbox::command`], ale bez limitu času CPU, pro perzistentní workery
+	pub fn worker_command(&self, script: &str) -> Command {
+		self.limited(script, None)
+	}
+
+	/// příkaz v sandboxu s daným limitem času CPU
+	fn limited(&self, script: &str, cpu: Option<u64>) -> Command {
+		// relativní cesta by se po změně adresáře hledala jinde
+		let program = fs::canonicalize(script).unwrap_or_else(|_| PathBuf::from(script));
+		let mut cmd = Command::new(program);
+
+		cmd.env_clear();
+		for name in &self.env {
+			if let Some(value) = env::var_os(name) {
+				cmd.env(name, value);
+			}
+		}
+
+		if let Some(cwd) = &self.cwd {
+			cmd.current_dir(cwd);
+		}
+		if let Some(gid) = self.gid {
+			cmd.gid(gid);
+		}
+		if let Some(uid) = self.uid {
+			cmd.uid(uid);
+		}
+
+		let limits = [
+			(libc::RLIMIT_CPU, cpu),
+			(libc::RLIMIT_AS, self.memory),
+			(libc::RLIMIT_NOFILE, self.open_files),
+		];
+		// běží v potomkovi mezi fork a exec, smí volat jen async-signal-safe funkce
+		unsafe {
+			cmd.pre_exec(move || {
+				for (resource, limit) in limits.iter() {
+					if let Some(limit) = limit {
+						let rlim = libc::rlimit { rlim_cur: *limit as libc::rlim_t, rlim_max: *limit as libc::rlim_t };
+						if libc::setrlimit(*resource, &rlim) != 0 {
+							return Err(io::Error::last_os_error());
+						}
+					}
+				}
+				Ok(())
+			});
+		}
+
+		cmd
+	}
+}
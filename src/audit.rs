@@ -0,0 +1,243 @@
+//! auditní log všech změn
+//!
+//! každá změna (vytvoření, úprava, smazání a schválení rezervace, změny uživatelů,
+//! pozvánek, API klíčů a rozložení) se zapíše do tabulky `audit_log` i se stavem před a po
+//! a s id requestu (hlavička `X-Request-Id`, případně vygenerované).
+//!
+//! zápis ze endpointu:
+//! ```no_run
+//! audit::record(&usr.user, "booking.delete", Target::Booking(id), audit::snapshot(&before), None, &req_id);
+//! ```
+//!
+//! - `GET /audit?<filtr>` -> výpis logu, jen s oprávněním [`AuditView`], viz [`AuditFilter`]
+
+use std::collections::BTreeSet;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use rand::Rng;
+use rocket::request::{self, Form, FromRequest, Request};
+use rocket::response::Response;
+use rocket::Route;
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::auth::permissions::actions::AuditView;
+use crate::auth::permissions::Permitted;
+use crate::db;
+use crate::db::{AuditEntry, NewAuditEntry, User};
+use crate::error::{ApiError, ApiResult};
+
+/// hlavička s id requestu
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// id requestu, převzaté z hlavičky `X-Request-Id` nebo vygenerované
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+	/// vrací id daného requestu (při prvním volání ho určí)
+	pub fn of(request: &Request) -> RequestId {
+		request
+			.local_cache(|| {
+				let id = request
+					.headers()
+					.get_one(REQUEST_ID_HEADER)
+					.map(|id| id.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').take(64).collect::<String>())
+					.filter(|id| !id.is_empty());
+
+				RequestId(id.unwrap_or_else(|| {
+					let mut rng = rand::thread_rng();
+					(0..8).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+				}))
+			})
+			.clone()
+	}
+
+	/// callback pro `AdHoc::on_response`, vrací id requestu klientovi
+	pub fn echo(request: &Request, response: &mut Response) {
+		response.set_raw_header(REQUEST_ID_HEADER, RequestId::of(request).0);
+	}
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestId {
+	type Error = ();
+
+	fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+		request::Outcome::Success(RequestId::of(request))
+	}
+}
+
+/// čeho se záznam týká
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+	/// rezervace
+	Booking(i32),
+	/// uživatel
+	User(i32),
+	/// pozvánka
+	Invitation(i32),
+	/// API klíč
+	ApiKey(i32),
+	/// série rezervací
+	Series(i32),
+	/// rozložení nábytku
+	Layout(i32),
+}
+
+impl Target {
+	/// druh cíle, tak jak se ukládá
+	pub fn kind(self) -> &'static str {
+		match self {
+			Target::Booking(_) => "booking",
+			Target::User(_) => "user",
+			Target::Invitation(_) => "invitation",
+			Target::ApiKey(_) => "apikey",
+			Target::Series(_) => "series",
+			Target::Layout(_) => "layout",
+		}
+	}
+
+	/// id cíle
+	pub fn id(self) -> i32 {
+		match self {
+			Target::Booking(id)
+			| Target::User(id)
+			| Target::Invitation(id)
+			| Target::ApiKey(id)
+			| Target::Series(id)
+			| Target::Layout(id) => id,
+		}
+	}
+}
+
+/// převede stav objektu na JSON pro [`record`]
+pub fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+	serde_json::to_value(value).ok()
+}
+
+/// zapíše změnu do auditního logu
+///
+/// selhání zápisu se jen zaloguje, request kvůli tomu neselže
+pub fn record(
+	actor: &User,
+	action: &str,
+	target: Target,
+	before: Option<Value>,
+	after: Option<Value>,
+	request_id: &RequestId,
+) {
+	use crate::schema::audit_log::dsl::audit_log;
+
+	let changes = diff(before.as_ref(), after.as_ref());
+
+	let entry = NewAuditEntry {
+		actor:       actor.email.clone(),
+		action:      action.to_string(),
+		target_type: target.kind().to_string(),
+		target_id:   Some(target.id()),
+		before:      before.map(|b| b.to_string()),
+		after:       after.map(|a| a.to_string()),
+		diff:        changes.map(|d| d.to_string()),
+		request_id:  request_id.0.clone(),
+	};
+
+	if let Err(e) = diesel::insert_into(audit_log).values(&entry).execute(&db::get_con()) {
+		eprintln!("failed to write audit log entry {:?}: {}", entry, e);
+	}
+}
+
+/// rozdíl dvou JSON objektů ve tvaru `{"pole": [před, po]}`
+///
+/// pro vytvoření a smazání (chybí jedna strana) vrací `None`
+pub fn diff(before: Option<&Value>, after: Option<&Value>) -> Option<Value> {
+	let (before, after) = match (before, after) {
+		(Some(Value::Object(b)), Some(Value::Object(a))) => (b, a),
+		_ => return None,
+	};
+
+	let mut changes = Map::new();
+	for key in before.keys().chain(after.keys()).collect::<BTreeSet<_>>() {
+		let old = before.get(key).unwrap_or(&Value::Null);
+		let new = after.get(key).unwrap_or(&Value::Null);
+
+		if old != new {
+			changes.insert(key.clone(), Value::Array(vec![old.clone(), new.clone()]));
+		}
+	}
+
+	Some(Value::Object(changes))
+}
+
+/// filtr pro výpis auditního logu, všechny parametry jsou nepovinné
+#[derive(FromForm, Debug)]
+pub struct AuditFilter {
+	/// email aktéra
+	pub actor: Option<String>,
+	/// akce, např. `booking.approve`
+	pub action: Option<String>,
+	/// druh cíle (`booking`, `series`, `layout`, `user`, `invitation`, `apikey`)
+	pub target_type: Option<String>,
+	/// id cíle
+	pub target_id: Option<i32>,
+	/// id requestu
+	pub request_id: Option<String>,
+	/// od (UTC, `2019-12-01T00:00:00`)
+	pub from: Option<String>,
+	/// do (UTC)
+	pub to: Option<String>,
+	/// maximální počet záznamů (výchozí 100, nejvýše 1000)
+	pub limit: Option<i64>,
+	/// kolik záznamů přeskočit
+	pub offset: Option<i64>,
+}
+
+/// vrací záznamy auditního logu od nejnovějších
+///
+/// GET /audit?<filtr> application/json
+#[get("/audit?<filter..>")]
+pub fn list(filter: Form<AuditFilter>, _u: Permitted<AuditView>) -> ApiResult<Vec<AuditEntry>> {
+	use crate::schema::audit_log::dsl::*;
+
+	let filter = filter.into_inner();
+	let mut query = audit_log.into_boxed();
+
+	if let Some(a) = filter.actor {
+		query = query.filter(actor.eq(a));
+	}
+	if let Some(a) = filter.action {
+		query = query.filter(action.eq(a));
+	}
+	if let Some(t) = filter.target_type {
+		query = query.filter(target_type.eq(t));
+	}
+	if let Some(t) = filter.target_id {
+		query = query.filter(target_id.eq(t));
+	}
+	if let Some(r) = filter.request_id {
+		query = query.filter(request_id.eq(r));
+	}
+	if let Some(from) = filter.from {
+		query = query.filter(created.ge(parse_time(&from)?));
+	}
+	if let Some(to) = filter.to {
+		query = query.filter(created.le(parse_time(&to)?));
+	}
+
+	let limit = filter.limit.unwrap_or(100).max(0).min(1000);
+	let offset = filter.offset.unwrap_or(0).max(0);
+
+	let con = db::get_con();
+	Ok(Json(query.order(id.desc()).limit(limit).offset(offset).load::<AuditEntry>(&con)?))
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list]
+}
+
+/// načte čas z parametru
+fn parse_time(raw: &str) -> Result<NaiveDateTime, ApiError> {
+	raw.parse::<NaiveDateTime>().map_err(|_| ApiError::invalid(format!("`{}` is not a valid time", raw)))
+}
@@ -0,0 +1,221 @@
+//! API klíče pro servisní účty (cedule, skripty, plánované úlohy)
+//!
+//! klient posílá hlavičku `Authorization: ApiKey <klíč>`. Klíč má tvar
+//! `<prefix>.<tajemství>`, v databázi je uložen jen prefix a SHA-256 tajemství.
+//!
+//! klíč se chová jako uživatel s rolí `noob`, akce z matice oprávnění ale smí jen
+//! ty, které mu dávají jeho scope (nikdy ne celou roli):
+//! - `events:read` -> žádné, jen čtení (GET/HEAD)
+//! - `approve` -> `booking.approve`
+//! - `admin` -> `user.manage`, `audit.view`, `job.view_any`, `layout.manage`
+//!
+//! API klíčem nejde spravovat API klíče, ani se scope `admin`.
+//!
+//! správa klíčů (oprávnění [`ApiKeyManage`]):
+//! - `GET /apikeys` -> seznam klíčů
+//! - `POST /apikeys` -> nový klíč, data: [`ApiKeyRequest`], tajemství se vrací jen jednou
+//! - `DELETE /apikeys/<id>` -> zneplatnění klíče
+
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::Rng;
+use rocket::Route;
+use rocket_contrib::json::{Json, JsonValue};
+use sha2::{Digest, Sha256};
+
+use super::permissions::actions::{Action, ApiKeyManage, AuditView, BookingApprove, JobViewAny, LayoutManage, UserManage};
+use super::permissions::Permitted;
+use super::roles::{Noob, Role};
+use crate::audit::{self, RequestId, Target};
+use crate::db;
+use crate::db::{ApiKey, ApiKeyRequest, NewApiKey, User};
+use crate::error::{ApiError, ApiResult};
+
+/// oprávnění API klíče
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+	/// čtení událostí
+	EventsRead,
+	/// schvalování
+	Approve,
+	/// správa
+	Admin,
+}
+
+impl Scope {
+	/// jméno scope, tak jak se ukládá
+	pub fn name(self) -> &'static str {
+		match self {
+			Scope::EventsRead => "events:read",
+			Scope::Approve => "approve",
+			Scope::Admin => "admin",
+		}
+	}
+
+	/// najde scope podle jména
+	pub fn parse(name: &str) -> Option<Scope> {
+		[Scope::EventsRead, Scope::Approve, Scope::Admin].iter().cloned().find(|s| s.name() == name.trim())
+	}
+
+	/// akce z matice oprávnění, které scope povoluje
+	pub fn actions(self) -> Vec<&'static str> {
+		match self {
+			Scope::EventsRead => vec![],
+			Scope::Approve => vec![BookingApprove::name()],
+			Scope::Admin => vec![UserManage::name(), AuditView::name(), JobViewAny::name(), LayoutManage::name()],
+		}
+	}
+
+	/// smí klíč s danými scope provést akci? správa klíčů se API klíčem nedělá nikdy
+	pub fn allow(scopes: &[Scope], action: &str) -> bool {
+		action != ApiKeyManage::name() && scopes.iter().any(|s| s.actions().contains(&action))
+	}
+}
+
+/// identita servisního účtu po ověření klíče
+pub struct ServiceAccount {
+	/// uživatel sestrojený z klíče (záporné id, email `apikey:<prefix>`, role `noob`)
+	pub user:      User,
+	/// scope klíče
+	pub scopes:    Vec<Scope>,
+	/// klíč smí jen číst
+	pub read_only: bool,
+}
+
+/// ověří klíč z hlavičky a zaznamená jeho použití
+pub fn authenticate(key: &str) -> Result<ServiceAccount, String> {
+	use crate::schema::api_keys::dsl::*;
+
+	let mut parts = key.splitn(2, '.');
+	let (key_prefix, secret) = match (parts.next(), parts.next()) {
+		(Some(p), Some(s)) => (p, s),
+		_ => return Err("malformed API key".to_string()),
+	};
+
+	let con = db::get_con();
+	let found = api_keys
+		.filter(prefix.eq(key_prefix))
+		.first::<ApiKey>(&con)
+		.optional()
+		.expect("failed to connect to db")
+		.filter(|k| constant_time_eq(k.secret_hash.as_bytes(), hash(secret).as_bytes()))
+		.ok_or_else(|| "invalid API key".to_string())?;
+
+	if found.revoked != 0 {
+		Err("API key has been revoked".to_string())?
+	}
+
+	let now = Utc::now().naive_utc();
+	if found.expires.map(|e| e <= now).unwrap_or(false) {
+		Err("API key has expired".to_string())?
+	}
+
+	diesel::update(api_keys.find(found.id)).set(last_used.eq(now)).execute(&con).expect("failed to connect to db");
+
+	let scopes = found.scopes.split(',').filter_map(Scope::parse).collect::<Vec<_>>();
+	let top = scopes.iter().max().cloned().ok_or_else(|| "API key has no scopes".to_string())?;
+
+	Ok(ServiceAccount {
+		user:      User {
+			id:     -found.id,
+			name:   found.name,
+			email:  format!("apikey:{}", found.prefix),
+			role:   Noob::name().to_lowercase(),
+			active: 1,
+		},
+		scopes,
+		read_only: top == Scope::EventsRead,
+	})
+}
+
+/// vrací seznam API klíčů
+///
+/// GET /apikeys application/json
+#[get("/apikeys")]
+pub fn list(_u: Permitted<ApiKeyManage>) -> ApiResult<Vec<ApiKey>> {
+	use crate::schema::api_keys::dsl::*;
+
+	let con = db::get_con();
+	Ok(Json(api_keys.order(id).load::<ApiKey>(&con)?))
+}
+
+/// vytvoří nový API klíč
+///
+/// POST /apikeys application/json
+///
+/// vrací `{"key": "<prefix>.<tajemství>", "info": ApiKey}`, tajemství už nejde znovu získat
+///
+/// data: [`ApiKeyRequest`]
+#[post("/apikeys", data = "<input>")]
+pub fn create(input: Json<ApiKeyRequest>, usr: Permitted<ApiKeyManage>, req_id: RequestId) -> Result<JsonValue, ApiError> {
+	use crate::schema::api_keys::dsl::*;
+
+	let input = input.into_inner();
+	if input.scopes.is_empty() {
+		Err(ApiError::invalid("at least one scope is required"))?
+	}
+
+	let mut checked = vec![];
+	for scope in &input.scopes {
+		let scope = Scope::parse(scope).ok_or_else(|| ApiError::invalid(format!("unknown scope `{}`", scope)))?;
+		checked.push(scope.name());
+	}
+
+	let new_prefix = random_hex(6);
+	let secret = random_hex(32);
+
+	let con = db::get_con();
+	diesel::insert_into(api_keys)
+		.values(NewApiKey {
+			name:        input.name,
+			prefix:      new_prefix.clone(),
+			secret_hash: hash(&secret),
+			scopes:      checked.join(","),
+			created_by:  usr.user.email.clone(),
+			expires:     input.expires,
+		})
+		.execute(&con)?;
+
+	let info = api_keys.filter(prefix.eq(&new_prefix)).first::<ApiKey>(&con)?;
+
+	audit::record(&usr.user, "apikey.create", Target::ApiKey(info.id), None, audit::snapshot(&info), &req_id);
+	Ok(json!({ "key": format!("{}.{}", new_prefix, secret), "info": info }))
+}
+
+/// zneplatní API klíč
+///
+/// DELETE /apikeys/<id>
+#[delete("/apikeys/<k_id>")]
+pub fn revoke(k_id: i32, usr: Permitted<ApiKeyManage>, req_id: RequestId) -> Result<(), ApiError> {
+	use crate::schema::api_keys::dsl::*;
+
+	let con = db::get_con();
+	let before = api_keys.find(k_id).first::<ApiKey>(&con).optional()?.ok_or_else(|| ApiError::not_found("no such API key"))?;
+
+	diesel::update(api_keys.find(k_id)).set(revoked.eq(1)).execute(&con)?;
+	let after = api_keys.find(k_id).first::<ApiKey>(&con)?;
+
+	audit::record(&usr.user, "apikey.revoke", Target::ApiKey(k_id), audit::snapshot(&before), audit::snapshot(&after), &req_id);
+	Ok(())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, create, revoke]
+}
+
+/// SHA-256 tajemství jako hex
+fn hash(secret: &str) -> String {
+	Sha256::digest(secret.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// náhodný hex string z `bytes` bajtů
+fn random_hex(bytes: usize) -> String {
+	let mut rng = rand::thread_rng();
+	(0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+/// porovnání, jehož doba nezávisí na tom, kde se řetězce liší
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
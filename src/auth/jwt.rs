@@ -0,0 +1,176 @@
+//! podepsané autorizační tokeny (JWT, HS256)
+//!
+//! konfigurace v Rocket.toml (lze přepsat proměnnými `ROCKET_*`, třeba v .env):
+//! ```toml
+//! [global]
+//! auth_audience = "auditorium-booking"  # hodnota claimu `aud`
+//! auth_token_lifetime = 86400           # platnost nového tokenu v sekundách
+//! auth_signing_kid = "2019-12"          # klíč, kterým se podepisují nové tokeny
+//! auth_keys = { "2019-12" = "tajemstvi", "2019-11" = "stare-tajemstvi" }
+//! ```
+//!
+//! rotace klíčů: přidat nový klíč do `auth_keys`, přepnout na něj `auth_signing_kid`
+//! a starý klíč odebrat, až vyprší všechny tokeny, které podepsal.
+//! Token nese jméno klíče v hlavičce `kid`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rocket::config::Config;
+use serde::{Deserialize, Serialize};
+
+/// tolerance pro rozdíly hodin mezi servery (v sekundách)
+const LEEWAY: u64 = 60;
+
+/// obsah tokenu
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Claims {
+	/// subjekt, email uživatele
+	pub sub: String,
+	/// jméno uživatele
+	pub name: String,
+	/// email uživatele
+	pub email: String,
+	/// pro koho je token určen
+	pub aud: String,
+	/// čas vydání (unix timestamp)
+	pub iat: u64,
+	/// čas vypršení (unix timestamp)
+	pub exp: u64,
+}
+
+/// důvod, proč token neprošel
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenError {
+	/// token není JWT
+	Malformed,
+	/// `kid` v hlavičce chybí nebo ho neznáme
+	UnknownKey,
+	/// podpis nesedí (nebo je použit jiný algoritmus)
+	Forged,
+	/// token vypršel
+	Expired,
+	/// token byl vydán v budoucnosti
+	NotYetValid,
+	/// token je určen pro jinou aplikaci
+	WrongAudience,
+	/// token vydal někdo, komu nevěříme
+	WrongIssuer,
+	/// účet nepatří do povolené domény
+	WrongDomain,
+	/// poskytovatel neověřil email uživatele
+	UnverifiedEmail,
+}
+
+impl fmt::Display for TokenError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let msg = match self {
+			TokenError::Malformed => "token is not a valid JWT",
+			TokenError::UnknownKey => "token is signed with an unknown key",
+			TokenError::Forged => "token signature is invalid",
+			TokenError::Expired => "token has expired",
+			TokenError::NotYetValid => "token was issued in the future",
+			TokenError::WrongAudience => "token is meant for a different audience",
+			TokenError::WrongIssuer => "token was issued by an untrusted issuer",
+			TokenError::WrongDomain => "account is not from the allowed domain",
+			TokenError::UnverifiedEmail => "account email is not verified",
+		};
+		write!(f, "{}", msg)
+	}
+}
+
+/// sada klíčů pro vydávání a ověřování tokenů
+pub struct Keys {
+	keys:        HashMap<String, Vec<u8>>,
+	signing_kid: String,
+	audience:    String,
+	lifetime:    u64,
+}
+
+impl Keys {
+	/// vytvoří sadu s jedním (podepisovacím) klíčem
+	pub fn new(signing_kid: &str, secret: &[u8], audience: &str, lifetime: u64) -> Self {
+		let mut keys = HashMap::new();
+		keys.insert(signing_kid.to_string(), secret.to_vec());
+
+		Keys { keys, signing_kid: signing_kid.to_string(), audience: audience.to_string(), lifetime }
+	}
+
+	/// přidá klíč, který se používá jen pro ověřování
+	pub fn with_key(mut self, kid: &str, secret: &[u8]) -> Self {
+		self.keys.insert(kid.to_string(), secret.to_vec());
+		self
+	}
+
+	/// načte klíče z konfigurace Rocketu, viz dokumentace modulu
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let table = config.get_table("auth_keys").map_err(|e| format!("auth_keys: {}", e))?;
+		let signing_kid = config.get_string("auth_signing_kid").map_err(|e| format!("auth_signing_kid: {}", e))?;
+		let audience = config.get_string("auth_audience").unwrap_or_else(|_| "auditorium-booking".to_string());
+		let lifetime = config.get_int("auth_token_lifetime").unwrap_or(24 * 60 * 60) as u64;
+
+		let mut keys = HashMap::new();
+		for (kid, secret) in table {
+			let secret = secret.as_str().ok_or_else(|| format!("auth_keys.{} must be a string", kid))?;
+			keys.insert(kid.clone(), secret.as_bytes().to_vec());
+		}
+
+		if !keys.contains_key(&signing_kid) {
+			Err(format!("auth_signing_kid `{}` is not in auth_keys", signing_kid))?
+		}
+
+		Ok(Keys { keys, signing_kid, audience, lifetime })
+	}
+
+	/// vydá nový token pro daného uživatele
+	pub fn issue(&self, name: &str, email: &str) -> String {
+		let mut header = Header::new(Algorithm::HS256);
+		header.kid = Some(self.signing_kid.clone());
+
+		let iat = now();
+		let claims = Claims {
+			sub: email.to_string(),
+			name: name.to_string(),
+			email: email.to_string(),
+			aud: self.audience.clone(),
+			iat,
+			exp: iat + self.lifetime,
+		};
+
+		encode(&header, &claims, &EncodingKey::from_secret(&self.keys[&self.signing_kid]))
+			.expect("HS256 signing can't fail")
+	}
+
+	/// ověří podpis, `kid`, `exp`, `iat` a `aud` tokenu
+	pub fn verify(&self, token: &str) -> Result<Claims, TokenError> {
+		let header = decode_header(token).map_err(|_| TokenError::Malformed)?;
+		let secret = header.kid.and_then(|kid| self.keys.get(&kid)).ok_or(TokenError::UnknownKey)?;
+
+		let mut validation = Validation::new(Algorithm::HS256);
+		validation.leeway = LEEWAY;
+		validation.set_audience(&[&self.audience]);
+
+		let claims = decode::<Claims>(token, &DecodingKey::from_secret(secret), &validation)
+			.map_err(|e| match e.kind() {
+				ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => TokenError::Forged,
+				ErrorKind::ExpiredSignature => TokenError::Expired,
+				ErrorKind::InvalidAudience => TokenError::WrongAudience,
+				_ => TokenError::Malformed,
+			})?
+			.claims;
+
+		if claims.iat > now() + LEEWAY {
+			Err(TokenError::NotYetValid)?
+		}
+
+		Ok(claims)
+	}
+}
+
+/// aktuální unix timestamp
+pub(crate) fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).expect("system clock is before 1970").as_secs()
+}
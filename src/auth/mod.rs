@@ -0,0 +1,323 @@
+//! modul s autentifikačními funkcemi
+//!
+//! ## přidání autorizace k endpointu
+//! stačí přidat AuthToken parametr s typem
+//! ```no_run
+//! #[get("/supersecretstuff")]
+//! pub fn example(_u: AuthToken<roles::FacilityManager>) {
+//!
+//! }
+//!```
+//!
+//! ## oprávnění
+//! místo role lze vyžadovat konkrétní akci z matice oprávnění, viz [`permissions`]:
+//! ```no_run
+//! #[post("/events/<id>/approve")]
+//! pub fn approve(id: i32, _u: Permitted<actions::BookingApprove>) {
+//!
+//! }
+//! ```
+//!
+//! ## tokeny
+//! klient posílá hlavičku `Authorization: Bearer <jwt>`, kde JWT je buď podepsané
+//! jedním z našich klíčů (HS256, viz [`jwt`]), nebo je to OpenID Connect ID token
+//! od poskytovatele přihlášení (RS256, viz [`oidc`]). Neplatný nebo prošlý token
+//! vrací 401 s JSONem `{"error": "..."}`.
+//!
+//! servisní účty místo tokenu posílají `Authorization: ApiKey <klíč>`, viz [`api_keys`].
+//!
+//! ## noví uživatelé
+//! uživatel, kterého ještě neznáme, se při prvním přihlášení založí, pokud to
+//! dovolí pravidla v [`provisioning`]. Odmítnutí vrací 403.
+
+pub mod api_keys;
+pub mod jwt;
+pub mod oidc;
+pub mod permissions;
+pub mod provisioning;
+
+use serde::{Deserialize, Serialize};
+
+use rocket::request::{FromRequest, Request, Outcome};
+use rocket::{Route, State};
+use rocket_contrib::json::{Json, JsonValue};
+use rocket::http::{Method, Status};
+
+use jsonwebtoken::{decode_header, Algorithm};
+
+use std::marker::PhantomData;
+
+use crate::db::User;
+use crate::error::ApiError;
+
+use self::roles::Role;
+
+/// ověřená identita, tak jak je přijata z tokenu
+#[derive(Serialize, Deserialize)]
+pub struct AuthTokenRaw {
+	/// jméno uživatele
+	pub name: String,
+	/// email uživatele
+	pub email: String,
+}
+
+/// autorizační token po vyřešení údajů s databází
+///
+/// typový parametr `R` určuje minimální roli, kterou uživatel musí mít,
+/// jinak request guard selže s 403. Bez parametru stačí libovolný přihlášený uživatel.
+#[derive(Serialize, Deserialize)]
+pub struct AuthToken<R: Role = roles::Noob> {
+	/// nalezený uživatel
+	pub user:   User,
+	/// scope API klíče, `None` pro uživatele přihlášené tokenem
+	#[serde(skip)]
+	pub scopes: Option<Vec<api_keys::Scope>>,
+	#[serde(skip)]
+	role:       PhantomData<R>,
+}
+
+impl<R: Role> AuthToken<R> {
+	/// sestrojí nový AuthToken z instace [`User`]
+	pub fn from_user(user: User) -> Self {
+		AuthToken { user, scopes: None, role: PhantomData }
+	}
+
+	/// smí uživatel provést akci? API klíč smí jen akce svých scope, ne celé role
+	pub fn may(&self, perms: &permissions::Permissions, action: &str) -> bool {
+		match self.scopes {
+			Some(ref scopes) => api_keys::Scope::allow(scopes, action),
+			None => perms.allows(&self.user.role, action),
+		}
+	}
+
+	/// zjistí, zda má uživatel roli `T` (nebo některou z jejích potomků)
+	pub fn has_role<T: Role>(&self) -> bool {
+		roles::inherits::<T>(&self.user.role)
+	}
+}
+
+/// obsahuje nulové typy pro role
+/// tento design umožňužje zneužít funkce Rustu pro deklarativní
+/// ověření -> pouze stačí do routy přidat parametr s typem `AuthToken<Approved>`.
+///
+/// současné role a jejich stringy (stringy jsou case-insensitive):
+/// -  [`roles::Noob`] -> `noob`
+/// -  [`roles::Approver`] -> `approver`
+/// -  [`roles::FacilityManager`] -> `facilitymanager`
+///
+/// hierarchie: `approver` -> `noob` a `facilitymanager` -> `noob`, tj. approver
+/// i facility manager projdou přes `AuthToken<Noob>`, ale facility manager ne
+/// přes `AuthToken<Approver>`.
+pub mod roles {
+	#![allow(dead_code, missing_docs)]
+
+	/// sdílený trait pro role
+	/// velmi rudimentárním způsobem reprezentuje hierarchii
+	pub trait Role {
+		/// jméno role jako string
+		fn name() -> &'static str;
+		/// jméno rodiše jako string
+		fn daddy() -> Option<&'static str> {
+			None
+		}
+	}
+
+	macro_rules! role_gen {
+		{$(($role:ident -> $daddy:expr))*} => {
+			$(
+				pub struct $role;
+				impl Role for $role {
+					fn name() -> &'static str { stringify!($role) }
+					fn daddy() -> Option<&'static str> { $daddy }
+				}
+			)*
+
+			/// všechny známé role jako dvojice (jméno, rodič)
+			pub const ALL: &[(&str, Option<&str>)] = &[$((stringify!($role), $daddy)),*];
+		}
+	 }
+
+	role_gen! {
+	   (Noob -> None)
+	   (Approver -> Some("noob"))
+	   (FacilityManager -> Some("noob"))
+	}
+
+	/// vrací rodiče role podle jména, `None` pokud role neexistuje
+	pub fn daddy_of(role: &str) -> Option<Option<&'static str>> {
+		ALL.iter().find(|(name, _)| name.eq_ignore_ascii_case(role)).map(|(_, daddy)| *daddy)
+	}
+
+	/// převede jméno role na tvar, ve kterém se ukládá do databáze (malými písmeny),
+	/// `None` pokud role neexistuje
+	pub fn canonical(role: &str) -> Option<String> {
+		ALL.iter().find(|(name, _)| name.eq_ignore_ascii_case(role.trim())).map(|(name, _)| name.to_lowercase())
+	}
+
+	/// vrací roli a všechny její předky (kanonická jména z [`ALL`]),
+	/// pro neznámou roli prázdný vektor
+	pub fn ancestors(role: &str) -> Vec<&'static str> {
+		let find = |role: &str| ALL.iter().find(|(name, _)| name.eq_ignore_ascii_case(role));
+
+		let mut chain = vec![];
+		let mut current = find(role);
+		while let Some((name, daddy)) = current {
+			// ochrana před cyklem v hierarchii
+			if chain.contains(name) {
+				break;
+			}
+
+			chain.push(*name);
+			current = daddy.and_then(find);
+		}
+
+		chain
+	}
+
+	/// zjistí, zda je `role` rovna `R` nebo je jejím potomkem
+	///
+	/// prochází celý řetězec rodičů, ne jen jeden skok
+	pub fn inherits<R: Role>(role: &str) -> bool {
+		ancestors(role).iter().any(|r| r.eq_ignore_ascii_case(R::name()))
+	}
+}
+
+impl<'a, 'r, R: Role> FromRequest<'a, 'r> for AuthToken<R> {
+	type Error = String;
+
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		let keys = match request.guard::<State<jwt::Keys>>() {
+			Outcome::Success(keys) => keys,
+			_ => return Outcome::Failure((Status::InternalServerError, "auth keys are not configured".to_string())),
+		};
+		let policy = match request.guard::<State<provisioning::Policy>>() {
+			Outcome::Success(policy) => policy,
+			_ => return Outcome::Failure((Status::InternalServerError, "provisioning is not configured".to_string())),
+		};
+
+		let header = request.headers().get_one("Authorization").unwrap_or("");
+		let (result, scopes) = match header.splitn(2, ' ').collect::<Vec<_>>().as_slice() {
+			["Bearer", token] => {
+				let raw = match verify_token(request, &keys, token.trim()) {
+					Ok(raw) => raw,
+					Err(e) => return fail(request, Status::Unauthorized, e),
+				};
+
+				match provisioning::resolve_user(&policy, &raw) {
+					Ok(user) => (user, None),
+					Err(e) => return fail(request, Status::Forbidden, e),
+				}
+			}
+			["ApiKey", key] => {
+				let account = match api_keys::authenticate(key.trim()) {
+					Ok(account) => account,
+					Err(e) => return fail(request, Status::Unauthorized, e),
+				};
+
+				if account.read_only && request.method() != Method::Get && request.method() != Method::Head {
+					return fail(request, Status::Forbidden, "this API key is read-only".to_string());
+				}
+
+				(account.user, Some(account.scopes))
+			}
+			_ => {
+				let reason = "expected `Authorization: Bearer <token>` or `Authorization: ApiKey <key>` header";
+				return fail(request, Status::Unauthorized, reason.to_string());
+			}
+		};
+
+		if roles::inherits::<R>(&result.role) {
+			Outcome::Success(AuthToken { user: result, scopes, role: PhantomData })
+		} else {
+			fail(request, Status::Forbidden, "you don't have the required role".to_string())
+		}
+	}
+}
+
+/// ověří token a vrátí identitu uživatele
+///
+/// naše tokeny jsou HS256, cokoliv jiného se zkusí ověřit jako OIDC ID token
+fn verify_token(request: &Request, keys: &jwt::Keys, token: &str) -> Result<AuthTokenRaw, String> {
+	let alg = decode_header(token).map_err(|_| jwt::TokenError::Malformed.to_string())?.alg;
+
+	if alg == Algorithm::HS256 {
+		let claims = keys.verify(token).map_err(|e| e.to_string())?;
+		return Ok(AuthTokenRaw { name: claims.name, email: claims.email });
+	}
+
+	match request.guard::<State<Option<oidc::Verifier>>>() {
+		Outcome::Success(verifier) => match *verifier {
+			Some(ref verifier) => verifier.verify(token).map_err(|e| e.to_string()),
+			None => Err("OpenID Connect tokens are not accepted".to_string()),
+		},
+		_ => Err("OpenID Connect tokens are not accepted".to_string()),
+	}
+}
+
+/// důvod posledního selhání autentifikace, čtou ho catchery [`unauthorized_catcher`]
+/// a [`forbidden_catcher`]
+pub struct AuthFailure(pub String);
+
+/// uloží důvod selhání do requestu a vrátí daný status
+fn fail<T>(request: &Request, status: Status, reason: String) -> Outcome<T, String> {
+	request.local_cache(|| AuthFailure(reason.clone()));
+	Outcome::Failure((status, reason))
+}
+
+/// catcher pro 401, vrací důvod selhání jako JSON
+#[catch(401)]
+pub fn unauthorized_catcher(req: &Request) -> JsonValue {
+	let AuthFailure(reason) = req.local_cache(|| AuthFailure("unauthorized".to_string()));
+	json!({ "error": reason })
+}
+
+/// catcher pro 403, vrací důvod selhání jako JSON
+#[catch(403)]
+pub fn forbidden_catcher(req: &Request) -> JsonValue {
+	let AuthFailure(reason) = req.local_cache(|| AuthFailure("forbidden".to_string()));
+	json!({ "error": reason })
+}
+
+/// vydá nový token podepsaný aktuálním klíčem
+///
+/// slouží i pro přechod na nový klíč při rotaci
+///
+/// POST /token/refresh
+///
+/// API klíč za token vyměnit nejde (403), token by neznal jeho scope a přežil by zneplatnění klíče
+#[post("/token/refresh")]
+pub fn refresh(usr: AuthToken, keys: State<jwt::Keys>) -> Result<JsonValue, ApiError> {
+	if usr.scopes.is_some() {
+		Err(ApiError::new(Status::Forbidden, "API keys can't be exchanged for a token"))?
+	}
+
+	Ok(json!({ "token": keys.issue(&usr.user.name, &usr.user.email) }))
+}
+
+/// vrací efektivní oprávnění přihlášeného uživatele, aby frontend mohl schovat tlačítka
+///
+/// GET /me/permissions application/json
+#[get("/me/permissions")]
+pub fn my_permissions(usr: AuthToken, perms: State<permissions::Permissions>) -> JsonValue {
+	let allowed = match usr.scopes {
+		Some(ref scopes) => {
+			let mut actions = scopes.iter().flat_map(|s| s.actions()).map(String::from).collect::<Vec<_>>();
+			actions.sort();
+			actions.dedup();
+			actions
+		}
+		None => perms.effective(&usr.user.role),
+	};
+	json!({ "role": usr.user.role, "permissions": allowed })
+}
+
+/// vrací informace o uživatelu
+#[get("/me")]
+pub fn me(_u: AuthToken) -> Json<User> {
+	Json(_u.user)
+}
+
+/// vrací routy přihlášeného uživatele
+pub fn routes() -> Vec<Route> {
+	routes![me, my_permissions, refresh]
+}
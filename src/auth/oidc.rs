@@ -0,0 +1,203 @@
+//! ověřování OpenID Connect ID tokenů (např. z přihlášení přes Google)
+//!
+//! frontend může místo našeho tokenu poslat přímo ID token od poskytovatele.
+//! Podpis se ověřuje proti JWKS, které se načte ze souboru nebo z URL
+//! a drží se v cache, dokud nezestárne (nebo dokud nepřijde token s neznámým `kid`).
+//!
+//! konfigurace v Rocket.toml (pokud `oidc_jwks` chybí, OIDC je vypnuté):
+//! ```toml
+//! [global]
+//! oidc_jwks = "https://www.googleapis.com/oauth2/v3/certs"  # nebo cesta k souboru
+//! oidc_jwks_refresh = 3600                                  # stáří cache v sekundách
+//! oidc_issuers = ["accounts.google.com", "https://accounts.google.com"]
+//! oidc_client_id = "1234-abcd.apps.googleusercontent.com"   # očekávaný `aud`
+//! oidc_hosted_domain = "gjk.cz"                             # nepovinné, claim `hd`
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rocket::config::Config;
+use serde::Deserialize;
+
+use super::jwt::{now, TokenError};
+use super::AuthTokenRaw;
+
+/// tolerance pro rozdíly hodin mezi servery (v sekundách)
+const LEEWAY: u64 = 60;
+
+/// jak často nejvýše se JWKS znovu načítá kvůli neznámému `kid`
+const MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// claimy ID tokenu, které nás zajímají
+#[derive(Deserialize, Debug, Clone)]
+pub struct IdClaims {
+	/// vydavatel
+	pub iss: String,
+	/// identifikátor uživatele u poskytovatele
+	pub sub: String,
+	/// email uživatele
+	pub email: Option<String>,
+	/// zda poskytovatel email ověřil
+	pub email_verified: Option<bool>,
+	/// celé jméno
+	pub name: Option<String>,
+	/// doména G Suite účtu
+	pub hd: Option<String>,
+	/// čas vydání
+	pub iat: u64,
+	/// čas vypršení
+	pub exp: u64,
+}
+
+/// jeden RSA klíč z JWKS
+#[derive(Deserialize, Debug, Clone)]
+struct Jwk {
+	kid: String,
+	kty: String,
+	n:   String,
+	e:   String,
+}
+
+/// JWKS dokument
+#[derive(Deserialize, Debug)]
+struct Jwks {
+	keys: Vec<Jwk>,
+}
+
+/// načtené klíče a čas načtení
+struct Cache {
+	keys:    HashMap<String, Jwk>,
+	fetched: Instant,
+}
+
+/// ověřovač ID tokenů
+pub struct Verifier {
+	source:        String,
+	refresh:       Duration,
+	issuers:       Vec<String>,
+	client_id:     String,
+	hosted_domain: Option<String>,
+	cache:         RwLock<Cache>,
+}
+
+impl Verifier {
+	/// načte konfiguraci z Rocketu, `Ok(None)` pokud OIDC není zapnuté
+	pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+		let source = match config.get_string("oidc_jwks") {
+			Ok(source) => source,
+			Err(_) => return Ok(None),
+		};
+
+		let client_id = config.get_string("oidc_client_id").map_err(|e| format!("oidc_client_id: {}", e))?;
+		let refresh = config.get_int("oidc_jwks_refresh").unwrap_or(60 * 60) as u64;
+		let hosted_domain = config.get_string("oidc_hosted_domain").ok();
+		let issuers = match config.get_slice("oidc_issuers") {
+			Ok(issuers) => issuers
+				.iter()
+				.map(|i| i.as_str().map(str::to_string).ok_or_else(|| "oidc_issuers must be strings".to_string()))
+				.collect::<Result<Vec<_>, _>>()?,
+			Err(_) => vec!["accounts.google.com".to_string(), "https://accounts.google.com".to_string()],
+		};
+
+		let keys = fetch(&source)?;
+
+		Ok(Some(Verifier {
+			source,
+			refresh: Duration::from_secs(refresh),
+			issuers,
+			client_id,
+			hosted_domain,
+			cache: RwLock::new(Cache { keys, fetched: Instant::now() }),
+		}))
+	}
+
+	/// znovu načte JWKS ze zdroje
+	pub fn refresh(&self) -> Result<(), String> {
+		let keys = fetch(&self.source)?;
+		*self.cache.write().expect("JWKS cache lock poisoned") = Cache { keys, fetched: Instant::now() };
+		Ok(())
+	}
+
+	/// najde klíč podle `kid`, v případě potřeby obnoví cache
+	fn key(&self, kid: &str) -> Option<Jwk> {
+		let (key, age) = {
+			let cache = self.cache.read().expect("JWKS cache lock poisoned");
+			(cache.keys.get(kid).cloned(), cache.fetched.elapsed())
+		};
+
+		let stale = age > self.refresh || (key.is_none() && age > MIN_REFRESH);
+		if !stale {
+			return key;
+		}
+
+		if let Err(e) = self.refresh() {
+			// radši použijeme starou cache, než abychom odmítli všechny
+			eprintln!("failed to refresh JWKS from {}: {}", self.source, e);
+			return key;
+		}
+
+		self.cache.read().expect("JWKS cache lock poisoned").keys.get(kid).cloned()
+	}
+
+	/// ověří ID token a vrátí identitu uživatele
+	pub fn verify(&self, token: &str) -> Result<AuthTokenRaw, TokenError> {
+		let header = decode_header(token).map_err(|_| TokenError::Malformed)?;
+		let jwk = header.kid.and_then(|kid| self.key(&kid)).ok_or(TokenError::UnknownKey)?;
+
+		let mut validation = Validation::new(Algorithm::RS256);
+		validation.leeway = LEEWAY;
+		validation.set_audience(&[&self.client_id]);
+
+		let claims = decode::<IdClaims>(token, &DecodingKey::from_rsa_components(&jwk.n, &jwk.e), &validation)
+			.map_err(|e| match e.kind() {
+				ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => TokenError::Forged,
+				ErrorKind::ExpiredSignature => TokenError::Expired,
+				ErrorKind::InvalidAudience => TokenError::WrongAudience,
+				_ => TokenError::Malformed,
+			})?
+			.claims;
+
+		if claims.iat > now() + LEEWAY {
+			Err(TokenError::NotYetValid)?
+		}
+
+		if !self.issuers.iter().any(|iss| *iss == claims.iss) {
+			Err(TokenError::WrongIssuer)?
+		}
+
+		if let Some(ref domain) = self.hosted_domain {
+			if claims.hd.as_ref() != Some(domain) {
+				Err(TokenError::WrongDomain)?
+			}
+		}
+
+		// chybějící email_verified se bere jako neověřený email
+		let email = match (claims.email, claims.email_verified) {
+			(Some(email), Some(true)) => email,
+			_ => return Err(TokenError::UnverifiedEmail),
+		};
+
+		Ok(AuthTokenRaw { name: claims.name.unwrap_or_else(|| email.clone()), email })
+	}
+}
+
+/// načte JWKS ze souboru nebo z http(s) URL
+fn fetch(source: &str) -> Result<HashMap<String, Jwk>, String> {
+	let body = if source.starts_with("http://") || source.starts_with("https://") {
+		reqwest::blocking::get(source)
+			.and_then(|r| r.error_for_status())
+			.and_then(|r| r.text())
+			.map_err(|e| format!("can't download JWKS: {}", e))?
+	} else {
+		fs::read_to_string(source).map_err(|e| format!("can't read JWKS file {}: {}", source, e))?
+	};
+
+	let jwks: Jwks = serde_json::from_str(&body).map_err(|e| format!("invalid JWKS: {}", e))?;
+
+	Ok(jwks.keys.into_iter().filter(|k| k.kty == "RSA").map(|k| (k.kid.clone(), k)).collect())
+}
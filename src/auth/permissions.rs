@@ -0,0 +1,155 @@
+//! matice oprávnění: role -> akce
+//!
+//! matice se načítá ze souboru (Rocket.toml: `permissions_file`, výchozí `permissions.toml`):
+//! ```toml
+//! [roles]
+//! noob = ["booking.create", "booking.edit_own"]
+//! approver = ["booking.edit_any", "booking.approve"]
+//! facilitymanager = ["user.manage", "apikey.manage", "audit.view", "job.view_any", "layout.manage"]
+//! ```
+//! role dědí akce všech svých předků (viz [`super::roles`]), takže approver
+//! může i všechno, co noob.
+//!
+//! v routě stačí přidat parametr [`Permitted`] s typem akce z [`actions`],
+//! nebo se zeptat přímo přes [`super::AuthToken::may`], které počítá i se scope API klíčů.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::marker::PhantomData;
+
+use rocket::config::Config;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+use serde::Deserialize;
+
+use super::{fail, roles, AuthToken};
+use crate::db::User;
+
+/// nulové typy pro akce, obdoba [`super::roles`]
+pub mod actions {
+	#![allow(missing_docs)]
+
+	/// sdílený trait pro akce
+	pub trait Action {
+		/// jméno akce, tak jak je v matici oprávnění
+		fn name() -> &'static str;
+	}
+
+	macro_rules! action_gen {
+		{$(($action:ident -> $name:literal))*} => {
+			$(
+				pub struct $action;
+				impl Action for $action {
+					fn name() -> &'static str { $name }
+				}
+			)*
+		}
+	}
+
+	action_gen! {
+		(BookingCreate -> "booking.create")
+		(BookingEditOwn -> "booking.edit_own")
+		(BookingEditAny -> "booking.edit_any")
+		(BookingApprove -> "booking.approve")
+		(UserManage -> "user.manage")
+		(ApiKeyManage -> "apikey.manage")
+		(AuditView -> "audit.view")
+		(JobViewAny -> "job.view_any")
+		(LayoutManage -> "layout.manage")
+	}
+}
+
+use self::actions::Action;
+
+/// obsah souboru s maticí
+#[derive(Deserialize)]
+struct PermissionsFile {
+	roles: HashMap<String, Vec<String>>,
+}
+
+/// matice oprávnění
+pub struct Permissions {
+	/// role (malými písmeny) -> akce přidělené přímo této roli
+	matrix: HashMap<String, Vec<String>>,
+}
+
+impl Permissions {
+	/// načte matici z TOML, role musí existovat v [`roles::ALL`]
+	pub fn from_toml(src: &str) -> Result<Self, String> {
+		let file: PermissionsFile = toml::from_str(src).map_err(|e| format!("invalid permissions file: {}", e))?;
+
+		let mut matrix = HashMap::new();
+		for (role, actions) in file.roles {
+			let role = roles::canonical(&role).ok_or_else(|| format!("unknown role `{}` in permissions", role))?;
+			matrix.insert(role, actions);
+		}
+
+		Ok(Permissions { matrix })
+	}
+
+	/// načte soubor z `permissions_file` v konfiguraci Rocketu
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let path = config.get_string("permissions_file").unwrap_or_else(|_| "permissions.toml".to_string());
+		let src = fs::read_to_string(&path).map_err(|e| format!("can't read {}: {}", path, e))?;
+
+		Permissions::from_toml(&src)
+	}
+
+	/// vrací seřazený seznam všech akcí, které role (i zděděně) smí
+	pub fn effective(&self, role: &str) -> Vec<String> {
+		roles::ancestors(role)
+			.iter()
+			.filter_map(|r| self.matrix.get(&r.to_lowercase()))
+			.flatten()
+			.cloned()
+			.collect::<BTreeSet<_>>()
+			.into_iter()
+			.collect()
+	}
+
+	/// smí role provést danou akci?
+	pub fn allows(&self, role: &str, action: &str) -> bool {
+		roles::ancestors(role)
+			.iter()
+			.filter_map(|r| self.matrix.get(&r.to_lowercase()))
+			.any(|actions| actions.iter().any(|a| a == action))
+	}
+
+	/// smí uživatel upravit (nebo smazat) rezervaci, jejímž autorem je `author`?
+	pub fn may_edit_booking(&self, user: &User, author: &str) -> bool {
+		use self::actions::{BookingEditAny, BookingEditOwn};
+
+		self.allows(&user.role, BookingEditAny::name())
+			|| (self.allows(&user.role, BookingEditOwn::name()) && author.trim() == user.email.trim())
+	}
+}
+
+/// request guard, který projde jen uživateli s oprávněním k akci `A`
+pub struct Permitted<A: Action> {
+	/// přihlášený uživatel
+	pub user: User,
+	action:   PhantomData<A>,
+}
+
+impl<'a, 'r, A: Action> FromRequest<'a, 'r> for Permitted<A> {
+	type Error = String;
+
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+		let token = match request.guard::<AuthToken>() {
+			Outcome::Success(token) => token,
+			Outcome::Failure(f) => return Outcome::Failure(f),
+			Outcome::Forward(f) => return Outcome::Forward(f),
+		};
+		let perms = match request.guard::<State<Permissions>>() {
+			Outcome::Success(perms) => perms,
+			_ => return Outcome::Failure((Status::InternalServerError, "permissions are not configured".to_string())),
+		};
+
+		if token.may(&perms, A::name()) {
+			Outcome::Success(Permitted { user: token.user, action: PhantomData })
+		} else {
+			fail(request, Status::Forbidden, format!("missing permission `{}`", A::name()))
+		}
+	}
+}
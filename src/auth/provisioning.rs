@@ -0,0 +1,121 @@
+//! pravidla pro zakládání nových uživatelů
+//!
+//! konfigurace v Rocket.toml:
+//! ```toml
+//! [global]
+//! provisioning_domains = ["gjk.cz"]  # povolené domény emailů, prázdné = všechny
+//! provisioning_pending = true        # noví uživatelé čekají na aktivaci facility managerem
+//! ```
+//!
+//! pozvánky (tabulka `invitations`, spravuje je facility manager přes `/invitations`)
+//! mají přednost: pozvaný uživatel se založí s rolí z pozvánky a rovnou aktivní,
+//! bez ohledu na doménu.
+
+use diesel::prelude::*;
+use rocket::config::Config;
+
+use super::AuthTokenRaw;
+use crate::db;
+use crate::db::{Invitation, NewUser, User};
+
+/// pravidla pro zakládání uživatelů
+pub struct Policy {
+	domains: Vec<String>,
+	pending: bool,
+}
+
+impl Policy {
+	/// sestrojí pravidla, `domains` prázdné = všechny domény
+	pub fn new(domains: Vec<String>, pending: bool) -> Self {
+		Policy { domains: domains.into_iter().map(|d| d.to_lowercase()).collect(), pending }
+	}
+
+	/// načte pravidla z konfigurace Rocketu, viz dokumentace modulu
+	pub fn from_config(config: &Config) -> Result<Self, String> {
+		let domains = match config.get_slice("provisioning_domains") {
+			Ok(domains) => domains
+				.iter()
+				.map(|d| d.as_str().map(str::to_string).ok_or_else(|| "provisioning_domains must be strings".to_string()))
+				.collect::<Result<Vec<_>, _>>()?,
+			Err(_) => vec![],
+		};
+		let pending = config.get_bool("provisioning_pending").unwrap_or(false);
+
+		Ok(Policy::new(domains, pending))
+	}
+
+	/// rozhodne, zda a jak založit nového uživatele
+	///
+	/// vrací dvojici (role, aktivní) nebo důvod odmítnutí
+	///
+	/// identity, které nejsou email (např. `apikey:<prefix>` servisních účtů), se nezakládají nikdy
+	pub fn admit(&self, email: &str, invitation: Option<&Invitation>) -> Result<(String, bool), String> {
+		if !email.contains('@') || email.starts_with("apikey:") {
+			Err(format!("`{}` is not an email address", email))?
+		}
+
+		if let Some(invitation) = invitation {
+			return Ok((invitation.role.clone(), true));
+		}
+
+		let domain = email.rsplit('@').next().unwrap_or("").to_lowercase();
+		if !self.domains.is_empty() && !self.domains.contains(&domain) {
+			Err(format!("sign-ups from `{}` are not allowed, ask a facility manager for an invitation", domain))?
+		}
+
+		Ok(("noob".to_string(), !self.pending))
+	}
+}
+
+/// najde uživatele podle emailu, případně ho založí podle pravidel
+///
+/// vrací důvod odmítnutí, pokud uživatel nesmí být založen nebo čeká na aktivaci
+pub fn resolve_user(policy: &Policy, token: &AuthTokenRaw) -> Result<User, String> {
+	use crate::schema::invitations;
+	use crate::schema::users::dsl::*;
+
+	let connection = db::get_con();
+
+	let existing = users.filter(email.eq(&token.email)).first::<User>(&connection).optional().expect("failed to connect to db");
+
+	let user = match existing {
+		Some(user) => user,
+		None => {
+			let invitation = invitations::table
+				.filter(invitations::email.eq(token.email.to_lowercase()))
+				.first::<Invitation>(&connection)
+				.optional()
+				.expect("failed to connect to db");
+
+			let (new_role, is_active) = policy.admit(&token.email, invitation.as_ref())?;
+
+			// unfortunately, SQLite does not support the RETURNING clause, so one has to do this atrocity
+			diesel::insert_into(users)
+				.values(NewUser {
+					name:   token.name.clone(),
+					email:  token.email.clone(),
+					role:   new_role,
+					active: is_active as i32,
+				})
+				.execute(&connection)
+				.expect("failed to connect to db or insert item");
+
+			if let Some(invitation) = invitation {
+				diesel::delete(invitations::table.find(invitation.id))
+					.execute(&connection)
+					.expect("failed to connect to db");
+			}
+
+			users
+				.filter(email.eq(&token.email))
+				.first::<User>(&connection)
+				.unwrap_or_else(|_| unreachable!("uh oh, this shouldn't happen, is your DB okay?"))
+		}
+	};
+
+	if user.active == 0 {
+		Err("account is waiting for activation by a facility manager".to_string())?
+	}
+
+	Ok(user)
+}
@@ -0,0 +1,122 @@
+//! časy rezervací
+//!
+//! v databázi jsou časy jako `TIMESTAMP` v UTC (`2019-12-12 17:00:00`), v Rustu `NaiveDateTime`
+//! v UTC. Klientům se posílají podle RFC 3339 vždy v UTC (`2019-12-12T17:00:00Z`).
+//!
+//! od klienta se přijímá:
+//! - RFC 3339 s posunem -> `2019-12-12T18:00:00+01:00`, `2019-12-12T17:00:00Z`
+//! - místní čas auditoria bez posunu -> `2019-12-12T18:00`, `2019-12-12 18:00:00`,
+//!   počítá se v [`FACILITY_TZ`] i s letním časem
+//!
+//! místní čas, který při přechodu na letní čas neexistuje (`02:30` poslední neděli v březnu),
+//! je chyba, dvojznačná hodina při přechodu zpět se bere jako ta dřívější (ještě letní).
+//! Rezervace musí končit po začátku a trvat aspoň [`MIN_DURATION_MINUTES`] minut
+//! a nejvýš [`MAX_DURATION_DAYS`] dní, viz [`check_span`].
+//!
+//! ve structech se čas (de)serializuje přes [`rfc3339`]:
+//! ```no_run
+//! #[serde(with = "crate::datetime::rfc3339")]
+//! pub begin_time: NaiveDateTime,
+//! ```
+
+use chrono::offset::LocalResult;
+use chrono::{DateTime, Duration, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::error::ApiError;
+
+/// časové pásmo auditoria
+pub const FACILITY_TZ: Tz = chrono_tz::Europe::Prague;
+
+/// nejkratší rezervace v minutách
+pub const MIN_DURATION_MINUTES: i64 = 15;
+/// nejdelší rezervace ve dnech
+pub const MAX_DURATION_DAYS: i64 = 7;
+
+/// formáty místního času bez posunu
+const LOCAL_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M"];
+
+/// načte čas od klienta, vrací ho v UTC
+pub fn parse(src: &str) -> Result<NaiveDateTime, String> {
+	let src = src.trim();
+	if let Ok(t) = DateTime::parse_from_rfc3339(src) {
+		return Ok(t.naive_utc());
+	}
+
+	let local = LOCAL_FORMATS.iter().find_map(|f| NaiveDateTime::parse_from_str(src, f).ok());
+	let local = local.ok_or_else(|| format!("invalid time `{}`, use RFC 3339 (2019-12-12T18:00:00+01:00)", src))?;
+
+	from_local(local).ok_or_else(|| format!("`{}` doesn't exist in {} (daylight saving time)", src, FACILITY_TZ.name()))
+}
+
+/// místní čas auditoria -> UTC, `None` pokud kvůli letnímu času neexistuje
+pub fn from_local(local: NaiveDateTime) -> Option<NaiveDateTime> {
+	match FACILITY_TZ.from_local_datetime(&local) {
+		LocalResult::Single(t) => Some(t.naive_utc()),
+		LocalResult::Ambiguous(earlier, _) => Some(earlier.naive_utc()),
+		LocalResult::None => None,
+	}
+}
+
+/// UTC -> místní čas auditoria
+pub fn to_local(utc: NaiveDateTime) -> NaiveDateTime {
+	FACILITY_TZ.from_utc_datetime(&utc).naive_local()
+}
+
+/// čas v UTC podle RFC 3339 (`2019-12-12T17:00:00Z`)
+pub fn format(utc: NaiveDateTime) -> String {
+	DateTime::<Utc>::from_utc(utc, Utc).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// zkontroluje, že rezervace končí po začátku a trvá rozumně dlouho, jinak 422
+pub fn check_span(begin: NaiveDateTime, end: NaiveDateTime) -> Result<(), ApiError> {
+	if end <= begin {
+		Err(ApiError::invalid("begin_time must be before end_time"))?
+	}
+	if end - begin < Duration::minutes(MIN_DURATION_MINUTES) {
+		Err(ApiError::invalid(format!("an event must take at least {} minutes", MIN_DURATION_MINUTES)))?
+	}
+	if end - begin > Duration::days(MAX_DURATION_DAYS) {
+		Err(ApiError::invalid(format!("an event can't take more than {} days", MAX_DURATION_DAYS)))?
+	}
+
+	Ok(())
+}
+
+/// serde pro `NaiveDateTime` v UTC, zapisuje přes [`format`], čte přes [`parse`]
+pub mod rfc3339 {
+	use chrono::NaiveDateTime;
+	use serde::{de, Deserialize, Deserializer, Serializer};
+
+	/// zapíše čas podle RFC 3339
+	pub fn serialize<S: Serializer>(t: &NaiveDateTime, s: S) -> Result<S::Ok, S::Error> {
+		s.serialize_str(&super::format(*t))
+	}
+
+	/// načte čas od klienta
+	pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDateTime, D::Error> {
+		super::parse(&String::deserialize(d)?).map_err(de::Error::custom)
+	}
+
+	/// totéž pro `Option<NaiveDateTime>`, pole potřebuje i `#[serde(default)]`
+	pub mod option {
+		use chrono::NaiveDateTime;
+		use serde::{de, Deserialize, Deserializer, Serializer};
+
+		/// zapíše čas podle RFC 3339, `None` jako `null`
+		pub fn serialize<S: Serializer>(t: &Option<NaiveDateTime>, s: S) -> Result<S::Ok, S::Error> {
+			match t {
+				Some(t) => s.serialize_some(&super::super::format(*t)),
+				None => s.serialize_none(),
+			}
+		}
+
+		/// načte čas od klienta, `null` je `None`
+		pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NaiveDateTime>, D::Error> {
+			Option::<String>::deserialize(d)?
+				.map(|src| super::super::parse(&src))
+				.transpose()
+				.map_err(de::Error::custom)
+		}
+	}
+}
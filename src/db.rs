@@ -2,8 +2,9 @@
 //! pro přidávání nového modelu viz [dokumentace Diesel ORM](https://diesel.rs)
 
 use rocket_contrib::databases::diesel;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Serializer, Deserialize};
 use diesel::Connection;
+use chrono::NaiveDateTime;
 
 use std::env;
 use crate::schema::*;
@@ -28,26 +29,107 @@ pub struct Reservation {
 	pub description: String,
 	/// "rezervujitel" události :^)
 	pub author: String,
-	/// místnosti, které si "rezervujitel" přeje zarezervovat
+	/// místnosti, které rezervace zabírá
 	///
-	/// funguje na bázi bitflagů:
-	/// ```
-	/// 0b00 -> žádná místnosti (nemělo by se stát :D)
-	/// 0b01 -> north
-	/// 0b10 -> south
+	/// funguje na bázi bitflagů, bity přiděluje katalog místností (viz [`crate::rgi::booking::rooms`]):
+	/// ```text
+	/// 0b01 -> sever
+	/// 0b10 -> jih
 	/// 0b11 -> celé auditorium
 	/// ```
 	pub rooms: i32,
-	/// počáteční čas rezervace
-	pub begin_time: String,
-	/// čas, kdy rezervace končí
-	pub end_time: String,
+	/// počáteční čas rezervace (UTC), viz [`crate::datetime`]
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub begin_time: NaiveDateTime,
+	/// čas, kdy rezervace končí (UTC)
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub end_time: NaiveDateTime,
 	/// rozložení nábytku v audioriu
 	pub layout: i32,
-	/// zda byla rezervace schválena
+	/// zda byla rezervace schválena, 1 právě když `status` je `approved` (pro starší klienty)
 	pub approved: i32,
 	/// počet lidí
 	pub people: i32,
+	/// série, do které rezervace patří, viz [`crate::rgi::booking::series`]
+	#[serde(default)]
+	pub series_id: Option<i32>,
+	/// stav rezervace, viz [`crate::rgi::booking::approval`]
+	#[serde(default = "pending")]
+	pub status: String,
+	/// důvod zamítnutí nebo zrušení
+	#[serde(default)]
+	pub reason: Option<String>,
+	/// email toho, kdo o rezervaci naposledy rozhodl (schválil, zamítl nebo zrušil)
+	#[serde(default)]
+	pub approved_by: Option<String>,
+	/// čas posledního rozhodnutí (UTC)
+	#[serde(default, with = "crate::datetime::rfc3339::option")]
+	pub decided_at: Option<NaiveDateTime>,
+}
+
+/// místnosti v požadavku
+///
+/// ```json
+/// "rooms": 3                        // bitflagy jako u starších klientů
+/// "rooms": "celé auditorium"        // jméno místnosti z katalogu
+/// "rooms": [1, 2]                   // id nebo jména, sever a jih dohromady je celé auditorium
+/// ```
+/// endpointy místnosti převedou na bitflagy (viz [`crate::rgi::booking::rooms::resolve`]),
+/// backend už vždy dostane [`Rooms::Mask`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Rooms {
+	/// bitflagy
+	Mask(i32),
+	/// jedna místnost podle jména
+	Name(String),
+	/// několik místností podle id nebo jména
+	List(Vec<RoomRef>),
+}
+
+impl Rooms {
+	/// bitflagy, pokud už jsou místnosti převedené
+	pub fn mask(&self) -> Option<i32> {
+		match self {
+			Rooms::Mask(mask) => Some(*mask),
+			_ => None,
+		}
+	}
+}
+
+/// odkaz na místnost z katalogu
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RoomRef {
+	/// podle id
+	Id(i32),
+	/// podle jména
+	Name(String),
+}
+
+/// Model místnosti z katalogu, viz [`crate::rgi::booking::rooms`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Queryable)]
+pub struct Room {
+	/// identifikátor
+	pub id: i32,
+	/// jméno
+	pub name: String,
+	/// popis
+	pub description: String,
+	/// kolik lidí se do místnosti vejde
+	pub capacity: i32,
+	/// bit v `rooms` rezervace, jen u místností, které nejsou složené z jiných
+	pub bit: Option<i32>,
+}
+
+/// místnost, kterou rezervace zabírá, pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "reservation_rooms"]
+pub struct InsertReservationRoom {
+	/// rezervace
+	pub reservation_id: i32,
+	/// místnost s vlastním bitem
+	pub room_id: i32,
 }
 
 /// Model rezervace pro přidání do databáze
@@ -58,20 +140,14 @@ pub struct NewReservation {
 	pub name: String,
 	/// popis události
 	pub description: String,
-	/// místnosti, které si "rezervujitel" přeje zarezervovat
-	///
-	/// funguje na bázi bitflagů:
-	/// ```
-	/// 0b00 -> žádná místnosti (nemělo by se stát :D)
-	/// 0b01 -> north
-	/// 0b10 -> south
-	/// 0b11 -> celé auditorium
-	/// ```
-	pub rooms: u8,
-	/// počáteční čas rezervace
-	pub begin_time: String,
+	/// místnosti, které si "rezervujitel" přeje zarezervovat, viz [`Rooms`]
+	pub rooms: Rooms,
+	/// počáteční čas rezervace, RFC 3339 nebo místní čas auditoria, viz [`crate::datetime`]
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub begin_time: NaiveDateTime,
 	/// čas, kdy rezervace končí
-	pub end_time: String,
+	#[serde(with = "crate::datetime::rfc3339")]
+	pub end_time: NaiveDateTime,
 	/// rozložení nábytku v audioriu
 	pub layout: u16,
 	/// počet lidí
@@ -86,26 +162,250 @@ pub struct UpdateReservation {
 	pub name: Option<String>,
 	/// popis události
 	pub description: Option<String>,
-	/// místnosti, které si "rezervujitel" přeje zarezervovat
-	///
-	/// funguje na bázi bitflagů:
-	/// ```
-	/// 0b00 -> žádná místnosti (nemělo by se stát :D)
-	/// 0b01 -> north
-	/// 0b10 -> south
-	/// 0b11 -> celé auditorium
-	/// ```
-	pub rooms: Option<u8>,
-	/// počáteční čas rezervace
-	pub begin_time: Option<String>,
+	/// místnosti, které si "rezervujitel" přeje zarezervovat, viz [`Rooms`]
+	pub rooms: Option<Rooms>,
+	/// počáteční čas rezervace, viz [`crate::datetime`]
+	#[serde(default, with = "crate::datetime::rfc3339::option")]
+	pub begin_time: Option<NaiveDateTime>,
 	/// čas, kdy rezervace končí
-	pub end_time: Option<String>,
+	#[serde(default, with = "crate::datetime::rfc3339::option")]
+	pub end_time: Option<NaiveDateTime>,
 	/// rozložení nábytku v audioriu
 	pub layout: Option<u16>,
 	/// počet lidí
 	pub people: Option<u16>,
 }
 
+/// Model rezervace pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "booking"]
+pub struct InsertReservation {
+	/// název události
+	pub name: String,
+	/// popis události
+	pub description: String,
+	/// email autora
+	pub author: String,
+	/// místnosti (bitflagy)
+	pub rooms: i32,
+	/// počáteční čas (UTC)
+	pub begin_time: NaiveDateTime,
+	/// čas konce (UTC)
+	pub end_time: NaiveDateTime,
+	/// rozložení nábytku
+	pub layout: i32,
+	/// zda je schválena
+	pub approved: i32,
+	/// počet lidí
+	pub people: i32,
+	/// série, do které rezervace patří
+	pub series_id: Option<i32>,
+}
+
+impl InsertReservation {
+	/// nová neschválená rezervace od daného autora
+	pub fn new(input: &NewReservation, author: &str) -> Self {
+		InsertReservation {
+			name:        input.name.clone(),
+			description: input.description.clone(),
+			author:      author.to_string(),
+			rooms:       input.rooms.mask().unwrap_or(0),
+			begin_time:  input.begin_time,
+			end_time:    input.end_time,
+			layout:      input.layout as i32,
+			approved:    0,
+			people:      input.people as i32,
+			series_id:   None,
+		}
+	}
+}
+
+/// Model opakované rezervace (série) pro přidání do databáze
+///
+/// `reservation` je první výskyt, další určí `rrule`, viz [`crate::rgi::booking::recurrence`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewSeries {
+	/// první výskyt
+	#[serde(flatten)]
+	pub reservation: NewReservation,
+	/// pravidlo opakování podle RFC 5545, např. `FREQ=WEEKLY;BYDAY=MO,WE;COUNT=10`
+	pub rrule: String,
+	/// začátky výskytů, které se vynechají, ve stejném tvaru jako `begin_time`
+	#[serde(default)]
+	pub exdates: Vec<String>,
+	/// jen vrátí výskyty a kolize, nic neuloží
+	#[serde(default)]
+	pub dry_run: bool,
+}
+
+/// Model série, tak jak je uložena v databázi
+#[derive(Queryable, Debug, Clone, Serialize)]
+pub struct Series {
+	/// identifikátor
+	pub id: i32,
+	/// pravidlo opakování
+	pub rrule: String,
+	/// vynechané výskyty
+	#[serde(serialize_with = "as_json_text")]
+	pub exdates: String,
+	/// email autora
+	pub author: String,
+	/// čas vytvoření (UTC)
+	pub created: NaiveDateTime,
+}
+
+/// Model série pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "booking_series"]
+pub struct InsertSeries {
+	/// pravidlo opakování
+	pub rrule: String,
+	/// vynechané výskyty (JSON pole)
+	pub exdates: String,
+	/// email autora
+	pub author: String,
+}
+
+/// změny rezervace pro update v databázi, `None` = beze změny
+#[derive(Debug, Clone, Default, AsChangeset)]
+#[table_name = "booking"]
+pub struct ReservationChanges {
+	/// název události
+	pub name: Option<String>,
+	/// popis události
+	pub description: Option<String>,
+	/// místnosti (bitflagy)
+	pub rooms: Option<i32>,
+	/// počáteční čas (UTC)
+	pub begin_time: Option<NaiveDateTime>,
+	/// čas konce (UTC)
+	pub end_time: Option<NaiveDateTime>,
+	/// rozložení nábytku
+	pub layout: Option<i32>,
+	/// počet lidí
+	pub people: Option<i32>,
+}
+
+impl From<&UpdateReservation> for ReservationChanges {
+	fn from(input: &UpdateReservation) -> Self {
+		ReservationChanges {
+			name:        input.name.clone(),
+			description: input.description.clone(),
+			rooms:       input.rooms.as_ref().and_then(Rooms::mask),
+			begin_time:  input.begin_time,
+			end_time:    input.end_time,
+			layout:      input.layout.map(i32::from),
+			people:      input.people.map(i32::from),
+		}
+	}
+}
+
+impl ReservationChanges {
+	/// nemění nic?
+	pub fn is_empty(&self) -> bool {
+		self.name.is_none()
+			&& self.description.is_none()
+			&& self.rooms.is_none()
+			&& self.begin_time.is_none()
+			&& self.end_time.is_none()
+			&& self.layout.is_none()
+			&& self.people.is_none()
+	}
+}
+
+/// Model rozložení nábytku, viz [`crate::rgi::booking::layouts`]
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable)]
+pub struct Layout {
+	/// identifikátor, na který odkazuje `layout` rezervace
+	pub id: i32,
+	/// název
+	pub name: String,
+	/// popis
+	pub description: String,
+	/// odkaz na nákres rozložení (URL nebo cesta k obrázku)
+	pub diagram: Option<String>,
+}
+
+/// kapacita rozložení v jedné kombinaci místností
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Queryable)]
+pub struct Capacity {
+	/// místnosti (bitflagy jako u rezervace)
+	pub rooms: i32,
+	/// kolik lidí se v této kombinaci vejde
+	pub capacity: i32,
+}
+
+/// požadavek na vytvoření rozložení
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewLayout {
+	/// název
+	pub name: String,
+	/// popis
+	#[serde(default)]
+	pub description: String,
+	/// odkaz na nákres
+	#[serde(default)]
+	pub diagram: Option<String>,
+	/// kombinace místností, ve kterých jde rozložení použít, jinde použít nejde
+	pub capacities: Vec<Capacity>,
+}
+
+/// úprava rozložení, `None` = beze změny
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpdateLayout {
+	/// název
+	pub name: Option<String>,
+	/// popis
+	pub description: Option<String>,
+	/// odkaz na nákres, prázdný string nákres odebere
+	pub diagram: Option<String>,
+	/// nahradí všechny kapacity
+	pub capacities: Option<Vec<Capacity>>,
+}
+
+/// Model rozložení pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "layouts"]
+pub struct InsertLayout {
+	/// název
+	pub name: String,
+	/// popis
+	pub description: String,
+	/// odkaz na nákres
+	pub diagram: Option<String>,
+}
+
+/// změny rozložení pro update v databázi
+#[derive(Debug, Clone, Default, AsChangeset)]
+#[table_name = "layouts"]
+pub struct LayoutChanges {
+	/// název
+	pub name: Option<String>,
+	/// popis
+	pub description: Option<String>,
+	/// odkaz na nákres, `Some(None)` ho smaže
+	pub diagram: Option<Option<String>>,
+}
+
+impl LayoutChanges {
+	/// nemění nic?
+	pub fn is_empty(&self) -> bool {
+		self.name.is_none() && self.description.is_none() && self.diagram.is_none()
+	}
+}
+
+/// Model kapacity pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "layout_capacities"]
+pub struct InsertCapacity {
+	/// rozložení
+	pub layout_id: i32,
+	/// místnosti
+	pub rooms: i32,
+	/// kapacita
+	pub capacity: i32,
+}
+
 /// Model usera
 #[derive(Serialize, Deserialize, Debug, Clone, Queryable)]
 #[allow(dead_code)]
@@ -118,6 +418,8 @@ pub struct User {
 	pub email: String,
 	/// role
 	pub role: String,
+	/// zda byl účet aktivován (0 = čeká na facility managera)
+	pub active: i32,
 }
 
 /// Model usera pro vložení do databáze
@@ -131,4 +433,214 @@ pub struct NewUser {
 	pub email: String,
 	/// role
 	pub role: String,
+	/// zda je účet aktivní
+	pub active: i32,
+}
+
+/// změna role uživatele
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UpdateRole {
+	/// nová role, viz [`crate::auth::roles`]
+	pub role: String,
+}
+
+/// Model pozvánky
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable)]
+pub struct Invitation {
+	/// identifikátor
+	pub id: i32,
+	/// email pozvaného (malými písmeny)
+	pub email: String,
+	/// role, se kterou bude uživatel založen
+	pub role: String,
+	/// email facility managera, který pozvánku vytvořil
+	pub invited_by: String,
+	/// čas vytvoření
+	pub created: NaiveDateTime,
+}
+
+/// Model pozvánky pro vložení do databáze
+#[derive(Serialize, Deserialize, Debug, Clone, Insertable)]
+#[table_name = "invitations"]
+pub struct NewInvitation {
+	/// email pozvaného
+	pub email: String,
+	/// role
+	pub role: String,
+	/// kdo zve
+	pub invited_by: String,
+}
+
+/// požadavek na vytvoření pozvánky
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvitationRequest {
+	/// email pozvaného
+	pub email: String,
+	/// role, výchozí je `noob`
+	pub role: Option<String>,
+}
+
+/// Model API klíče, viz [`crate::auth::api_keys`]
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable)]
+pub struct ApiKey {
+	/// identifikátor
+	pub id: i32,
+	/// popis, k čemu klíč slouží
+	pub name: String,
+	/// veřejná část klíče
+	pub prefix: String,
+	/// SHA-256 tajné části klíče
+	#[serde(skip_serializing, default)]
+	pub secret_hash: String,
+	/// scopes oddělené čárkou
+	pub scopes: String,
+	/// email facility managera, který klíč vytvořil
+	pub created_by: String,
+	/// čas vytvoření (UTC)
+	pub created: NaiveDateTime,
+	/// čas vypršení (UTC), `None` = nevyprší
+	pub expires: Option<NaiveDateTime>,
+	/// kdy byl klíč naposledy použit (UTC)
+	pub last_used: Option<NaiveDateTime>,
+	/// zda byl klíč zneplatněn
+	pub revoked: i32,
+}
+
+/// Model API klíče pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "api_keys"]
+pub struct NewApiKey {
+	/// popis
+	pub name: String,
+	/// veřejná část klíče
+	pub prefix: String,
+	/// SHA-256 tajné části
+	pub secret_hash: String,
+	/// scopes oddělené čárkou
+	pub scopes: String,
+	/// kdo klíč vytvořil
+	pub created_by: String,
+	/// čas vypršení
+	pub expires: Option<NaiveDateTime>,
+}
+
+/// požadavek na vytvoření API klíče
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyRequest {
+	/// popis, k čemu klíč slouží
+	pub name: String,
+	/// scopes, viz [`crate::auth::api_keys::Scope`]
+	pub scopes: Vec<String>,
+	/// čas vypršení (UTC), např. `2020-06-30T00:00:00`
+	pub expires: Option<NaiveDateTime>,
+}
+
+/// Model záznamu auditního logu, viz [`crate::audit`]
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct AuditEntry {
+	/// identifikátor
+	pub id: i32,
+	/// email toho, kdo změnu provedl
+	pub actor: String,
+	/// akce, např. `booking.approve`
+	pub action: String,
+	/// druh cíle
+	pub target_type: String,
+	/// id cíle
+	pub target_id: Option<i32>,
+	/// stav před změnou
+	#[serde(serialize_with = "as_json")]
+	pub before: Option<String>,
+	/// stav po změně
+	#[serde(serialize_with = "as_json")]
+	pub after: Option<String>,
+	/// změněná pole ve tvaru `{"pole": [před, po]}`
+	#[serde(serialize_with = "as_json")]
+	pub diff: Option<String>,
+	/// čas změny (UTC)
+	pub created: NaiveDateTime,
+	/// id requestu
+	pub request_id: String,
+}
+
+/// Model záznamu auditního logu pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditEntry {
+	/// kdo
+	pub actor: String,
+	/// co
+	pub action: String,
+	/// druh cíle
+	pub target_type: String,
+	/// id cíle
+	pub target_id: Option<i32>,
+	/// stav před (JSON)
+	pub before: Option<String>,
+	/// stav po (JSON)
+	pub after: Option<String>,
+	/// rozdíl (JSON)
+	pub diff: Option<String>,
+	/// id requestu
+	pub request_id: String,
+}
+
+/// Model asynchronní RGI úlohy, viz [`crate::rgi::jobs`]
+#[derive(Serialize, Debug, Clone, Queryable)]
+pub struct Job {
+	/// identifikátor
+	pub id: i32,
+	/// cesta ke skriptu
+	#[serde(skip)]
+	pub script: String,
+	/// metoda předaná skriptu
+	#[serde(skip)]
+	pub method: String,
+	/// `queued`, `running`, `done` nebo `failed`
+	pub status: String,
+	/// odpověď skriptu, u chyby další pole chyby (např. `conflicts`)
+	#[serde(serialize_with = "as_json")]
+	pub result: Option<String>,
+	/// chybová zpráva
+	pub error: Option<String>,
+	/// HTTP status, který by vrátilo synchronní volání
+	pub code: Option<i32>,
+	/// email toho, kdo úlohu založil (`None` u veřejných endpointů)
+	#[serde(skip)]
+	pub created_by: Option<String>,
+	/// čas založení (UTC)
+	pub created: NaiveDateTime,
+	/// čas spuštění (UTC)
+	pub started: Option<NaiveDateTime>,
+	/// čas dokončení (UTC)
+	pub finished: Option<NaiveDateTime>,
+}
+
+/// Model asynchronní RGI úlohy pro vložení do databáze
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "rgi_jobs"]
+pub struct NewJob {
+	/// cesta ke skriptu
+	pub script: String,
+	/// metoda předaná skriptu
+	pub method: String,
+	/// počáteční stav
+	pub status: String,
+	/// kdo úlohu zakládá
+	pub created_by: Option<String>,
+}
+
+/// serializuje uložený JSON string jako JSON, ne jako string
+fn as_json<S: Serializer>(raw: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+	raw.as_ref().and_then(|r| serde_json::from_str::<serde_json::Value>(r).ok()).serialize(s)
+}
+
+/// výchozí stav rezervace, pokud ho RGI nevrátí
+fn pending() -> String {
+	"pending".to_string()
+}
+
+/// jako [`as_json`], jen pro povinný sloupec
+fn as_json_text<S: Serializer>(raw: &str, s: S) -> Result<S::Ok, S::Error> {
+	serde_json::from_str::<serde_json::Value>(raw).unwrap_or(serde_json::Value::Null).serialize(s)
 }
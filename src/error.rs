@@ -0,0 +1,86 @@
+//! společný typ chyby pro JSON endpointy
+//!
+//! chyba se klientovi posílá jako `{"error": "..."}` s odpovídajícím HTTP statusem
+//! ```no_run
+//! #[get("/neco/<id>")]
+//! pub fn neco(id: i32) -> ApiResult<Neco> {
+//!     if id < 0 {
+//!         Err(ApiError::new(Status::BadRequest, "id must not be negative"))?
+//!     }
+//!     ...
+//! }
+//! ```
+//! další pole do odpovědi jde přidat přes [`ApiError::with`]
+
+use std::fmt;
+
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, status, Responder};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// výsledek JSON endpointu
+pub type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// chyba vrácená klientovi
+#[derive(Debug, Clone)]
+pub struct ApiError {
+	/// HTTP status
+	pub status: Status,
+	/// zpráva pro klienta
+	pub message: String,
+	/// další pole odpovědi vedle `error`
+	pub details: Map<String, Value>,
+}
+
+impl ApiError {
+	/// sestrojí chybu s daným statusem a zprávou
+	pub fn new<S: Into<String>>(status: Status, message: S) -> Self {
+		ApiError { status, message: message.into(), details: Map::new() }
+	}
+
+	/// přidá do odpovědi další pole
+	pub fn with<T: Serialize>(mut self, key: &str, value: T) -> Self {
+		self.details.insert(key.to_string(), serde_json::to_value(value).unwrap_or(Value::Null));
+		self
+	}
+
+	/// 404 s danou zprávou
+	pub fn not_found<S: Into<String>>(message: S) -> Self {
+		ApiError::new(Status::NotFound, message)
+	}
+
+	/// 422 s danou zprávou
+	pub fn invalid<S: Into<String>>(message: S) -> Self {
+		ApiError::new(Status::UnprocessableEntity, message)
+	}
+}
+
+impl fmt::Display for ApiError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {}", self.status, self.message)
+	}
+}
+
+impl From<diesel::result::Error> for ApiError {
+	fn from(e: diesel::result::Error) -> Self {
+		match e {
+			diesel::result::Error::NotFound => ApiError::not_found("not found"),
+			e => {
+				eprintln!("database error: {}", e);
+				ApiError::new(Status::InternalServerError, "database error")
+			}
+		}
+	}
+}
+
+impl<'r> Responder<'r> for ApiError {
+	fn respond_to(self, req: &Request) -> response::Result<'r> {
+		let mut body = self.details;
+		body.insert("error".to_string(), Value::String(self.message));
+
+		status::Custom(self.status, Json(Value::Object(body))).respond_to(req)
+	}
+}
@@ -16,6 +16,7 @@
 //! ├── Dockerfile  - dockerfile
 //! ├── frontend    - submodul s frontendem
 //! ├── Makefile    - make
+//! ├── permissions.toml - matice oprávnění (role -> akce)
 //! ├── migrations  - migrace
 //! │   ├── 00000000000000_diesel_initial_setup
 //! │   │   ├── down.sql
@@ -26,18 +27,47 @@
 //! ├── README.md   - README
 //! ├── rgi         - obsahuje RGI, viz modul rgi
 //! │   ├── booking - booking rgi
+//! │   │   ├── approval.rs - schvalování rezervací
+//! │   │   ├── availability.rs - hledání volných termínů
+//! │   │   ├── backend.rs - úložiště rezervací (Diesel nebo RGI)
 //! │   │   ├── booking.py
+//! │   │   ├── conflicts.rs - kolize rezervací
 //! │   │   ├── curltest
-//! │   │   └── mod.rs
+//! │   │   ├── layouts.rs - katalog rozložení nábytku
+//! │   │   ├── mod.rs
+//! │   │   ├── query.rs - výpis rezervací po stránkách
+//! │   │   ├── recurrence.rs - opakování rezervací (RRULE)
+//! │   │   ├── rooms.rs - katalog místností
+//! │   │   └── series.rs - opakované rezervace (série)
+//! │   ├── envelope.rs          - obálka požadavku pro RGI
+//! │   ├── envelope.schema.json - JSON Schema obálky
+//! │   ├── jobs.rs              - asynchronní RGI úlohy
+//! │   ├── manifest.rs          - deklarativní RGI endpointy
+//! │   ├── manifest.toml        - manifest RGI endpointů
+//! │   ├── pool.rs              - perzistentní RGI workery
+//! │   ├── response.rs          - typované odpovědi RGI
+//! │   ├── runner.rs            - spouštění RGI procesů
+//! │   ├── sandbox.rs           - omezení RGI procesů
 //! │   └── mod.rs
 //! ├── Rocket.toml  - konfigurační soubor Rocketu
 //! ├── rustfmt.toml - pravidla pro automatické formátování kódu
 //! └── src          - zdrojové soubory
+//!     ├── audit.rs         - auditní log změn
+//!     ├── auth             - autentifikace a role
+//!     │   ├── api_keys.rs  - API klíče pro servisní účty
+//!     │   ├── jwt.rs       - podepsané tokeny
+//!     │   ├── oidc.rs      - ověřování OpenID Connect ID tokenů
+//!     │   ├── permissions.rs - matice oprávnění
+//!     │   ├── provisioning.rs - zakládání nových uživatelů
+//!     │   └── mod.rs
+//!     ├── datetime.rs      - časy rezervací (UTC, Europe/Prague)
 //!     ├── db.rs            - databázové modely, utility
+//!     ├── error.rs         - společný typ chyby pro JSON endpointy
 //!     ├── main.rs          - entrypoint programu
 //!     ├── rgi -> ../rgi/   - symlink
 //!     ├── schema.rs        - schéma databáze (vygenerováno Dieselem, neupravovat!)
-//!     └── static_server.rs - statický server
+//!     ├── static_server.rs - statický server
+//!     └── users.rs         - správa uživatelů
 //! ```
 #![feature(proc_macro_hygiene, decl_macro)]
 #![deny(missing_docs)]
@@ -51,15 +81,20 @@ extern crate diesel;
 extern crate dotenv;
 
 use dotenv::dotenv;
+use rocket::fairing::AdHoc;
 use rocket::http::Method;
 use rocket_cors::{AllowedHeaders, AllowedOrigins, Error};
 
 use std::str::FromStr;
 
+pub mod audit;
 pub mod auth;
 pub mod rgi;
+pub mod datetime;
 pub mod db;
+pub mod error;
 pub mod static_server;
+pub mod users;
 
 /// schéma databáze (vygenerováno Dieselem)
 #[allow(missing_docs)]
@@ -87,9 +122,96 @@ pub fn init() -> rocket::Rocket {
 	.unwrap();
 
 	rocket::ignite()
-		.register(catchers![static_server::not_found])
-		.mount("/", routes![static_server::index, static_server::frontend, static_server::favicon, auth::me])
-		.mount("/rgi/", rgi::routes())
+		.register(catchers![static_server::not_found, auth::unauthorized_catcher, auth::forbidden_catcher])
+		.mount(
+			"/",
+			routes![
+				static_server::index,
+				static_server::frontend,
+				static_server::favicon
+			],
+		)
+		.mount("/", auth::routes())
+		.mount("/", users::routes())
+		.mount("/", auth::api_keys::routes())
+		.mount("/", audit::routes())
 		.attach(cors)
 		.attach(DbConn::fairing())
+		.attach(AdHoc::on_response("request id", audit::RequestId::echo))
+		.attach(AdHoc::on_attach("auth keys", |rocket| match auth::jwt::Keys::from_config(rocket.config()) {
+			Ok(keys) => Ok(rocket.manage(keys)),
+			Err(e) => {
+				eprintln!("invalid auth configuration: {}", e);
+				Err(rocket)
+			}
+		}))
+		.attach(AdHoc::on_attach("permissions", |rocket| match auth::permissions::Permissions::from_config(rocket.config()) {
+			Ok(perms) => Ok(rocket.manage(perms)),
+			Err(e) => {
+				eprintln!("invalid permissions configuration: {}", e);
+				Err(rocket)
+			}
+		}))
+		.attach(AdHoc::on_attach("provisioning", |rocket| match auth::provisioning::Policy::from_config(rocket.config()) {
+			Ok(policy) => Ok(rocket.manage(policy)),
+			Err(e) => {
+				eprintln!("invalid provisioning configuration: {}", e);
+				Err(rocket)
+			}
+		}))
+		.attach(AdHoc::on_attach("RGI manifest", |rocket| match rgi::manifest::Manifest::from_config(rocket.config()) {
+			Ok(manifest) => Ok(rocket.mount("/rgi/", rgi::routes(&manifest))),
+			Err(e) => {
+				eprintln!("invalid RGI manifest: {}", e);
+				Err(rocket)
+			}
+		}))
+		.attach(AdHoc::on_attach("RGI runner", |rocket| match rgi::runner::Runner::from_config(rocket.config()) {
+			Ok(runner) => Ok(rocket.manage(runner)),
+			Err(e) => {
+				eprintln!("invalid RGI configuration: {}", e);
+				Err(rocket)
+			}
+		}))
+		.attach(AdHoc::on_attach("RGI jobs", |rocket| {
+			let runner = rocket.state::<rgi::runner::Runner>().cloned().expect("RGI runner is attached first");
+			match rgi::jobs::Jobs::from_config(rocket.config(), runner) {
+				Ok(jobs) => Ok(rocket.manage(jobs)),
+				Err(e) => {
+					eprintln!("invalid RGI jobs configuration: {}", e);
+					Err(rocket)
+				}
+			}
+		}))
+		.attach(AdHoc::on_launch("RGI jobs recovery", |_| match rgi::jobs::recover() {
+			Ok(0) => (),
+			Ok(n) => eprintln!("{} unfinished RGI jobs marked as failed", n),
+			Err(e) => eprintln!("can't recover RGI jobs: {}", e),
+		}))
+		.attach(AdHoc::on_attach("booking backend", |rocket| {
+			let runner = rocket.state::<rgi::runner::Runner>().cloned().expect("RGI runner is attached first");
+			match rgi::booking::backend::from_config(rocket.config(), runner) {
+				Ok(backend) => Ok(rocket.manage(backend)),
+				Err(e) => {
+					eprintln!("invalid booking configuration: {}", e);
+					Err(rocket)
+				}
+			}
+		}))
+		.attach(AdHoc::on_attach("opening hours", |rocket| {
+			match rgi::booking::availability::OpeningHours::from_config(rocket.config()) {
+				Ok(hours) => Ok(rocket.manage(hours)),
+				Err(e) => {
+					eprintln!("invalid opening hours: {}", e);
+					Err(rocket)
+				}
+			}
+		}))
+		.attach(AdHoc::on_attach("OpenID Connect", |rocket| match auth::oidc::Verifier::from_config(rocket.config()) {
+			Ok(verifier) => Ok(rocket.manage(verifier)),
+			Err(e) => {
+				eprintln!("invalid OpenID Connect configuration: {}", e);
+				Err(rocket)
+			}
+		}))
 }
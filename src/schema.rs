@@ -1,3 +1,33 @@
+table! {
+	api_keys (id) {
+		id -> Integer,
+		name -> Text,
+		prefix -> Text,
+		secret_hash -> Text,
+		scopes -> Text,
+		created_by -> Text,
+		created -> Timestamp,
+		expires -> Nullable<Timestamp>,
+		last_used -> Nullable<Timestamp>,
+		revoked -> Integer,
+	}
+}
+
+table! {
+	audit_log (id) {
+		id -> Integer,
+		actor -> Text,
+		action -> Text,
+		target_type -> Text,
+		target_id -> Nullable<Integer>,
+		before -> Nullable<Text>,
+		after -> Nullable<Text>,
+		diff -> Nullable<Text>,
+		created -> Timestamp,
+		request_id -> Text,
+	}
+}
+
 table! {
 	booking (id) {
 		id -> Integer,
@@ -5,11 +35,93 @@ table! {
 		description -> Text,
 		author -> Text,
 		rooms -> Integer,
-		begin_time -> Text,
-		end_time -> Text,
+		begin_time -> Timestamp,
+		end_time -> Timestamp,
 		layout -> Integer,
 		approved -> Integer,
 		people -> Integer,
+		series_id -> Nullable<Integer>,
+		status -> Text,
+		reason -> Nullable<Text>,
+		approved_by -> Nullable<Text>,
+		decided_at -> Nullable<Timestamp>,
+	}
+}
+
+table! {
+	booking_series (id) {
+		id -> Integer,
+		rrule -> Text,
+		exdates -> Text,
+		author -> Text,
+		created -> Timestamp,
+	}
+}
+
+table! {
+	invitations (id) {
+		id -> Integer,
+		email -> Text,
+		role -> Text,
+		invited_by -> Text,
+		created -> Timestamp,
+	}
+}
+
+table! {
+	layout_capacities (layout_id, rooms) {
+		layout_id -> Integer,
+		rooms -> Integer,
+		capacity -> Integer,
+	}
+}
+
+table! {
+	layouts (id) {
+		id -> Integer,
+		name -> Text,
+		description -> Text,
+		diagram -> Nullable<Text>,
+	}
+}
+
+table! {
+	reservation_rooms (reservation_id, room_id) {
+		reservation_id -> Integer,
+		room_id -> Integer,
+	}
+}
+
+table! {
+	rgi_jobs (id) {
+		id -> Integer,
+		script -> Text,
+		method -> Text,
+		status -> Text,
+		result -> Nullable<Text>,
+		error -> Nullable<Text>,
+		code -> Nullable<Integer>,
+		created_by -> Nullable<Text>,
+		created -> Timestamp,
+		started -> Nullable<Timestamp>,
+		finished -> Nullable<Timestamp>,
+	}
+}
+
+table! {
+	room_parts (room_id, part_id) {
+		room_id -> Integer,
+		part_id -> Integer,
+	}
+}
+
+table! {
+	rooms (id) {
+		id -> Integer,
+		name -> Text,
+		description -> Text,
+		capacity -> Integer,
+		bit -> Nullable<Integer>,
 	}
 }
 
@@ -19,7 +131,26 @@ table! {
 		name -> Text,
 		email -> Text,
 		role -> Text,
+		active -> Integer,
 	}
 }
 
-allow_tables_to_appear_in_same_query!(booking, users,);
+joinable!(booking -> booking_series (series_id));
+joinable!(layout_capacities -> layouts (layout_id));
+joinable!(reservation_rooms -> booking (reservation_id));
+joinable!(reservation_rooms -> rooms (room_id));
+
+allow_tables_to_appear_in_same_query!(
+	api_keys,
+	audit_log,
+	booking,
+	booking_series,
+	invitations,
+	layout_capacities,
+	layouts,
+	reservation_rooms,
+	rgi_jobs,
+	room_parts,
+	rooms,
+	users,
+);
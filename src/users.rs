@@ -0,0 +1,189 @@
+//! správa uživatelů
+//!
+//! všechny endpointy vyžadují oprávnění [`UserManage`]
+//!
+//! - `GET /users?<q>` -> seznam uživatelů, `q` hledá v jméně a emailu
+//! - `GET /users/<id>` -> jeden uživatel
+//! - `PATCH /users/<id>/role` -> změna role, data: [`UpdateRole`]
+//! - `POST /users/<id>/activate` -> aktivace čekajícího účtu
+//! - `GET /invitations` -> seznam pozvánek
+//! - `POST /invitations` -> nová pozvánka, data: [`InvitationRequest`]
+//! - `DELETE /invitations/<id>` -> zrušení pozvánky
+
+use rocket::http::Status;
+use rocket::Route;
+use rocket_contrib::json::Json;
+
+use diesel::prelude::*;
+
+use crate::auth::roles::{self, FacilityManager, Role};
+use crate::audit::{self, RequestId, Target};
+use crate::auth::permissions::actions::UserManage;
+use crate::auth::permissions::Permitted;
+use crate::db;
+use crate::db::{Invitation, InvitationRequest, NewInvitation, UpdateRole, User};
+use crate::error::{ApiError, ApiResult};
+
+/// vrací seznam uživatelů, volitelně filtrovaný podle jména nebo emailu
+///
+/// GET /users?<q> application/json
+#[get("/users?<q>")]
+pub fn list(q: Option<String>, _u: Permitted<UserManage>) -> ApiResult<Vec<User>> {
+	use crate::schema::users::dsl::*;
+
+	let con = db::get_con();
+	let mut query = users.order(id).into_boxed();
+
+	if let Some(q) = q {
+		let pattern = format!("%{}%", escape_like(q.trim()));
+		query = query.filter(name.like(pattern.clone()).escape('\\').or(email.like(pattern).escape('\\')));
+	}
+
+	Ok(Json(query.load::<User>(&con)?))
+}
+
+/// vrací jednoho uživatele
+///
+/// GET /users/<id> application/json
+#[get("/users/<u_id>")]
+pub fn get(u_id: i32, _u: Permitted<UserManage>) -> ApiResult<User> {
+	use crate::schema::users::dsl::*;
+
+	let con = db::get_con();
+	users.find(u_id).first::<User>(&con).optional()?.map(Json).ok_or_else(|| ApiError::not_found("no such user"))
+}
+
+/// změní roli uživatele
+///
+/// PATCH /users/<id>/role application/json
+///
+/// role musí být jedna z [`roles::ALL`] (case-insensitive), poslední
+/// aktivní facility manager nemůže být degradován
+///
+/// data: [`UpdateRole`]
+#[patch("/users/<u_id>/role", data = "<input>")]
+pub fn set_role(u_id: i32, input: Json<UpdateRole>, usr: Permitted<UserManage>, req_id: RequestId) -> ApiResult<User> {
+	use crate::schema::users::dsl::*;
+
+	let new_role = roles::canonical(&input.role)
+		.ok_or_else(|| ApiError::invalid(format!("unknown role `{}`", input.role)))?;
+
+	let con = db::get_con();
+	let (before, after) = con.immediate_transaction(|| {
+		let user = users.find(u_id).first::<User>(&con).optional()?.ok_or_else(|| ApiError::not_found("no such user"))?;
+
+		// neaktivní facility manager se mezi aktivní nepočítá, jeho degradace je vždy v pořádku
+		if user.active == 1 && is_manager(&user.role) && !is_manager(&new_role) {
+			let managers =
+				users.filter(active.eq(1)).select(role).load::<String>(&con)?.iter().filter(|r| is_manager(r)).count();
+
+			if managers <= 1 {
+				Err(ApiError::new(Status::Conflict, "can't demote the last facility manager"))?
+			}
+		}
+
+		diesel::update(users.find(u_id)).set(role.eq(&new_role)).execute(&con)?;
+		Ok::<_, ApiError>((user, users.find(u_id).first::<User>(&con)?))
+	})?;
+
+	// the audit log uses its own connection, so it has to be written after the transaction ends
+	audit::record(&usr.user, "user.role", Target::User(u_id), audit::snapshot(&before), audit::snapshot(&after), &req_id);
+	Ok(Json(after))
+}
+
+/// aktivuje účet čekající na schválení
+///
+/// POST /users/<id>/activate application/json
+#[post("/users/<u_id>/activate")]
+pub fn activate(u_id: i32, usr: Permitted<UserManage>, req_id: RequestId) -> ApiResult<User> {
+	use crate::schema::users::dsl::*;
+
+	let con = db::get_con();
+	let before = users.find(u_id).first::<User>(&con).optional()?.ok_or_else(|| ApiError::not_found("no such user"))?;
+
+	diesel::update(users.find(u_id)).set(active.eq(1)).execute(&con)?;
+	let after = users.find(u_id).first::<User>(&con)?;
+
+	audit::record(&usr.user, "user.activate", Target::User(u_id), audit::snapshot(&before), audit::snapshot(&after), &req_id);
+	Ok(Json(after))
+}
+
+/// vrací seznam nevyužitých pozvánek
+///
+/// GET /invitations application/json
+#[get("/invitations")]
+pub fn list_invitations(_u: Permitted<UserManage>) -> ApiResult<Vec<Invitation>> {
+	use crate::schema::invitations::dsl::*;
+
+	let con = db::get_con();
+	Ok(Json(invitations.order(id).load::<Invitation>(&con)?))
+}
+
+/// pozve uživatele, který se pak může přihlásit bez ohledu na povolené domény
+///
+/// POST /invitations application/json
+///
+/// data: [`InvitationRequest`]
+#[post("/invitations", data = "<input>")]
+pub fn invite(input: Json<InvitationRequest>, usr: Permitted<UserManage>, req_id: RequestId) -> ApiResult<Invitation> {
+	use crate::schema::invitations::dsl::*;
+
+	let input = input.into_inner();
+	let new_email = input.email.trim().to_lowercase();
+	if !new_email.contains('@') {
+		Err(ApiError::invalid(format!("`{}` is not an email address", input.email)))?
+	}
+
+	let new_role = match input.role {
+		Some(r) => roles::canonical(&r).ok_or_else(|| ApiError::invalid(format!("unknown role `{}`", r)))?,
+		None => "noob".to_string(),
+	};
+
+	let con = db::get_con();
+	let created = con.immediate_transaction(|| {
+		if invitations.filter(email.eq(&new_email)).first::<Invitation>(&con).optional()?.is_some() {
+			Err(ApiError::new(Status::Conflict, "this email is already invited"))?
+		}
+
+		diesel::insert_into(invitations)
+			.values(NewInvitation { email: new_email.clone(), role: new_role, invited_by: usr.user.email.clone() })
+			.execute(&con)?;
+
+		Ok::<_, ApiError>(invitations.filter(email.eq(&new_email)).first::<Invitation>(&con)?)
+	})?;
+
+	audit::record(&usr.user, "invitation.create", Target::Invitation(created.id), None, audit::snapshot(&created), &req_id);
+	Ok(Json(created))
+}
+
+/// zruší pozvánku
+///
+/// DELETE /invitations/<id>
+#[delete("/invitations/<i_id>")]
+pub fn uninvite(i_id: i32, usr: Permitted<UserManage>, req_id: RequestId) -> Result<(), ApiError> {
+	use crate::schema::invitations::dsl::*;
+
+	let con = db::get_con();
+	let before =
+		invitations.find(i_id).first::<Invitation>(&con).optional()?.ok_or_else(|| ApiError::not_found("no such invitation"))?;
+
+	diesel::delete(invitations.find(i_id)).execute(&con)?;
+
+	audit::record(&usr.user, "invitation.delete", Target::Invitation(i_id), audit::snapshot(&before), None, &req_id);
+	Ok(())
+}
+
+/// escapuje `%`, `_` a `\`, aby se hledaný text v LIKE bral doslova
+fn escape_like(q: &str) -> String {
+	q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// zjistí, zda je role facility manager
+fn is_manager(r: &str) -> bool {
+	r.eq_ignore_ascii_case(FacilityManager::name())
+}
+
+/// vrací seznam endpointů pro nabindování do Rocketu
+pub fn routes() -> Vec<Route> {
+	routes![list, get, set_role, activate, list_invitations, invite, uninvite]
+}
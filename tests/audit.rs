@@ -0,0 +1,24 @@
+extern crate backend;
+#[macro_use]
+extern crate serde_json;
+
+use backend::audit::{diff, Target};
+
+#[test]
+pub fn test_audit_diff() {
+	let before = json!({"id": 1, "name": "koncert", "approved": 0});
+	let after = json!({"id": 1, "name": "koncert", "approved": 1});
+
+	assert_eq!(diff(Some(&before), Some(&after)), Some(json!({"approved": [0, 1]})));
+	assert_eq!(diff(Some(&before), Some(&before)), Some(json!({})));
+
+	// vytvoření a smazání nemá diff
+	assert_eq!(diff(None, Some(&after)), None);
+	assert_eq!(diff(Some(&before), None), None);
+}
+
+#[test]
+pub fn test_audit_target() {
+	assert_eq!(Target::Booking(3).kind(), "booking");
+	assert_eq!(Target::ApiKey(7).id(), 7);
+}
@@ -0,0 +1,234 @@
+extern crate backend;
+extern crate jsonwebtoken;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use backend::auth::api_keys::Scope;
+use backend::auth::jwt::{Claims, Keys, TokenError};
+use backend::auth::oidc::Verifier;
+use backend::auth::permissions::Permissions;
+use backend::auth::provisioning::Policy;
+use backend::auth::roles::{self, Approver, FacilityManager, Noob};
+use backend::db::Invitation;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rocket::config::{Config, Environment};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+
+use common::{at, body, App, FakeRgi, BOOKING, MANAGER, NOOB};
+
+#[test]
+pub fn test_role_hierarchy() {
+	assert!(roles::inherits::<Noob>("noob"));
+	assert!(roles::inherits::<Noob>("Approver"));
+	assert!(roles::inherits::<Noob>("facilitymanager"));
+	assert!(roles::inherits::<FacilityManager>("FacilityManager"));
+
+	assert!(!roles::inherits::<Approver>("noob"));
+	assert!(!roles::inherits::<Approver>("facilitymanager"));
+	assert!(!roles::inherits::<FacilityManager>("approver"));
+	assert!(!roles::inherits::<Noob>("hacker"));
+}
+
+#[test]
+pub fn test_token_roundtrip() {
+	let keys = Keys::new("new", b"new secret", "auditorium-booking", 3600).with_key("old", b"old secret");
+
+	let token = keys.issue("Hozdič", "hozdic@example.com");
+	let claims = keys.verify(&token).unwrap();
+	assert_eq!(claims.email, "hozdic@example.com");
+	assert_eq!(claims.name, "Hozdič");
+
+	// tokeny podepsané starým klíčem musí projít i po rotaci
+	let old = Keys::new("old", b"old secret", "auditorium-booking", 3600);
+	assert!(keys.verify(&old.issue("a", "a@example.com")).is_ok());
+}
+
+#[test]
+pub fn test_token_rejected() {
+	let keys = Keys::new("k", b"secret", "auditorium-booking", 3600);
+
+	let forged = Keys::new("k", b"not the secret", "auditorium-booking", 3600);
+	assert_eq!(keys.verify(&forged.issue("a", "a@example.com")).unwrap_err(), TokenError::Forged);
+
+	let unknown = Keys::new("x", b"secret", "auditorium-booking", 3600);
+	assert_eq!(keys.verify(&unknown.issue("a", "a@example.com")).unwrap_err(), TokenError::UnknownKey);
+
+	let foreign = Keys::new("k", b"secret", "someone-else", 3600);
+	assert_eq!(keys.verify(&foreign.issue("a", "a@example.com")).unwrap_err(), TokenError::WrongAudience);
+
+	assert_eq!(keys.verify("definitely.not.jwt").unwrap_err(), TokenError::Malformed);
+}
+
+#[test]
+pub fn test_token_expired() {
+	let keys = Keys::new("k", b"secret", "auditorium-booking", 3600);
+
+	let mut header = Header::new(Algorithm::HS256);
+	header.kid = Some("k".to_string());
+	let claims = Claims {
+		sub: "a@example.com".to_string(),
+		name: "a".to_string(),
+		email: "a@example.com".to_string(),
+		aud: "auditorium-booking".to_string(),
+		iat: 1_000_000,
+		exp: 1_003_600,
+	};
+	let token = encode(&header, &claims, &EncodingKey::from_secret(b"secret")).unwrap();
+
+	assert_eq!(keys.verify(&token).unwrap_err(), TokenError::Expired);
+}
+
+/// vytvoří ID token podepsaný testovacím klíčem z `tests/fixtures`
+fn id_token(claims: serde_json::Value) -> String {
+	let mut header = Header::new(Algorithm::RS256);
+	header.kid = Some("test-key".to_string());
+	let key = EncodingKey::from_rsa_pem(include_bytes!("fixtures/oidc_key.pem")).unwrap();
+	encode(&header, &claims, &key).unwrap()
+}
+
+#[test]
+pub fn test_oidc_id_token() {
+	let config = Config::build(Environment::Development)
+		.extra("oidc_jwks", "tests/fixtures/oidc_jwks.json")
+		.extra("oidc_client_id", "test-client")
+		.extra("oidc_hosted_domain", "gjk.cz")
+		.finalize()
+		.unwrap();
+	let verifier = Verifier::from_config(&config).unwrap().unwrap();
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+	let claims = json!({
+		"iss": "https://accounts.google.com",
+		"aud": "test-client",
+		"sub": "1234",
+		"email": "hozdic@gjk.cz",
+		"email_verified": true,
+		"name": "Lukáš",
+		"hd": "gjk.cz",
+		"iat": now,
+		"exp": now + 3600,
+	});
+	let raw = verifier.verify(&id_token(claims.clone())).unwrap();
+	assert_eq!(raw.email, "hozdic@gjk.cz");
+	assert_eq!(raw.name, "Lukáš");
+
+	let mut other = claims.clone();
+	other["iss"] = json!("https://evil.example.com");
+	assert_eq!(verifier.verify(&id_token(other)).unwrap_err(), TokenError::WrongIssuer);
+
+	let mut other = claims.clone();
+	other["aud"] = json!("another-client");
+	assert_eq!(verifier.verify(&id_token(other)).unwrap_err(), TokenError::WrongAudience);
+
+	let mut other = claims.clone();
+	other["hd"] = json!("gmail.com");
+	assert_eq!(verifier.verify(&id_token(other)).unwrap_err(), TokenError::WrongDomain);
+
+	let mut other = claims.clone();
+	other["email_verified"] = json!(false);
+	assert_eq!(verifier.verify(&id_token(other)).unwrap_err(), TokenError::UnverifiedEmail);
+
+	let mut other = claims.clone();
+	other.as_object_mut().unwrap().remove("email_verified");
+	assert_eq!(verifier.verify(&id_token(other)).unwrap_err(), TokenError::UnverifiedEmail);
+}
+
+#[test]
+pub fn test_provisioning_policy() {
+	let open = Policy::new(vec![], false);
+	assert_eq!(open.admit("kdokoliv@gmail.com", None), Ok(("noob".to_string(), true)));
+
+	let school = Policy::new(vec!["GJK.cz".to_string()], true);
+	assert_eq!(school.admit("student@gjk.cz", None), Ok(("noob".to_string(), false)));
+	assert!(school.admit("cizinec@gmail.com", None).is_err());
+	// servisní účty API klíčů ani jiné identity bez zavináče se nezakládají
+	assert!(open.admit("apikey:0a1b2c", None).is_err());
+	assert!(open.admit("novak", None).is_err());
+
+	let invitation = Invitation {
+		id:         1,
+		email:      "cizinec@gmail.com".to_string(),
+		role:       "approver".to_string(),
+		invited_by: "reditel@gjk.cz".to_string(),
+		created:    at("2019-12-02T10:15"),
+	};
+	assert_eq!(school.admit("cizinec@gmail.com", Some(&invitation)), Ok(("approver".to_string(), true)));
+}
+
+#[test]
+pub fn test_api_key_scopes() {
+	assert_eq!(Scope::parse("events:read"), Some(Scope::EventsRead));
+	assert_eq!(Scope::parse(" admin"), Some(Scope::Admin));
+	assert_eq!(Scope::parse("root"), None);
+
+	// scope dávají jednotlivé akce, ne celé role
+	assert!(Scope::allow(&[Scope::Approve], "booking.approve"));
+	assert!(!Scope::allow(&[Scope::Approve], "booking.create"));
+	assert!(!Scope::allow(&[Scope::Admin], "booking.approve"));
+	assert!(Scope::allow(&[Scope::EventsRead, Scope::Admin], "user.manage"));
+	assert!(!Scope::allow(&[Scope::EventsRead, Scope::Approve, Scope::Admin], "apikey.manage"));
+}
+
+#[test]
+pub fn test_api_keys() {
+	let event = json!({
+		"id": 1, "name": "koncert", "description": "", "author": "novak@gjk.cz", "author_name": null, "rooms": 3,
+		"begin_time": "2019-12-12T17:00:00Z", "end_time": "2019-12-12T19:00:00Z",
+		"layout": 0, "approved": 0, "people": 100, "status": "pending"
+	});
+	let rgi = FakeRgi::new().reply(BOOKING, "GET", event).reply(BOOKING, "APPROVE", json!({ "result": 0 }));
+	let app = App::new(&rgi);
+	let cli = &app.client;
+	let key = |scopes: Value| {
+		let post = cli.post("/apikeys").header(ContentType::JSON).header(app.auth(MANAGER));
+		let mut response = post.body(json!({ "name": "cedule", "scopes": scopes }).to_string()).dispatch();
+		assert_eq!(response.status(), Status::Ok);
+		Header::new("Authorization", format!("ApiKey {}", body(&mut response)["key"].as_str().unwrap()))
+	};
+	let (approver, admin) = (key(json!(["approve"])), key(json!(["admin", "events:read"])));
+
+	// schvalovací klíč schvaluje, ale rezervace nezakládá
+	assert_eq!(cli.post("/rgi/events/1/approve").header(approver.clone()).dispatch().status(), Status::Ok);
+	let post = cli.post("/rgi/events").header(ContentType::JSON).header(approver.clone());
+	let new = json!({
+		"name": "přednáška", "description": "", "rooms": 1,
+		"begin_time": "2019-12-13T10:00", "end_time": "2019-12-13T11:00", "layout": 0, "people": 10
+	});
+	assert_eq!(post.body(new.to_string()).dispatch().status(), Status::Forbidden);
+	assert_eq!(rgi.count(BOOKING, "POST"), 0);
+	assert_eq!(cli.get("/users").header(approver.clone()).dispatch().status(), Status::Forbidden);
+
+	// admin klíč spravuje uživatele, ale neschvaluje a nové klíče nevydá
+	assert_eq!(cli.get("/users").header(admin.clone()).dispatch().status(), Status::Ok);
+	assert_eq!(cli.post("/rgi/events/1/approve").header(admin.clone()).dispatch().status(), Status::Forbidden);
+	let mint = cli.post("/apikeys").header(ContentType::JSON).header(admin.clone());
+	let response = mint.body(json!({ "name": "další", "scopes": ["admin"] }).to_string()).dispatch();
+	assert_eq!(response.status(), Status::Forbidden);
+	assert_eq!(cli.get("/apikeys").header(admin).dispatch().status(), Status::Forbidden);
+
+	// klíč se nevymění za token, který by neznal jeho scope
+	assert_eq!(cli.post("/token/refresh").header(approver.clone()).dispatch().status(), Status::Forbidden);
+	assert_eq!(cli.post("/token/refresh").header(app.auth(NOOB)).dispatch().status(), Status::Ok);
+}
+
+#[test]
+pub fn test_permission_matrix() {
+	let perms = Permissions::from_toml(include_str!("../permissions.toml")).unwrap();
+
+	assert!(perms.allows("noob", "booking.create"));
+	assert!(!perms.allows("noob", "booking.approve"));
+	assert!(perms.allows("approver", "booking.create"));
+	assert!(perms.allows("FacilityManager", "booking.create"));
+	assert!(!perms.allows("FacilityManager", "booking.approve"));
+	assert!(!perms.allows("hacker", "booking.create"));
+
+	assert_eq!(perms.effective("noob"), vec!["booking.create", "booking.edit_own"]);
+
+	assert!(Permissions::from_toml("[roles]\nroot = [\"user.manage\"]").is_err());
+}
@@ -0,0 +1,159 @@
+extern crate backend;
+extern crate chrono;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use backend::datetime;
+use backend::rgi::booking::availability::{free_slots, search, AvailabilityQuery, OpeningHours, Slot};
+use backend::rgi::booking::backend::{BookingBackend, DieselBackend};
+use chrono::Duration;
+use rocket::http::Status;
+use serde_json::Value;
+
+use common::{at, body, reservation, user, App, FakeRgi, APPROVER, BOOKING, NOOB};
+
+fn slot(begin_time: &str, end_time: &str) -> Slot {
+	Slot { begin_time: at(begin_time), end_time: at(end_time) }
+}
+
+fn query(rooms: Option<&str>, at: Option<&str>) -> AvailabilityQuery {
+	AvailabilityQuery {
+		rooms: rooms.map(String::from),
+		from: Some("2019-12-12T07:00".into()),
+		to: Some("2019-12-12T22:00".into()),
+		at: at.map(String::from),
+		..AvailabilityQuery::default()
+	}
+}
+
+#[test]
+pub fn test_free_slots() {
+	// 12. 12. 2019 je čtvrtek
+	let hours = OpeningHours::default().with_day("fri", "closed").unwrap().with_day("sat", "10:00-24:00").unwrap();
+	let windows = hours.windows(at("2019-12-12T00:00"), at("2019-12-15T00:00"));
+	let open = vec![slot("2019-12-12T07:00", "2019-12-12T22:00"), slot("2019-12-14T10:00", "2019-12-15T00:00")];
+	assert_eq!(windows, open);
+	// v UTC
+	assert_eq!(datetime::format(windows[0].begin_time), "2019-12-12T06:00:00Z");
+	// okraje intervalu se oříznou
+	let windows = hours.windows(at("2019-12-12T12:00"), at("2019-12-12T13:00"));
+	assert_eq!(windows, vec![slot("2019-12-12T12:00", "2019-12-12T13:00")]);
+
+	for (day, bad) in &[("mon", "18:00-09:00"), ("mon", "banana"), ("mon", "07:00"), ("monday", "07:00-22:00")] {
+		assert!(OpeningHours::default().with_day(day, bad).is_err(), "{} {}", day, bad);
+	}
+
+	// navazující a přesahující rezervace
+	let busy = [
+		slot("2019-12-12T12:00", "2019-12-12T13:00"),
+		slot("2019-12-12T10:00", "2019-12-12T12:00"),
+		slot("2019-12-12T21:30", "2019-12-12T23:00"),
+		slot("2019-12-11T20:00", "2019-12-12T07:30"),
+	];
+	let (from, to) = (at("2019-12-12T00:00"), at("2019-12-13T00:00"));
+	let free = free_slots(&hours, from, to, &busy, Duration::minutes(60));
+	assert_eq!(free, vec![slot("2019-12-12T07:30", "2019-12-12T10:00"), slot("2019-12-12T13:00", "2019-12-12T21:30")]);
+	let free = free_slots(&hours, from, to, &busy, Duration::hours(3));
+	assert_eq!(free, vec![slot("2019-12-12T13:00", "2019-12-12T21:30")]);
+}
+
+#[test]
+pub fn test_availability() {
+	let filtered = json!({ "result": 0, "results": [{
+		"id": 1, "name": "beseda", "description": "", "author": NOOB.1, "rooms": 1,
+		"begin_time": "2019-12-12T09:00:00Z", "end_time": "2019-12-12T11:00:00Z",
+		"layout": 0, "approved": 1, "people": 10, "status": "approved"
+	}]});
+	let rgi = FakeRgi::new().reply(BOOKING, "FILTER", filtered);
+	let app = App::new(&rgi);
+	let cli = &app.client;
+
+	// přes RGI backend
+	let url = "/rgi/availability?rooms=3&from=2019-12-12T07:00&to=2019-12-12T22:00&duration=90";
+	assert_eq!(cli.get(url).dispatch().status(), Status::Unauthorized);
+	let mut response = cli.get(url).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let found = body(&mut response);
+	assert_eq!((&found["rooms"][0]["name"], &found["requested"]), (&json!("celé auditorium"), &Value::Null));
+	assert_eq!(
+		found["rooms"][0]["free"],
+		json!([
+			{ "begin_time": "2019-12-12T06:00:00Z", "end_time": "2019-12-12T09:00:00Z" },
+			{ "begin_time": "2019-12-12T11:00:00Z", "end_time": "2019-12-12T21:00:00Z" }
+		])
+	);
+	let args = json!({ "rooms": 3, "begin_time": "2019-12-12T06:00:00Z", "end_time": "2019-12-12T21:00:00Z" });
+	rgi.assert_called(BOOKING, "FILTER", args, None);
+
+	let bad = "/rgi/availability?from=2019-12-12T07:00&duration=5";
+	assert_eq!(cli.get(bad).header(app.auth(NOOB)).dispatch().status(), Status::UnprocessableEntity);
+
+	// přes Diesel: sever je schválený, jih jen požádaný
+	let usr = user(APPROVER);
+	let north = DieselBackend.create(&reservation("akce", 1, "2019-12-12T10:00", "2019-12-12T12:00"), &usr).unwrap();
+	DieselBackend.approve(north, &usr).unwrap();
+	DieselBackend.create(&reservation("akce", 2, "2019-12-12T14:00", "2019-12-12T16:00"), &usr).unwrap();
+
+	let hours = OpeningHours::default();
+	let free = |query: &AvailabilityQuery| {
+		let found = search(&DieselBackend, &hours, query).unwrap();
+		found.rooms.into_iter().map(|r| (r.name, r.free)).collect::<Vec<_>>()
+	};
+	let morning = slot("2019-12-12T07:00", "2019-12-12T10:00");
+	let (day, evening) = (slot("2019-12-12T07:00", "2019-12-12T22:00"), slot("2019-12-12T12:00", "2019-12-12T22:00"));
+	assert_eq!(
+		free(&query(None, None)),
+		vec![
+			("sever".to_string(), vec![morning, evening]),
+			("jih".to_string(), vec![day]),
+			("celé auditorium".to_string(), vec![morning, evening]),
+		]
+	);
+	// i neschválené žádosti, celé auditorium je obsazené, když je obsazená kterákoli polovina
+	let pending = AvailabilityQuery { pending: Some("true".into()), ..query(Some("jih,celé auditorium"), None) };
+	let (noon, late) = (slot("2019-12-12T12:00", "2019-12-12T14:00"), slot("2019-12-12T16:00", "2019-12-12T22:00"));
+	assert_eq!(
+		free(&pending),
+		vec![
+			("jih".to_string(), vec![slot("2019-12-12T07:00", "2019-12-12T14:00"), late]),
+			("celé auditorium".to_string(), vec![morning, noon, late]),
+		]
+	);
+
+	// požadovaný termín je volný jen na jihu
+	let requested = search(&DieselBackend, &hours, &query(Some("1,2"), Some("2019-12-12T11:00"))).unwrap().requested;
+	assert_eq!(requested.unwrap().available, vec![2]);
+
+	// celé auditorium v 11:00 nejde, nabídne se dřív i později a jih
+	let whole = AvailabilityQuery { people: Some("50".into()), ..query(Some("3"), Some("2019-12-12T11:00")) };
+	let requested = search(&DieselBackend, &hours, &whole).unwrap().requested.unwrap();
+	assert!(requested.available.is_empty());
+	let times = requested.times.iter().map(|s| s.slot).collect::<Vec<_>>();
+	assert_eq!(times, vec![slot("2019-12-12T12:00", "2019-12-12T13:00"), slot("2019-12-12T09:00", "2019-12-12T10:00")]);
+	let rooms = requested.rooms.iter().map(|s| (s.name.as_str(), s.slot)).collect::<Vec<_>>();
+	assert_eq!(rooms, vec![("jih", slot("2019-12-12T11:00", "2019-12-12T12:00"))]);
+	// pro víc lidí, než se vejde do poloviny, jiná místnost není
+	let crowd = AvailabilityQuery { people: Some("100".into()), ..whole };
+	assert!(search(&DieselBackend, &hours, &crowd).unwrap().requested.unwrap().rooms.is_empty());
+
+	// špatné parametry
+	for bad in &[
+		AvailabilityQuery { to: None, ..query(None, None) },
+		AvailabilityQuery { to: Some("2020-02-12T07:00".into()), ..query(None, None) },
+		AvailabilityQuery { to: Some("2019-12-12T06:00".into()), ..query(None, None) },
+		AvailabilityQuery { duration: Some("10".into()), ..query(None, None) },
+		// hodnoty, které nejdou přečíst, se neignorují
+		AvailabilityQuery { duration: Some("abc".into()), ..query(None, None) },
+		AvailabilityQuery { pending: Some("yes".into()), ..query(None, None) },
+		AvailabilityQuery { people: Some("hodně".into()), ..query(None, Some("2019-12-12T11:00")) },
+		query(None, Some("2019-12-12T21:30")),
+		query(Some("kuchyň"), None),
+		query(Some("0"), None),
+	] {
+		let e = search(&DieselBackend, &hours, bad).unwrap_err();
+		assert_eq!(e.status, Status::UnprocessableEntity, "{:?}", bad);
+	}
+}
@@ -1,32 +1,204 @@
-extern crate rocket;
 extern crate backend;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
 extern crate serde_json;
-use rocket::local::Client;
-use rocket::http::ContentType;
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use backend::db::Reservation;
+use backend::rgi::envelope::Envelope;
+use backend::rgi::runner::RgiError;
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use common::{body, App, FakeRgi, APPROVER, BOOKING, NOOB, OTHER};
+
+/// booking.py v paměti: rezervace jako JSON, kolize jen mezi schválenými
+fn in_memory_booking() -> FakeRgi {
+	let store = Arc::new(Mutex::new(Vec::<Value>::new()));
+
+	FakeRgi::new().on(BOOKING, move |envelope: &Envelope| {
+		let mut events = store.lock().unwrap();
+		let id = envelope.args.get("id").and_then(Value::as_i64);
+		let position = events.iter().position(|e| e["id"].as_i64() == id);
+
+		match (envelope.method.as_str(), position) {
+			("LIST", _) => json!(*events),
+			("GET", Some(i)) => {
+				let mut detail = events[i].clone();
+				detail["author_name"] = json!(null);
+				detail
+			}
+			("POST", _) => {
+				let mut event = envelope.data.clone().unwrap();
+				let id = events.len() as i64 + 1;
+				event["id"] = json!(id);
+				event["author"] = json!(envelope.caller.as_ref().unwrap().email);
+				event["approved"] = json!(0);
+				events.push(event);
+				json!({ "result": 0, "id": id })
+			}
+			("APPROVE", Some(i)) => {
+				let conflicts = events
+					.iter()
+					.filter(|e| e["approved"] == 1 && e["id"] != events[i]["id"])
+					.filter(|e| e["begin_time"].as_str() <= events[i]["end_time"].as_str())
+					.filter(|e| e["end_time"].as_str() >= events[i]["begin_time"].as_str())
+					.map(|e| e["id"].clone())
+					.collect::<Vec<_>>();
+				if !conflicts.is_empty() {
+					return json!({ "result": 2, "conflicts": conflicts });
+				}
+				events[i]["approved"] = json!(1);
+				json!({ "result": 0 })
+			}
+			("REJECT", Some(i)) => {
+				events[i]["status"] = json!("rejected");
+				events[i]["reason"] = envelope.data.as_ref().unwrap()["reason"].clone();
+				json!({ "result": 0 })
+			}
+			("DELETE", Some(i)) => {
+				events.remove(i);
+				json!({ "result": 0 })
+			}
+			_ => json!({ "result": 1 }),
+		}
+	})
+}
+
+fn event(name: &str, begin_time: &str, end_time: &str) -> String {
+	json!({
+		"name": name,
+		"description": "test stuff",
+		"rooms": 3,
+		"begin_time": begin_time,
+		"end_time": end_time,
+		"layout": 0,
+		"people": 30
+	})
+	.to_string()
+}
 
 #[test]
 pub fn test_booking() {
-	let cli = Client::new(backend::init()).expect("wtf? the virtual client failed");
-	assert_eq!(cli.get("/rgi/events/").dispatch().body_string().unwrap(), "[]");
+	let rgi = in_memory_booking();
+	let app = App::new(&rgi);
+	let cli = &app.client;
 
-	let mut req = cli.post("/rgi/events/").header(ContentType::JSON);
-	req.set_body(r#"{ "name": "test", "description": "test stuff", "author": "hozdic", "begin_time": "2019-12-12T12:30", "end_time": "2019-12-13T13:25", "rooms": 3, "layout": 0, "people": 30}"#);
-	assert_eq!(req.dispatch().body_string().unwrap(), r#"{"result": 0, "id": 1}"#);
+	assert_eq!(cli.get("/rgi/events").dispatch().status(), Status::Unauthorized);
+	let page = body(&mut cli.get("/rgi/events").header(app.auth(NOOB)).dispatch());
+	assert_eq!((&page["results"], &page["total"], &page["page"]), (&json!([]), &json!(0), &json!(1)));
 
-	let v: backend::db::Reservation =
-		serde_json::from_str(&cli.get("/rgi/events/1").dispatch().body_string().unwrap()).unwrap();
-	assert_eq!(v.approved, 0);
+	// bez přihlášení nic
+	let response = cli.post("/rgi/events").header(ContentType::JSON).body(event("test", "a", "b")).dispatch();
+	assert_eq!(response.status(), Status::Unauthorized);
+	assert_eq!(rgi.count(BOOKING, "POST"), 0);
 
-	cli.post("/rgi/events/1/approve/").header(ContentType::JSON).dispatch();
+	// nesmyslné časy, konec před začátkem, čas zrušený letním časem, příliš krátká a dlouhá rezervace
+	for (begin, end) in &[
+		("banana", "2019-12-12T13:00"),
+		("2019-12-12T13:00", "2019-12-12T12:00"),
+		("2020-03-29T02:30", "2020-03-29T04:00"),
+		("2019-12-12T13:00", "2019-12-12T13:05"),
+		("2019-12-01T13:00", "2019-12-12T13:00"),
+	] {
+		let new = event("x", begin, end);
+		let response = cli.post("/rgi/events").header(ContentType::JSON).header(app.auth(NOOB)).body(new).dispatch();
+		assert_eq!(response.status(), Status::UnprocessableEntity, "{} - {}", begin, end);
+	}
+	assert_eq!(rgi.count(BOOKING, "POST"), 0);
 
-	let v: backend::db::Reservation =
-		serde_json::from_str(&cli.get("/rgi/events/1").dispatch().body_string().unwrap()).unwrap();
+	let mut response = cli
+		.post("/rgi/events")
+		.header(ContentType::JSON)
+		.header(app.auth(NOOB))
+		.body(event("test", "2019-12-12T12:30", "2019-12-13T13:25"))
+		.dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	assert_eq!(body(&mut response), json!({ "result": 0, "id": 1 }));
+	// do RGI jde čas v UTC
+	let sent = serde_json::from_str(&event("test", "2019-12-12T11:30:00Z", "2019-12-13T12:25:00Z")).unwrap();
+	rgi.assert_called(BOOKING, "POST", json!({}), Some(sent));
+	rgi.assert_caller(BOOKING, NOOB.1);
+
+	let mut response = cli.get("/rgi/events/1").header(app.auth(NOOB)).dispatch();
+	let v: Reservation = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+	assert_eq!((v.approved, v.author.as_str()), (0, NOOB.1));
+	rgi.assert_called(BOOKING, "GET", json!({ "id": 1 }), None);
+
+	// schvalovat smí jen approver
+	let response = cli.post("/rgi/events/1/approve").header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Forbidden);
+	let response = cli.post("/rgi/events/1/approve").header(app.auth(APPROVER)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	rgi.assert_called(BOOKING, "APPROVE", json!({ "id": 1 }), None);
+	rgi.assert_caller(BOOKING, APPROVER.1);
+
+	let mut response = cli.get("/rgi/events/1").header(app.auth(NOOB)).dispatch();
+	let v: Reservation = serde_json::from_str(&response.body_string().unwrap()).unwrap();
 	assert_eq!(v.approved, 1);
 
-	let mut req = cli.post("/rgi/events/").header(ContentType::JSON);
-	req.set_body(r#"{ "name": "test2", "description": "test stuff2", "author": "hozdic2", "begin_time": "2019-12-12T11:30", "end_time": "2019-12-13T13:25", "rooms": 3, "layout": 0, "people": 100}"#);
-	assert_eq!(req.dispatch().body_string().unwrap(), r#"{"result": 2}"#);
+	// kolize se schválenou rezervací
+	cli.post("/rgi/events")
+		.header(ContentType::JSON)
+		.header(app.auth(OTHER))
+		.body(event("test2", "2019-12-12T10:30:00Z", "2019-12-13T13:25:00+01:00"))
+		.dispatch();
+	let mut response = cli.post("/rgi/events/2/approve").header(app.auth(APPROVER)).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+	assert_eq!(body(&mut response)["conflicts"], json!([1]));
+
+	// zamítnout jde jen s důvodem
+	let reject = |reason: &str| {
+		let data = json!({ "reason": reason }).to_string();
+		cli.post("/rgi/events/2/reject").header(ContentType::JSON).header(app.auth(APPROVER)).body(data).dispatch()
+	};
+	assert_eq!(reject("  ").status(), Status::UnprocessableEntity);
+	assert_eq!(rgi.count(BOOKING, "REJECT"), 0);
+	assert_eq!(reject("koliduje s koncertem").status(), Status::Ok);
+	rgi.assert_called(BOOKING, "REJECT", json!({ "id": 2 }), Some(json!({ "reason": "koliduje s koncertem" })));
+	let mut response = cli.get("/rgi/events/2").header(app.auth(OTHER)).dispatch();
+	let v: Reservation = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+	assert_eq!((v.status.as_str(), v.reason), ("rejected", Some("koliduje s koncertem".to_string())));
+
+	// cizí rezervaci smazat nejde
+	let response = cli.delete("/rgi/events/1").header(app.auth(OTHER)).dispatch();
+	assert_eq!(response.status(), Status::NotFound);
+	assert_eq!(rgi.count(BOOKING, "DELETE"), 0);
+
+	let response = cli.delete("/rgi/events/1").header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	rgi.assert_called(BOOKING, "DELETE", json!({ "id": 1 }), None);
+	assert_eq!(body(&mut cli.get("/rgi/events").header(app.auth(NOOB)).dispatch())["total"], json!(1));
+
+	let response = cli.get("/rgi/events/1").header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::NotFound);
+}
+
+#[test]
+pub fn test_booking_rgi_failures() {
+	let rgi = FakeRgi::new()
+		.reply(BOOKING, "LIST", json!({ "unexpected": true }))
+		.fail(BOOKING, "FILTER", RgiError::Timeout(Duration::from_secs(30)))
+		.fail(BOOKING, "GET", RgiError::Failed(Some(1)));
+	let app = App::new(&rgi);
+	let cli = &app.client;
+
+	assert_eq!(cli.get("/rgi/events").header(app.auth(NOOB)).dispatch().status(), Status::BadGateway);
+
+	let response = cli.get("/rgi/events/filter/1/2019-12-12T10:00/2019-12-12T12:00").header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::GatewayTimeout);
+	rgi.assert_called(
+		BOOKING,
+		"FILTER",
+		json!({ "rooms": 1, "begin_time": "2019-12-12T09:00:00Z", "end_time": "2019-12-12T11:00:00Z" }),
+		None,
+	);
 
-	cli.delete("/rgi/events/1").header(ContentType::JSON).dispatch();
-	assert_eq!(cli.get("/rgi/events/").dispatch().body_string().unwrap(), "[]");
+	assert_eq!(cli.get("/rgi/events/3").header(app.auth(NOOB)).dispatch().status(), Status::BadGateway);
+	assert_eq!(rgi.calls().len(), 3);
 }
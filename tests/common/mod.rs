@@ -0,0 +1,267 @@
+//! společné pomůcky pro testy
+//!
+//! - [`fresh_db`] -> prázdná databáze se všemi migracemi
+//! - [`FakeRgi`] -> falešná RGI (Rust funkce nebo připravené odpovědi), která si
+//!   pamatuje každou obálku, co dostala
+//! - [`App`] -> Rocket s RGI endpointy nad [`FakeRgi`], `/me`, správou uživatelů a API klíčů
+//!   a tokeny pro testovací uživatele
+//! - [`body`], [`at`], [`user`], [`reservation`] -> drobnosti, které potřebuje skoro každý test
+//!
+//! ```no_run
+//! let rgi = FakeRgi::new().reply(BOOKING, "GET", json!({ "id": 1, ... }));
+//! let app = App::new(&rgi);
+//! app.client.get("/rgi/events/1").header(app.auth(NOOB)).dispatch();
+//! rgi.assert_called(BOOKING, "GET", json!({ "id": 1 }), None);
+//! rgi.assert_caller(BOOKING, NOOB.1);
+//! ```
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use backend::auth::jwt::Keys;
+use backend::auth::permissions::Permissions;
+use backend::auth::provisioning::Policy;
+use backend::datetime;
+use backend::db::{NewReservation, Rooms, User};
+use backend::rgi::booking::availability::OpeningHours;
+use backend::rgi::booking::backend::{Backend, RgiBackend};
+use backend::rgi::envelope::Envelope;
+use backend::rgi::jobs::Jobs;
+use backend::rgi::manifest::Manifest;
+use backend::rgi::runner::{RgiError, Runner};
+use chrono::NaiveDateTime;
+use diesel::connection::SimpleConnection;
+use diesel::Connection;
+use rocket::http::Header;
+use rocket::local::{Client, LocalResponse};
+use serde_json::Value;
+
+/// skript rezervací
+pub const BOOKING: &str = "rgi/booking/booking.py";
+
+/// testovací uživatelé (jméno, email, role)
+pub const NOOB: (&str, &str, &str) = ("Jan Novák", "novak@gjk.cz", "noob");
+pub const OTHER: (&str, &str, &str) = ("Petr Svoboda", "svoboda@gjk.cz", "noob");
+pub const APPROVER: (&str, &str, &str) = ("Marie Dvořáková", "dvorakova@gjk.cz", "approver");
+pub const MANAGER: (&str, &str, &str) = ("Eva Černá", "cerna@gjk.cz", "facilitymanager");
+
+/// tělo odpovědi jako JSON
+pub fn body(response: &mut LocalResponse) -> Value {
+	serde_json::from_str(&response.body_string().unwrap()).unwrap()
+}
+
+/// místní čas auditoria
+pub fn at(time: &str) -> NaiveDateTime {
+	datetime::parse(time).unwrap()
+}
+
+/// testovací uživatel tak, jak ho [`fresh_db`] uložila do databáze
+pub fn user((name, email, role): (&str, &str, &str)) -> User {
+	let id = [NOOB, OTHER, APPROVER, MANAGER].iter().position(|u| u.1 == email).unwrap() as i32 + 1;
+	User { id, name: name.into(), email: email.into(), role: role.into(), active: 1 }
+}
+
+/// rezervace pro 30 lidí bez rozložení
+pub fn reservation(name: &str, rooms: i32, begin_time: &str, end_time: &str) -> NewReservation {
+	NewReservation {
+		name:        name.into(),
+		description: "test".into(),
+		rooms:       Rooms::Mask(rooms),
+		begin_time:  at(begin_time),
+		end_time:    at(end_time),
+		layout:      0,
+		people:      30,
+	}
+}
+
+/// založí prázdnou databázi se všemi migracemi a nastaví na ni `DATABASE_URL`
+///
+/// `DATABASE_URL` je společná pro celý proces, takže jeden testovací soubor = jedna databáze
+pub fn fresh_db(name: &str) {
+	let path = env::temp_dir().join(format!("booking-test-{}-{}.sqlite3", name, std::process::id()));
+	let _ = fs::remove_file(&path);
+	env::set_var("DATABASE_URL", &path);
+
+	let con = diesel::SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+	let mut migrations = fs::read_dir("migrations").unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+	migrations.sort();
+	for dir in migrations {
+		con.batch_execute(&fs::read_to_string(dir.join("up.sql")).unwrap()).unwrap();
+	}
+
+	for (name, email, role) in [NOOB, OTHER, APPROVER, MANAGER].iter() {
+		con.batch_execute(&format!("INSERT INTO users (name, email, role) VALUES ('{}', '{}', '{}')", name, email, role))
+			.unwrap();
+	}
+}
+
+/// jedno zavolání falešného RGI
+#[derive(Debug, Clone)]
+pub struct Call {
+	/// cesta ke skriptu
+	pub script:   String,
+	/// obálka, kterou skript dostal
+	pub envelope: Envelope,
+}
+
+type Handler = Arc<dyn Fn(&Envelope) -> Result<String, RgiError> + Send + Sync>;
+
+/// falešná RGI
+///
+/// klony sdílí záznam volání
+#[derive(Clone, Default)]
+pub struct FakeRgi {
+	/// skript -> funkce, která odpovídá místo něj
+	handlers: HashMap<String, Handler>,
+	/// (skript, metoda) -> připravená odpověď
+	replies:  HashMap<(String, String), Result<String, RgiError>>,
+	calls:    Arc<Mutex<Vec<Call>>>,
+}
+
+impl FakeRgi {
+	/// falešná RGI bez skriptů, každé volání skončí chybou jako neexistující skript
+	pub fn new() -> Self {
+		FakeRgi::default()
+	}
+
+	/// skript odpovídá Rust funkcí, vrací JSON, který by skript vypsal
+	pub fn on<F>(mut self, script: &str, handler: F) -> Self
+	where
+		F: Fn(&Envelope) -> Value + Send + Sync + 'static,
+	{
+		self.handlers.insert(script.to_string(), Arc::new(move |envelope| Ok(handler(envelope).to_string())));
+		self
+	}
+
+	/// metoda skriptu vždy vrátí `response`
+	pub fn reply(mut self, script: &str, method: &str, response: Value) -> Self {
+		self.replies.insert((script.to_string(), method.to_string()), Ok(response.to_string()));
+		self
+	}
+
+	/// metoda skriptu vždy selže
+	pub fn fail(mut self, script: &str, method: &str, error: RgiError) -> Self {
+		self.replies.insert((script.to_string(), method.to_string()), Err(error));
+		self
+	}
+
+	/// runner, ve kterém jsou všechny zaregistrované skripty nahrazené
+	pub fn runner(&self) -> Runner {
+		let mut scripts = self.handlers.keys().cloned().collect::<Vec<_>>();
+		scripts.extend(self.replies.keys().map(|(script, _)| script.clone()));
+		scripts.sort();
+		scripts.dedup();
+
+		scripts.into_iter().fold(Runner::new(Duration::from_secs(5)), |runner, script| {
+			let fake = self.clone();
+			let name = script.clone();
+			runner.with_stub(&script, move |envelope| fake.call(&name, envelope))
+		})
+	}
+
+	/// zaznamená volání a odpoví
+	fn call(&self, script: &str, envelope: &Envelope) -> Result<String, RgiError> {
+		self.calls.lock().unwrap().push(Call { script: script.to_string(), envelope: envelope.clone() });
+
+		match self.replies.get(&(script.to_string(), envelope.method.clone())) {
+			Some(reply) => reply.clone(),
+			None => match self.handlers.get(script) {
+				Some(handler) => handler(envelope),
+				None => Err(RgiError::Failed(Some(1))),
+			},
+		}
+	}
+
+	/// všechna volání v pořadí
+	pub fn calls(&self) -> Vec<Call> {
+		self.calls.lock().unwrap().clone()
+	}
+
+	/// obálky poslané danému skriptu
+	pub fn envelopes(&self, script: &str) -> Vec<Envelope> {
+		self.calls().into_iter().filter(|c| c.script == script).map(|c| c.envelope).collect()
+	}
+
+	/// poslední obálka poslaná skriptu
+	pub fn last(&self, script: &str) -> Envelope {
+		self.envelopes(script).pop().unwrap_or_else(|| panic!("{} was never called", script))
+	}
+
+	/// zapomene zaznamenaná volání
+	pub fn clear(&self) {
+		self.calls.lock().unwrap().clear();
+	}
+
+	/// poslední volání metody skriptu mělo dané argumenty a data
+	pub fn assert_called(&self, script: &str, method: &str, args: Value, data: Option<Value>) {
+		let envelope = self
+			.envelopes(script)
+			.into_iter()
+			.rev()
+			.find(|e| e.method == method)
+			.unwrap_or_else(|| panic!("{} {} was never called", script, method));
+		assert_eq!(Value::Object(envelope.args.clone()), args, "args of the last {} {} call", script, method);
+		assert_eq!(envelope.data, data, "data of the last {} {} call", script, method);
+	}
+
+	/// poslední volání skriptu poslal uživatel s daným emailem
+	pub fn assert_caller(&self, script: &str, email: &str) {
+		let envelope = self.last(script);
+		assert_eq!(envelope.caller.map(|c| c.email), Some(email.to_string()), "caller of the last {} call", script);
+	}
+
+	/// kolikrát byla metoda skriptu zavolána
+	pub fn count(&self, script: &str, method: &str) -> usize {
+		self.envelopes(script).iter().filter(|e| e.method == method).count()
+	}
+}
+
+/// klíče pro testovací tokeny
+fn keys() -> Keys {
+	Keys::new("test", b"test secret", "auditorium-booking", 3600)
+}
+
+/// Rocket s RGI endpointy, `/me` a správou uživatelů a API klíčů, ve kterém všechna RGI obstarává [`FakeRgi`]
+pub struct App {
+	/// lokální klient
+	pub client: Client,
+}
+
+impl App {
+	/// Rocket s rezervacemi přes RGI, databáze se založí jednou za celý proces
+	pub fn new(rgi: &FakeRgi) -> Self {
+		App::with_manifest(rgi, Manifest::default())
+	}
+
+	/// jako [`App::new`], navíc s endpointy z manifestu
+	pub fn with_manifest(rgi: &FakeRgi, manifest: Manifest) -> Self {
+		static DB: Once = Once::new();
+		DB.call_once(|| fresh_db("app"));
+
+		let runner = rgi.runner();
+		let bookings: Backend = Box::new(RgiBackend::new(runner.clone()));
+		let rocket = rocket::ignite()
+			.manage(keys())
+			.manage(Policy::new(vec![], false))
+			.manage(Permissions::from_toml(&fs::read_to_string("permissions.toml").unwrap()).unwrap())
+			.manage(Jobs::new(runner.clone(), 2))
+			.manage(bookings)
+			.manage(OpeningHours::default())
+			.manage(runner)
+			.mount("/", backend::auth::routes())
+			.mount("/", backend::users::routes())
+			.mount("/", backend::auth::api_keys::routes())
+			.mount("/rgi/", backend::rgi::routes(&manifest));
+
+		App { client: Client::new(rocket).unwrap() }
+	}
+
+	/// hlavička `Authorization` pro uživatele
+	pub fn auth(&self, (name, email, _): (&str, &str, &str)) -> Header<'static> {
+		Header::new("Authorization", format!("Bearer {}", keys().issue(name, email)))
+	}
+}
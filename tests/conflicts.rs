@@ -0,0 +1,105 @@
+extern crate backend;
+extern crate chrono;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use std::fmt::Debug;
+
+use backend::db::{Rooms, UpdateReservation};
+use backend::rgi::booking::backend::{BookingBackend, DieselBackend};
+use backend::rgi::booking::conflicts::{rooms_intersect, times_overlap, validate};
+use backend::error::ApiError;
+use rocket::http::Status;
+use serde_json::Value;
+
+use common::{at, fresh_db, reservation, user, NOOB};
+
+fn moved(rooms: Option<u8>, begin_time: Option<&str>, end_time: Option<&str>) -> UpdateReservation {
+	UpdateReservation {
+		name:        None,
+		description: None,
+		rooms:       rooms.map(|r| Rooms::Mask(r as i32)),
+		begin_time:  begin_time.map(at),
+		end_time:    end_time.map(at),
+		layout:      None,
+		people:      None,
+	}
+}
+
+/// chyba musí být 409, vrací id kolidujících rezervací
+fn conflicts<T: Debug>(result: Result<T, ApiError>) -> Value {
+	let e = result.unwrap_err();
+	assert_eq!(e.status, Status::Conflict);
+	e.details["conflicts"].clone()
+}
+
+#[test]
+pub fn test_overlap_rules() {
+	assert!(rooms_intersect(0b11, 0b01));
+	assert!(rooms_intersect(0b10, 0b11));
+	assert!(rooms_intersect(0b01, 0b01));
+	assert!(!rooms_intersect(0b01, 0b10));
+
+	assert!(times_overlap(("2019-12-12 10:00", "2019-12-12 12:00"), ("2019-12-12 11:00", "2019-12-12 13:00")));
+	assert!(times_overlap(("2019-12-12 10:00", "2019-12-12 12:00"), ("2019-12-12 10:30", "2019-12-12 11:00")));
+	// navazující rezervace nekolidují
+	assert!(!times_overlap(("2019-12-12 10:00", "2019-12-12 12:00"), ("2019-12-12 12:00", "2019-12-12 13:00")));
+	assert!(!times_overlap(("2019-12-12 12:00", "2019-12-12 13:00"), ("2019-12-12 10:00", "2019-12-12 12:00")));
+
+	let (begin, end) = (at("2019-12-12 10:00"), at("2019-12-12 12:00"));
+	assert!(validate(3, begin, end).is_ok());
+	assert_eq!(validate(0, begin, end).unwrap_err().status, Status::UnprocessableEntity);
+	assert_eq!(validate(-1, begin, end).unwrap_err().status, Status::UnprocessableEntity);
+	assert_eq!(validate(1, end, end).unwrap_err().status, Status::UnprocessableEntity);
+	assert_eq!(validate(1, end, begin).unwrap_err().status, Status::UnprocessableEntity);
+}
+
+#[test]
+pub fn test_diesel_conflicts() {
+	fresh_db("conflicts");
+	let (db, usr) = (DieselBackend, user(NOOB));
+
+	// místnosti mimo katalog rezervovat nejde
+	let unknown = db.create(&reservation("akce", 4, "2019-12-12 10:00", "2019-12-12 12:00"), &usr);
+	assert_eq!(unknown.unwrap_err().status, Status::UnprocessableEntity);
+
+	// sever je schválený, celé auditorium přes něj nejde
+	let north = db.create(&reservation("akce", 1, "2019-12-12 10:00", "2019-12-12 12:00"), &usr).unwrap();
+	db.approve(north, &usr).unwrap();
+	let whole = reservation("akce", 3, "2019-12-12 11:00", "2019-12-12 13:00");
+	assert_eq!(conflicts(db.create(&whole, &usr)), json!([north]));
+
+	// jih ve stejný čas a navazující rezervace projdou
+	let south = db.create(&reservation("akce", 2, "2019-12-12 10:00", "2019-12-12 12:00"), &usr).unwrap();
+	db.approve(south, &usr).unwrap();
+	let after = db.create(&reservation("akce", 3, "2019-12-12 12:00", "2019-12-12 14:00"), &usr).unwrap();
+	db.approve(after, &usr).unwrap();
+
+	// neschválené žádosti se můžou překrývat, schválit jde ale jen jednu
+	let pending = db.create(&reservation("akce", 1, "2019-12-12 11:00", "2019-12-12 11:30"), &usr).unwrap();
+	assert_eq!(conflicts(db.approve(pending, &usr)), json!([north]));
+
+	// přesun přes PATCH se kontroluje stejně
+	assert_eq!(conflicts(db.update(south, &moved(Some(3), None, None), &usr)), json!([north]));
+	assert_eq!(
+		conflicts(db.update(south, &moved(None, None, Some("2019-12-12 12:30")), &usr)),
+		json!([after])
+	);
+	assert_eq!(db.get(south).unwrap().reservation.end_time, at("2019-12-12 12:00"));
+	db.update(south, &moved(None, Some("2019-12-12 09:00"), None), &usr).unwrap();
+	assert_eq!(
+		db.update(north, &moved(None, Some("2019-12-12 13:00"), None), &usr).unwrap_err().status,
+		Status::UnprocessableEntity
+	);
+
+	// bez změny času a místností se nic nekontroluje
+	let renamed = UpdateReservation { name: Some("jiná".into()), ..moved(None, None, None) };
+	db.update(pending, &renamed, &usr).unwrap();
+
+	let found = db.filter(1, at("2019-12-12 00:00"), at("2019-12-13 00:00")).unwrap();
+	assert_eq!(found.iter().map(|r| r.id).collect::<Vec<_>>(), vec![north, pending, after]);
+}
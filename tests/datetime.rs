@@ -0,0 +1,69 @@
+extern crate backend;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+use backend::datetime::{check_span, format, parse, to_local};
+use backend::db::{NewReservation, UpdateReservation};
+use rocket::http::Status;
+
+fn utc(time: &str) -> String {
+	format(parse(time).unwrap())
+}
+
+#[test]
+pub fn test_parse_times() {
+	// s posunem i bez něj, bez posunu platí místní čas auditoria
+	assert_eq!(utc("2019-12-12T18:00:00+01:00"), "2019-12-12T17:00:00Z");
+	assert_eq!(utc("2019-12-12T17:00:00Z"), "2019-12-12T17:00:00Z");
+	assert_eq!(utc("2019-12-12T17:00:00.250Z"), "2019-12-12T17:00:00.250Z");
+	assert_eq!(utc("2019-12-12T18:00"), "2019-12-12T17:00:00Z");
+	assert_eq!(utc(" 2019-12-12 18:00:00 "), "2019-12-12T17:00:00Z");
+	// letní čas
+	assert_eq!(utc("2020-07-01T18:00"), "2020-07-01T16:00:00Z");
+	assert_eq!(to_local(parse("2020-07-01T16:00:00Z").unwrap()).to_string(), "2020-07-01 18:00:00");
+
+	// 02:30 při přechodu na letní čas není, při přechodu zpět je dvakrát a bere se dřívější
+	assert!(parse("2020-03-29T02:30").is_err());
+	assert_eq!(utc("2020-03-29T02:30:00+01:00"), "2020-03-29T01:30:00Z");
+	assert_eq!(utc("2020-10-25T02:30"), "2020-10-25T00:30:00Z");
+
+	for bad in &["banana", "", "2019-13-01T10:00", "2019-12-12", "12.12.2019 18:00"] {
+		assert!(parse(bad).is_err(), "{:?} should be rejected", bad);
+	}
+}
+
+#[test]
+pub fn test_span() {
+	let at = |time: &str| parse(time).unwrap();
+
+	assert!(check_span(at("2019-12-12T10:00"), at("2019-12-12T10:15")).is_ok());
+	assert!(check_span(at("2019-12-12T10:00"), at("2019-12-19T10:00")).is_ok());
+	for (begin, end) in &[
+		("2019-12-12T10:00", "2019-12-12T10:00"),
+		("2019-12-12T10:00", "2019-12-12T09:00"),
+		("2019-12-12T10:00", "2019-12-12T10:14"),
+		("2019-12-12T10:00", "2019-12-19T10:01"),
+	] {
+		assert_eq!(check_span(at(begin), at(end)).unwrap_err().status, Status::UnprocessableEntity);
+	}
+}
+
+#[test]
+pub fn test_serde() {
+	let input = r#"{"name": "koncert", "description": "", "rooms": 3, "begin_time": "2019-12-12T18:00",
+		"end_time": "2019-12-12T20:00:00+01:00", "layout": 0, "people": 100}"#;
+	let new = serde_json::from_str::<NewReservation>(input).unwrap();
+	let value = serde_json::to_value(&new).unwrap();
+	assert_eq!(value["begin_time"], json!("2019-12-12T17:00:00Z"));
+	assert_eq!(value["end_time"], json!("2019-12-12T19:00:00Z"));
+
+	assert!(serde_json::from_str::<NewReservation>(&input.replace("2019-12-12T18:00", "banana")).is_err());
+
+	// u úprav čas chybět může
+	let update = serde_json::from_str::<UpdateReservation>(r#"{"name": "jiný"}"#).unwrap();
+	assert_eq!((update.begin_time, update.end_time), (None, None));
+	let update = serde_json::from_str::<UpdateReservation>(r#"{"end_time": "2019-12-12T21:00"}"#).unwrap();
+	assert_eq!(update.end_time, Some(parse("2019-12-12T20:00:00Z").unwrap()));
+	assert_eq!(serde_json::to_value(&update).unwrap()["end_time"], "2019-12-12T20:00:00Z");
+}
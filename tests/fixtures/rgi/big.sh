@@ -0,0 +1,3 @@
+#!/bin/sh
+# vypíše 100 kB nul
+head -c 100000 /dev/zero
@@ -0,0 +1,4 @@
+#!/bin/sh
+# vrátí obálku ze stdinu, na stderr zapíše metodu
+echo "method $1" >&2
+cat
@@ -0,0 +1,3 @@
+#!/bin/sh
+# vypíše, co ze sandboxu vidí
+echo "{\"secret\":\"$RGI_TEST_SECRET\",\"cwd\":\"$(pwd)\",\"files\":\"$(ulimit -n)\",\"cpu\":\"$(ulimit -t)\"}"
@@ -0,0 +1,3 @@
+#!/bin/sh
+# sleep běží jako potomek shellu, roury drží otevřené i po zabití shellu
+sleep 10
@@ -0,0 +1,4 @@
+#!/bin/sh
+# skončí hned, sleep na pozadí ale drží roury otevřené
+sleep 10 &
+echo '{"result": 0}'
@@ -0,0 +1,4 @@
+#!/bin/sh
+# chvíli počká a vrátí obálku ze stdinu
+sleep 1
+cat
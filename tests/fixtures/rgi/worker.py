@@ -0,0 +1,16 @@
+#!/usr/bin/env python3
+# perzistentní worker pro testy: vrací obálku, metoda CRASH ho shodí, FAIL ohlásí chybu
+import json
+import os
+import sys
+
+for line in sys.stdin:
+    data = json.loads(line)
+    if data["method"] == "CRASH":
+        sys.exit(1)
+    if data["method"] == "FAIL":
+        reply = {"id": data["id"], "error": "failed on purpose"}
+    else:
+        reply = {"id": data["id"], "response": {"pid": os.getpid(), "envelope": data}}
+    sys.stdout.write(json.dumps(reply) + "\n")
+    sys.stdout.flush()
@@ -0,0 +1,142 @@
+extern crate backend;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use backend::auth::permissions::Permissions;
+use backend::db::{Job, NewJob};
+use backend::rgi::envelope::Envelope;
+use backend::rgi::jobs::{self, Jobs};
+use backend::rgi::manifest::Manifest;
+use backend::rgi::runner::Runner;
+use diesel::prelude::*;
+use rocket::http::Status;
+use rocket::local::Client;
+
+use common::fresh_db;
+
+const SLOW: &str = "tests/fixtures/rgi/slow.sh";
+
+fn load(id: i32) -> Job {
+	use backend::schema::rgi_jobs::dsl::rgi_jobs;
+
+	rgi_jobs.find(id).first::<Job>(&backend::db::get_con()).unwrap()
+}
+
+/// počká, až úloha `id` přejde do stavu `status`
+fn wait_for(id: i32, status: &str) -> Job {
+	let started = Instant::now();
+	loop {
+		let job = load(id);
+		if job.status == status {
+			return job;
+		}
+		assert!(started.elapsed() < Duration::from_secs(10), "job {} is still {}", id, job.status);
+		thread::sleep(Duration::from_millis(50));
+	}
+}
+
+// jedna databáze, `DATABASE_URL` je společná pro celý proces
+#[test]
+pub fn test_jobs() {
+	fresh_db("jobs");
+
+	// fronta a limit
+	let queue = Jobs::new(Runner::new(Duration::from_secs(5)), 2).with_limit(SLOW, 1);
+	assert_eq!(queue.limit(SLOW), 1);
+	assert_eq!(queue.limit("tests/fixtures/rgi/fail.sh"), 2);
+
+	let first = queue.submit(SLOW, Envelope::new("FIRST"), None).unwrap();
+	let second = queue.submit(SLOW, Envelope::new("SECOND"), None).unwrap();
+	let failed = queue.submit("tests/fixtures/rgi/fail.sh", Envelope::new("GET"), None).unwrap();
+
+	wait_for(first, jobs::RUNNING);
+	assert_eq!(load(second).status, jobs::QUEUED);
+
+	let done = wait_for(second, jobs::DONE);
+	assert_eq!(done.code, Some(200));
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(done.result.as_ref().unwrap()).unwrap(),
+		json!({ "method": "SECOND", "args": {} })
+	);
+	assert!(done.started.is_some() && done.finished.is_some());
+	assert_eq!(load(first).status, jobs::DONE);
+
+	let failed = wait_for(failed, jobs::FAILED);
+	assert_eq!(failed.code, Some(502));
+	assert_eq!(failed.error.as_ref().unwrap(), "RGI exited with code 3");
+
+	// plná fronta se odmítne, úloha se ani nezaloží
+	let full = Jobs::new(Runner::new(Duration::from_secs(5)), 1).with_queue(1);
+	let running = full.submit(SLOW, Envelope::new("GET"), None).unwrap();
+	wait_for(running, jobs::RUNNING);
+	let waiting = full.submit(SLOW, Envelope::new("GET"), None).unwrap();
+	assert_eq!(full.submit(SLOW, Envelope::new("GET"), None).unwrap_err().status, Status::ServiceUnavailable);
+	wait_for(waiting, jobs::DONE);
+	let accepted = full.submit(SLOW, Envelope::new("GET"), None).unwrap();
+	assert_eq!(accepted, waiting + 1);
+	wait_for(accepted, jobs::DONE);
+
+	// nedokončené úlohy po restartu
+	let con = backend::db::get_con();
+	let stale = NewJob { script: SLOW.into(), method: "GET".into(), status: jobs::RUNNING.into(), created_by: None };
+	diesel::insert_into(backend::schema::rgi_jobs::table).values(&stale).execute(&con).unwrap();
+	assert_eq!(jobs::recover().unwrap(), 1);
+	assert_eq!(jobs::recover().unwrap(), 0);
+
+	// přes HTTP
+	let manifest = Manifest::from_toml(
+		r#"
+		[[endpoint]]
+		path = "/slow/<id>"
+		method = "POST"
+		script = "tests/fixtures/rgi/slow.sh"
+		verb = "SLOW"
+		mode = "async"
+		args = [{ name = "id", source = "url", type = "int" }]
+	"#,
+	)
+	.unwrap();
+
+	let runner = Runner::new(Duration::from_secs(5));
+	let rocket = rocket::ignite()
+		.manage(Permissions::from_toml(&fs::read_to_string("permissions.toml").unwrap()).unwrap())
+		.manage(Jobs::new(runner.clone(), 2))
+		.manage(runner)
+		.mount("/rgi/", manifest.routes())
+		.mount("/rgi/", jobs::routes());
+	let client = Client::new(rocket).unwrap();
+
+	let mut response = client.post("/rgi/slow/7").dispatch();
+	assert_eq!(response.status(), Status::Accepted);
+	let body = serde_json::from_str::<serde_json::Value>(&response.body_string().unwrap()).unwrap();
+	assert_eq!(body["status"], "queued");
+	let id = body["job"].as_i64().unwrap() as i32;
+	assert_eq!(response.headers().get_one("Location"), Some(format!("/rgi/jobs/{}", id).as_str()));
+
+	wait_for(id, jobs::DONE);
+	let mut response = client.get(format!("/rgi/jobs/{}", id)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let body = serde_json::from_str::<serde_json::Value>(&response.body_string().unwrap()).unwrap();
+	assert_eq!(body["status"], "done");
+	assert_eq!(body["result"], json!({ "method": "SLOW", "args": { "id": 7 } }));
+
+	// cizí úlohu anonym neuvidí
+	let private = NewJob {
+		script:     SLOW.into(),
+		method:     "GET".into(),
+		status:     jobs::DONE.into(),
+		created_by: Some("novak@gjk.cz".into()),
+	};
+	diesel::insert_into(backend::schema::rgi_jobs::table).values(&private).execute(&con).unwrap();
+	let private = id + 1;
+	assert_eq!(client.get(format!("/rgi/jobs/{}", private)).dispatch().status(), Status::NotFound);
+	assert_eq!(client.get("/rgi/jobs/999").dispatch().status(), Status::NotFound);
+}
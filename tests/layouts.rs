@@ -0,0 +1,110 @@
+extern crate backend;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use backend::db::{self, NewReservation};
+use backend::rgi::booking::backend::{BookingBackend, DieselBackend};
+use backend::rgi::booking::layouts::check;
+use rocket::http::{ContentType, Status};
+use rocket::local::LocalRequest;
+use serde_json::Value;
+
+use common::{body, reservation, user, App, FakeRgi, BOOKING, MANAGER, NOOB};
+
+fn send<'c>(request: LocalRequest<'c>, app: &App, who: (&str, &str, &str), data: Value) -> LocalRequest<'c> {
+	request.header(ContentType::JSON).header(app.auth(who)).body(data.to_string())
+}
+
+fn event(rooms: u8, layout: u16, people: u16) -> Value {
+	json!({
+		"name": "přednáška",
+		"description": "test",
+		"rooms": rooms,
+		"begin_time": "2019-12-12T18:00",
+		"end_time": "2019-12-12T20:00",
+		"layout": layout,
+		"people": people
+	})
+}
+
+#[test]
+pub fn test_layouts() {
+	let rgi = FakeRgi::new();
+	let app = App::new(&rgi);
+	let cli = &app.client;
+
+	// z migrace
+	let all = body(&mut cli.get("/rgi/layouts").dispatch());
+	let names = all.as_array().unwrap().iter().map(|l| l["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+	assert_eq!(names, vec!["divadlo", "stoly"]);
+	assert_eq!(all[0]["capacities"][2], json!({ "rooms": 3, "capacity": 150 }));
+
+	// spravovat je smí jen facility manager
+	let kino = json!({
+		"name": "kino",
+		"description": "jen celé auditorium",
+		"diagram": "/static/layouts/kino.svg",
+		"capacities": [{ "rooms": 3, "capacity": 100 }]
+	});
+	let response = send(cli.post("/rgi/layouts"), &app, NOOB, kino.clone()).dispatch();
+	assert_eq!(response.status(), Status::Forbidden);
+	let mut response = send(cli.post("/rgi/layouts"), &app, MANAGER, kino.clone()).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let created = body(&mut response);
+	let kino_id = created["id"].as_i64().unwrap();
+	assert_eq!(created["diagram"], json!("/static/layouts/kino.svg"));
+	assert_eq!(created["capacities"], json!([{ "rooms": 3, "capacity": 100 }]));
+
+	let response = send(cli.post("/rgi/layouts"), &app, MANAGER, kino).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+	// neznámé místnosti, nulová kapacita a víc lidí, než se do místnosti vejde
+	for capacities in &[
+		json!([{ "rooms": 4, "capacity": 10 }]),
+		json!([{ "rooms": 1, "capacity": 0 }]),
+		json!([{ "rooms": 1, "capacity": 80 }]),
+	] {
+		let bad = json!({ "name": "jiné", "capacities": capacities });
+		let response = send(cli.post("/rgi/layouts"), &app, MANAGER, bad).dispatch();
+		assert_eq!(response.status(), Status::UnprocessableEntity, "{}", capacities);
+	}
+
+	// rezervace musí rozložení respektovat, backend se ani nezavolá
+	for (rooms, layout, people) in &[(1, kino_id, 10), (3, kino_id, 101), (3, 42, 10), (3, 1, 61)] {
+		let data = event(*rooms, *layout as u16, *people);
+		let mut response = send(cli.post("/rgi/events"), &app, NOOB, data).dispatch();
+		assert_eq!(response.status(), Status::UnprocessableEntity, "{:?}", (rooms, layout, people));
+		assert!(body(&mut response)["error"].is_string());
+	}
+	assert_eq!(rgi.count(BOOKING, "POST"), 0);
+
+	// kapacity se nahrazují všechny
+	let url = format!("/rgi/layouts/{}", kino_id);
+	let capacities = json!([{ "rooms": 1, "capacity": 40 }, { "rooms": 3, "capacity": 80 }]);
+	let change = json!({ "diagram": "", "capacities": capacities });
+	let mut response = send(cli.patch(url.clone()), &app, MANAGER, change).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let updated = body(&mut response);
+	assert_eq!((&updated["name"], &updated["diagram"]), (&json!("kino"), &Value::Null));
+	assert_eq!(updated["capacities"], capacities);
+	let con = db::get_con();
+	assert!(check(&con, kino_id as i32, 1, 40).is_ok());
+	assert_eq!(check(&con, kino_id as i32, 3, 81).unwrap_err().status, Status::UnprocessableEntity);
+	assert!(check(&con, kino_id as i32, 2, 1).is_err());
+
+	let response = send(cli.patch(url.clone()), &app, MANAGER, json!({ "name": "stoly" })).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+
+	// používané rozložení smazat nejde
+	let workshop = reservation("workshop", 2, "2019-12-13T10:00", "2019-12-13T12:00");
+	let used = DieselBackend.create(&NewReservation { layout: 1, people: 20, ..workshop }, &user(NOOB)).unwrap();
+	let mut response = cli.delete("/rgi/layouts/1").header(app.auth(MANAGER)).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+	assert_eq!(body(&mut response)["events"], json!([used]));
+
+	let response = cli.delete(url.clone()).header(app.auth(MANAGER)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	assert_eq!(cli.get(url).dispatch().status(), Status::NotFound);
+}
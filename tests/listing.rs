@@ -0,0 +1,103 @@
+extern crate backend;
+extern crate diesel;
+extern crate rocket;
+
+mod common;
+
+use backend::db::NewReservation;
+use backend::rgi::booking::approval::Decision;
+use backend::rgi::booking::backend::{BookingBackend, DieselBackend};
+use backend::rgi::booking::query::{in_memory, EventQuery};
+use rocket::http::Status;
+
+use common::{fresh_db, reservation, user, NOOB, OTHER};
+
+/// rezervace, u které na počtu lidí závisí i rozložení
+fn sized(name: &str, rooms: i32, begin_time: &str, end_time: &str, people: u16) -> NewReservation {
+	NewReservation { layout: people % 2, people, ..reservation(name, rooms, begin_time, end_time) }
+}
+
+/// jména rezervací na stránce, SQL i Rust musí vrátit totéž
+fn names(query: EventQuery) -> (Vec<String>, i64) {
+	let (sql, total) = DieselBackend.search(&query).unwrap();
+	let (rust, rust_total) = in_memory(DieselBackend.list().unwrap(), &query);
+
+	let names = sql.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
+	assert_eq!(names, rust.iter().map(|r| r.name.clone()).collect::<Vec<_>>(), "{:?}", query);
+	assert_eq!(total, rust_total, "{:?}", query);
+
+	(names, total)
+}
+
+fn list(names: &[&str], total: i64) -> (Vec<String>, i64) {
+	(names.iter().map(|n| n.to_string()).collect(), total)
+}
+
+#[test]
+pub fn test_event_listing() {
+	fresh_db("listing");
+	let (novak, svoboda) = (user(NOOB), user(OTHER));
+
+	let db = DieselBackend;
+	let koncert = db.create(&sized("koncert", 3, "2019-12-12T18:00", "2019-12-12T20:00", 120), &novak).unwrap();
+	db.create(&sized("beseda", 1, "2019-12-10T10:00", "2019-12-10T12:00", 30), &novak).unwrap();
+	let divadlo = db.create(&sized("divadlo", 2, "2019-12-11T08:00", "2019-12-11T09:00", 45), &svoboda).unwrap();
+	db.create(&sized("autorské čtení", 1, "2019-12-14T10:00", "2019-12-14T11:00", 15), &svoboda).unwrap();
+	db.approve(koncert, &novak).unwrap();
+	db.decide(divadlo, &Decision::Reject("nejde".into()), &novak).unwrap();
+
+	assert_eq!(names(EventQuery::default()), list(&["beseda", "divadlo", "koncert", "autorské čtení"], 4));
+	let sorted = EventQuery { sort: Some("-name".into()), ..EventQuery::default() };
+	assert_eq!(names(sorted), list(&["koncert", "divadlo", "beseda", "autorské čtení"], 4));
+	let created = EventQuery { sort: Some("created".into()), ..EventQuery::default() };
+	assert_eq!(names(created), list(&["koncert", "beseda", "divadlo", "autorské čtení"], 4));
+
+	// filtry
+	let approved = EventQuery { status: Some("approved,rejected".into()), ..EventQuery::default() };
+	assert_eq!(names(approved), list(&["divadlo", "koncert"], 2));
+	let by_author = EventQuery { author: Some("svoboda@gjk.cz".into()), ..EventQuery::default() };
+	assert_eq!(names(by_author), list(&["divadlo", "autorské čtení"], 2));
+	// celé auditorium patří i k severní polovině
+	let north = EventQuery { rooms: Some("1".into()), ..EventQuery::default() };
+	assert_eq!(names(north), list(&["beseda", "koncert", "autorské čtení"], 3));
+	let people = EventQuery { people_min: Some("30".into()), people_max: Some("45".into()), ..EventQuery::default() };
+	assert_eq!(names(people), list(&["beseda", "divadlo"], 2));
+	let layout = EventQuery { layout: Some("1".into()), ..EventQuery::default() };
+	assert_eq!(names(layout), list(&["divadlo", "autorské čtení"], 2));
+	// navazující rezervace do intervalu nezasahují
+	let (from, to) = (Some("2019-12-11T09:00".to_string()), Some("2019-12-14T10:00".to_string()));
+	let dates = EventQuery { from, to, ..EventQuery::default() };
+	assert_eq!(names(dates), list(&["koncert"], 1));
+
+	// stránkování
+	let second = EventQuery { page: Some("2".into()), limit: Some("3".into()), ..EventQuery::default() };
+	assert_eq!(names(second.clone()), list(&["autorské čtení"], 4));
+	let page = second.page_of(vec![()], 4);
+	assert_eq!((page.next, page.prev), (None, Some("/rgi/events?page=1&limit=3".to_string())));
+
+	let first = EventQuery { author: Some("a+b@gjk.cz".into()), limit: Some("1".into()), ..EventQuery::default() };
+	assert_eq!(first.page_of(vec![()], 2).next, Some("/rgi/events?author=a%2Bb%40gjk.cz&page=2&limit=1".to_string()));
+	assert_eq!(EventQuery { limit: Some("100000".into()), ..EventQuery::default() }.limit(), 500);
+
+	let bad_sort = EventQuery { sort: Some("people".into()), ..EventQuery::default() };
+	assert_eq!(bad_sort.validate().unwrap_err().status, Status::UnprocessableEntity);
+	let bad_status = EventQuery { status: Some("approved,lost".into()), ..EventQuery::default() };
+	assert_eq!(bad_status.validate().unwrap_err().status, Status::UnprocessableEntity);
+
+	// čísla, která nejdou přečíst, se neignorují
+	let bad_numbers = [
+		EventQuery { rooms: Some("sever".into()), ..EventQuery::default() },
+		EventQuery { layout: Some("x".into()), ..EventQuery::default() },
+		EventQuery { people_min: Some("30.5".into()), ..EventQuery::default() },
+		EventQuery { people_max: Some("".into()), ..EventQuery::default() },
+		EventQuery { limit: Some("all".into()), ..EventQuery::default() },
+		EventQuery { page: Some("9223372036854775807".into()), ..EventQuery::default() },
+	];
+	for query in bad_numbers.iter() {
+		assert_eq!(query.validate().unwrap_err().status, Status::UnprocessableEntity, "{:?}", query);
+	}
+	// obří stránka nepřeteče ani bez validace
+	let huge = EventQuery { page: Some(i64::MAX.to_string()), limit: Some("500".into()), ..EventQuery::default() };
+	assert_eq!(names(huge.clone()), list(&[], 4));
+	assert_eq!(huge.page_of(Vec::<()>::new(), 4).next, None);
+}
@@ -0,0 +1,259 @@
+extern crate backend;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use backend::db::{Reservation, User};
+use backend::error::ApiError;
+use backend::rgi::envelope::Envelope;
+use backend::rgi::manifest::Manifest;
+use backend::rgi::pool::PoolConfig;
+use backend::rgi::response::{Done, RgiResponse};
+use backend::rgi::runner::{RgiError, Runner};
+use backend::rgi::sandbox::Sandbox;
+use rocket::http::{ContentType, Status};
+use rocket::local::Client;
+
+#[test]
+pub fn test_envelope_escaping() {
+	let user = User {
+		id:     1,
+		name:   "Jan \"Honza\"\nNovák".to_string(),
+		email:  "novak@gjk.cz".to_string(),
+		role:   "noob".to_string(),
+		active: 1,
+	};
+	let id = 3;
+	let begin_time = "2019-12-01 10:00";
+
+	let envelope = Envelope::new("PATCH")
+		.arg("id", &id)
+		.arg("begin_time", begin_time)
+		.data(&json!({"name": "a\", \"approved\": 1"}))
+		.caller(&user);
+	let line = envelope.to_json();
+
+	assert!(!line.contains('\n'));
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+		json!({
+			"method": "PATCH",
+			"args": { "id": 3, "begin_time": "2019-12-01 10:00" },
+			"data": { "name": "a\", \"approved\": 1" },
+			"caller": { "id": 1, "name": "Jan \"Honza\"\nNovák", "email": "novak@gjk.cz", "role": "noob" }
+		})
+	);
+}
+
+#[test]
+pub fn test_envelope_optional_fields() {
+	let line = Envelope::new("LIST").to_json();
+	assert_eq!(serde_json::from_str::<serde_json::Value>(&line).unwrap(), json!({"method": "LIST", "args": {}}));
+
+	let line = Envelope::new("LIST").db("booking.sqlite3").to_json();
+	assert_eq!(serde_json::from_str::<serde_json::Value>(&line).unwrap()["db"], json!("booking.sqlite3"));
+}
+
+#[test]
+pub fn test_runner_output() {
+	let runner = Runner::new(Duration::from_secs(5));
+	let envelope = Envelope::new("GET").arg("id", &1);
+
+	let out = runner.run("tests/fixtures/rgi/echo.sh", &envelope).unwrap();
+	assert_eq!(out.trim(), envelope.to_json());
+}
+
+#[test]
+pub fn test_runner_failures() {
+	let runner = Runner::new(Duration::from_secs(5))
+		.with_timeout("tests/fixtures/rgi/hang.sh GET", Duration::from_millis(200))
+		.with_timeout("tests/fixtures/rgi/orphan.sh", Duration::from_millis(200));
+	let envelope = Envelope::new("GET");
+
+	assert_eq!(runner.run("tests/fixtures/rgi/fail.sh", &envelope), Err(RgiError::Failed(Some(3))));
+	assert_eq!(runner.run("tests/fixtures/rgi/hang.sh", &envelope), Err(RgiError::Timeout(Duration::from_millis(200))));
+	// potomek skriptu drží roury, po timeoutu se zabije s ním
+	let started = Instant::now();
+	let orphan = runner.run("tests/fixtures/rgi/orphan.sh", &envelope);
+	assert_eq!(orphan, Err(RgiError::Timeout(Duration::from_millis(200))));
+	assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+	// i když skript skončí v pořádku, potomek na pozadí request nezdrží
+	let started = Instant::now();
+	assert_eq!(runner.run("tests/fixtures/rgi/orphan_ok.sh", &envelope).unwrap().trim(), r#"{"result": 0}"#);
+	assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+	assert!(match runner.run("tests/fixtures/rgi/missing.sh", &envelope) {
+		Err(RgiError::Spawn(_)) => true,
+		_ => false,
+	});
+
+	assert_eq!(ApiError::from(RgiError::Failed(Some(3))).status, Status::BadGateway);
+	assert_eq!(ApiError::from(RgiError::Timeout(Duration::from_secs(1))).status, Status::GatewayTimeout);
+}
+
+#[test]
+pub fn test_runner_timeouts() {
+	let runner = Runner::new(Duration::from_secs(10))
+		.with_timeout("a.py", Duration::from_secs(20))
+		.with_timeout("a.py FILTER", Duration::from_secs(30));
+
+	assert_eq!(runner.timeout("a.py", "FILTER"), Duration::from_secs(30));
+	assert_eq!(runner.timeout("a.py", "GET"), Duration::from_secs(20));
+	assert_eq!(runner.timeout("b.py", "GET"), Duration::from_secs(10));
+}
+
+#[test]
+pub fn test_sandbox() {
+	env::set_var("RGI_TEST_SECRET", "tajné");
+	let tmp = fs::canonicalize(env::temp_dir()).unwrap();
+	let envelope = Envelope::new("GET");
+
+	let sandbox = Sandbox { cwd: Some(tmp.clone()), open_files: Some(32), ..Sandbox::default() };
+	let out = Runner::new(Duration::from_secs(5)).with_sandbox(sandbox).run("tests/fixtures/rgi/env.sh", &envelope);
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(&out.unwrap()).unwrap(),
+		json!({ "secret": "", "cwd": tmp.to_str().unwrap(), "files": "32", "cpu": "10" })
+	);
+
+	// perzistentní workery limit času CPU nedostanou
+	let sandbox = Sandbox { cpu: Some(7), ..Sandbox::default() };
+	let cpu = |mut cmd: Command| {
+		let out = cmd.output().unwrap().stdout;
+		serde_json::from_slice::<serde_json::Value>(&out).unwrap()["cpu"].clone()
+	};
+	assert_eq!(cpu(sandbox.command("tests/fixtures/rgi/env.sh")), json!("7"));
+	assert_eq!(cpu(sandbox.worker_command("tests/fixtures/rgi/env.sh")), json!("unlimited"));
+
+	let sandbox = Sandbox::default().pass_env("RGI_TEST_SECRET");
+	let out = Runner::new(Duration::from_secs(5)).with_sandbox(sandbox).run("tests/fixtures/rgi/env.sh", &envelope);
+	assert_eq!(serde_json::from_str::<serde_json::Value>(&out.unwrap()).unwrap()["secret"], "tajné");
+
+	let sandbox = Sandbox { max_output: 1000, ..Sandbox::default() };
+	let runner = Runner::new(Duration::from_secs(5)).with_sandbox(sandbox);
+	assert_eq!(runner.run("tests/fixtures/rgi/big.sh", &envelope), Err(RgiError::OutputTooLarge(1000)));
+	assert_eq!(ApiError::from(RgiError::OutputTooLarge(1000)).status, Status::BadGateway);
+}
+
+#[test]
+pub fn test_rgi_response() {
+	let done = Done { result: 0, id: Some(4) };
+	assert_eq!(RgiResponse::<Done>::parse(r#"{"result": 0, "id": 4}"#).unwrap(), RgiResponse::Ok(done));
+	assert_eq!(RgiResponse::<Done>::parse(r#"{"result": 1}"#).unwrap(), RgiResponse::NotFound);
+	let conflict = RgiResponse::<Done>::parse(r#"{"result": 2, "conflicts": [1, 2]}"#).unwrap();
+	assert_eq!(conflict, RgiResponse::Conflict(vec![1, 2]));
+
+	let err = RgiResponse::<Done>::parse(r#"{"result": 2, "conflicts": [7]}"#).unwrap().into_result().unwrap_err();
+	assert_eq!(err.status, Status::Conflict);
+	assert_eq!(err.details["conflicts"], json!([7]));
+
+	let state = RgiResponse::<Done>::parse(r#"{"result": 3, "status": "withdrawn"}"#).unwrap();
+	assert_eq!(state, RgiResponse::WrongState("withdrawn".into()));
+	assert_eq!(state.into_result().unwrap_err().details["status"], json!("withdrawn"));
+
+	assert_eq!(RgiResponse::<Done>::parse("Traceback (most recent call last):").unwrap_err().status, Status::BadGateway);
+	assert_eq!(RgiResponse::<Done>::parse(r#"{"result": 5}"#).unwrap_err().status, Status::BadGateway);
+	assert_eq!(RgiResponse::<Reservation>::parse(r#"{"id": "nope"}"#).unwrap_err().status, Status::BadGateway);
+}
+
+#[test]
+pub fn test_worker_pool() {
+	let script = "tests/fixtures/rgi/worker.py";
+	let config = PoolConfig { size: 1, max_requests: 3, health_interval: Duration::from_secs(30) };
+	let runner = Runner::new(Duration::from_secs(5)).with_workers(script, config);
+
+	let pid = |out: String| serde_json::from_str::<serde_json::Value>(&out).unwrap()["pid"].as_i64().unwrap();
+
+	// jeden worker obslouží víc requestů a chyba skriptu ho nezabije
+	let first = pid(runner.run(script, &Envelope::new("GET").arg("id", &1)).unwrap());
+	assert_eq!(runner.run(script, &Envelope::new("FAIL")), Err(RgiError::Worker("failed on purpose".to_string())));
+	assert_eq!(pid(runner.run(script, &Envelope::new("GET")).unwrap()), first);
+
+	// po max_requests se vymění
+	let second = pid(runner.run(script, &Envelope::new("GET")).unwrap());
+	assert_ne!(second, first);
+
+	// spadlý worker se nahradí novým
+	assert_eq!(runner.run(script, &Envelope::new("CRASH")), Err(RgiError::Failed(Some(1))));
+	assert_ne!(pid(runner.run(script, &Envelope::new("GET")).unwrap()), second);
+}
+
+#[test]
+pub fn test_manifest_validation() {
+	let ok = r#"
+		[[endpoint]]
+		path = "/reports/<from>"
+		method = "get"
+		script = "rgi/reports/usage.py"
+		verb = "USAGE"
+		role = "Approver"
+		args = [{ name = "from", source = "url" }, { name = "email", source = "user" }]
+	"#;
+	assert_eq!(Manifest::from_toml(ok).unwrap().endpoint.len(), 1);
+	assert!(Manifest::from_toml("").unwrap().endpoint.is_empty());
+
+	let broken = [
+		// neznámá role
+		r#"[[endpoint]]
+		path = "/a"
+		method = "GET"
+		script = "a.py"
+		verb = "A"
+		role = "admin""#,
+		// argument, který není v cestě
+		r#"[[endpoint]]
+		path = "/a"
+		method = "GET"
+		script = "a.py"
+		verb = "A"
+		args = [{ name = "id", source = "url" }]"#,
+		// uživatel na veřejném endpointu
+		r#"[[endpoint]]
+		path = "/a"
+		method = "GET"
+		script = "a.py"
+		verb = "A"
+		args = [{ name = "email", source = "user" }]"#,
+		// neznámá metoda
+		r#"[[endpoint]]
+		path = "/a"
+		method = "FETCH"
+		script = "a.py"
+		verb = "A""#,
+	];
+	for src in broken.iter() {
+		assert!(Manifest::from_toml(src).is_err(), "{}", src);
+	}
+}
+
+#[test]
+pub fn test_manifest_routes() {
+	let manifest = Manifest::from_toml(
+		r#"
+		[[endpoint]]
+		path = "/echo/<id>"
+		method = "POST"
+		script = "tests/fixtures/rgi/echo.sh"
+		verb = "ECHO"
+		body = "json"
+		args = [{ name = "id", source = "url", type = "int" }, { name = "q", source = "query" }]
+	"#,
+	)
+	.unwrap();
+
+	let rocket = rocket::ignite().manage(Runner::new(Duration::from_secs(5))).mount("/rgi/", manifest.routes());
+	let client = Client::new(rocket).unwrap();
+
+	let mut response = client.post("/rgi/echo/3?q=hi").header(ContentType::JSON).body(r#"{"a": 1}"#).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	assert_eq!(
+		serde_json::from_str::<serde_json::Value>(&response.body_string().unwrap()).unwrap(),
+		json!({ "method": "ECHO", "args": { "id": 3, "q": "hi" }, "data": { "a": 1 } })
+	);
+
+	let response = client.post("/rgi/echo/three").body("{}").dispatch();
+	assert_eq!(response.status(), Status::UnprocessableEntity);
+}
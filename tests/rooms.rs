@@ -0,0 +1,144 @@
+extern crate backend;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use backend::db::{self, Room, RoomRef, Rooms};
+use backend::rgi::booking::rooms::{names, Catalogue};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use common::{body, App, FakeRgi, BOOKING, NOOB};
+
+fn room(id: i32, name: &str, capacity: i32, bit: Option<i32>) -> Room {
+	Room { id, name: name.into(), description: "".into(), capacity, bit }
+}
+
+/// id místností rezervace v `reservation_rooms`
+fn assigned(r_id: i32) -> Vec<i32> {
+	use backend::schema::reservation_rooms::dsl::*;
+
+	reservation_rooms.filter(reservation_id.eq(r_id)).select(room_id).order(room_id).load(&db::get_con()).unwrap()
+}
+
+fn event(rooms: Value) -> String {
+	json!({
+		"name": "koncert",
+		"description": "test",
+		"rooms": rooms,
+		"begin_time": "2019-12-12T18:00",
+		"end_time": "2019-12-12T20:00",
+		"layout": 0,
+		"people": 100
+	})
+	.to_string()
+}
+
+#[test]
+pub fn test_catalogue() {
+	let rooms = vec![
+		room(1, "sever", 70, Some(1)),
+		room(2, "jih", 70, Some(2)),
+		room(3, "celé auditorium", 150, None),
+		room(4, "foyer", 40, Some(4)),
+	];
+	let catalogue = Catalogue::new(rooms, &[(3, 1), (3, 2)]);
+	assert_eq!(catalogue.all(), 7);
+	assert_eq!(catalogue.by_mask(3).unwrap().parts, vec![1, 2]);
+
+	// starší bitflagy, jména i seznamy
+	let name = |rooms: Rooms| catalogue.resolve(&rooms).map(|r| r.room.name.clone());
+	assert_eq!(name(Rooms::Mask(1)).unwrap(), "sever");
+	assert_eq!(name(Rooms::Name("Celé auditorium ".into())).unwrap(), "celé auditorium");
+	assert_eq!(name(Rooms::List(vec![RoomRef::Id(1), RoomRef::Name("jih".into())])).unwrap(), "celé auditorium");
+	assert_eq!(name(Rooms::List(vec![RoomRef::Id(3), RoomRef::Id(1)])).unwrap(), "celé auditorium");
+
+	// neznámé místnosti a místnosti, které k sobě nepatří
+	for bad in &[
+		Rooms::Mask(0),
+		Rooms::Mask(8),
+		Rooms::Name("kuchyň".into()),
+		Rooms::List(vec![]),
+		Rooms::List(vec![RoomRef::Id(1), RoomRef::Id(42)]),
+	] {
+		assert_eq!(catalogue.resolve(bad).unwrap_err().status, Status::UnprocessableEntity, "{:?}", bad);
+	}
+	let apart = catalogue.resolve(&Rooms::List(vec![RoomRef::Id(1), RoomRef::Id(4)])).unwrap_err();
+	assert_eq!(apart.details["bookable"], json!(["sever", "jih", "celé auditorium", "foyer"]));
+	// filtry spojení nepotřebují
+	assert_eq!(catalogue.union(&Rooms::List(vec![RoomRef::Id(1), RoomRef::Id(4)])).unwrap(), 5);
+
+	let occupied = catalogue.occupied(7).into_iter().map(|r| r.room.id).collect::<Vec<_>>();
+	assert_eq!(occupied, vec![1, 2, 4]);
+
+	// serde: číslo jsou bitflagy, text jméno, pole id nebo jména
+	let parsed = serde_json::from_value::<Vec<Rooms>>(json!([3, "sever", [1, "jih"]])).unwrap();
+	assert_eq!(
+		parsed,
+		vec![
+			Rooms::Mask(3),
+			Rooms::Name("sever".into()),
+			Rooms::List(vec![RoomRef::Id(1), RoomRef::Name("jih".into())])
+		]
+	);
+}
+
+#[test]
+pub fn test_rooms() {
+	let rgi = FakeRgi::new().reply(BOOKING, "POST", json!({ "result": 0, "id": 1 })).reply(BOOKING, "LIST", json!([]));
+	let app = App::new(&rgi);
+	let cli = &app.client;
+
+	// z migrace
+	let all = body(&mut cli.get("/rgi/rooms").dispatch());
+	let masks = all.as_array().unwrap().iter().map(|r| (r["name"].clone(), r["mask"].clone())).collect::<Vec<_>>();
+	let expected = vec![(json!("sever"), json!(1)), (json!("jih"), json!(2)), (json!("celé auditorium"), json!(3))];
+	assert_eq!(masks, expected);
+	let whole = body(&mut cli.get("/rgi/rooms/3").dispatch());
+	assert_eq!((&whole["capacity"], &whole["parts"]), (&json!(150), &json!([1, 2])));
+	assert_eq!(cli.get("/rgi/rooms/42").dispatch().status(), Status::NotFound);
+
+	// neznámé místnosti se do RGI vůbec nepošlou
+	for rooms in &[json!("kuchyň"), json!([1, 42]), json!(4), json!([])] {
+		let post = cli.post("/rgi/events").header(ContentType::JSON).header(app.auth(NOOB));
+		let response = post.body(event(rooms.clone())).dispatch();
+		assert_eq!(response.status(), Status::UnprocessableEntity, "{}", rooms);
+	}
+	assert_eq!(rgi.count(BOOKING, "POST"), 0);
+
+	// RGI dostane bitflagy, do reservation_rooms se zapíšou obě poloviny
+	let post = cli.post("/rgi/events").header(ContentType::JSON).header(app.auth(NOOB));
+	let response = post.body(event(json!(["sever", "jih"]))).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	assert_eq!(rgi.last(BOOKING).data.unwrap()["rooms"], json!(3));
+	assert_eq!(assigned(1), vec![1, 2]);
+	assert_eq!(names(&db::get_con(), 1, 3).unwrap(), vec!["sever", "jih"]);
+
+	// reservation_rooms je jen kopie bitflagů, když se nezapíše, rezervace se uloží stejně
+	db::get_con().batch_execute("ALTER TABLE reservation_rooms RENAME TO broken").unwrap();
+	let post = cli.post("/rgi/events").header(ContentType::JSON).header(app.auth(NOOB));
+	let response = post.body(event(json!("sever"))).dispatch();
+	db::get_con().batch_execute("ALTER TABLE broken RENAME TO reservation_rooms").unwrap();
+	assert_eq!(response.status(), Status::Ok);
+	assert_eq!(assigned(1), vec![1, 2]);
+	// zastaralé řádky opraví čtení jmen
+	assert_eq!(names(&db::get_con(), 1, 1).unwrap(), vec!["sever"]);
+	assert_eq!(assigned(1), vec![1]);
+
+	// výpis filtruje podle bitflagů, id i jmen, odkazy nechají místnosti, jak přišly
+	for rooms in &["3", "1,2", "sever,jih", "1,jih"] {
+		let get = cli.get(format!("/rgi/events?rooms={}&limit=1&page=2", rooms)).header(app.auth(NOOB));
+		let mut response = get.dispatch();
+		assert_eq!(response.status(), Status::Ok, "{}", rooms);
+		assert_eq!(body(&mut response)["prev"], json!(format!("/rgi/events?rooms={}&page=1&limit=1", rooms)));
+	}
+	for rooms in &["kuchyne", "1,42", "8"] {
+		let response = cli.get(format!("/rgi/events?rooms={}", rooms)).header(app.auth(NOOB)).dispatch();
+		assert_eq!(response.status(), Status::UnprocessableEntity, "{}", rooms);
+	}
+}
@@ -0,0 +1,204 @@
+extern crate backend;
+extern crate chrono;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use backend::datetime;
+use backend::rgi::booking::backend::{BookingBackend, DieselBackend};
+use backend::rgi::booking::recurrence::{expand, Rule};
+use rocket::http::{ContentType, Status};
+use rocket::local::LocalRequest;
+use serde_json::Value;
+
+use common::{at, body, reservation, user, App, FakeRgi, APPROVER, NOOB, OTHER};
+
+/// začátky výskytů v místním čase
+fn starts(rule: &str, begin_time: &str, end_time: &str, exdates: &[&str]) -> Vec<String> {
+	let rule = rule.parse::<Rule>().unwrap();
+	let exdates = exdates.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+	let expanded = expand(&rule, at(begin_time), at(end_time), &exdates).unwrap();
+	expanded.into_iter().map(|o| datetime::to_local(o.begin_time).format("%Y-%m-%dT%H:%M").to_string()).collect()
+}
+
+fn series(rrule: &str, begin_time: &str, end_time: &str, dry_run: bool) -> String {
+	json!({
+		"name": "seminář",
+		"description": "každý týden",
+		"rooms": 1,
+		"begin_time": begin_time,
+		"end_time": end_time,
+		"layout": 0,
+		"people": 20,
+		"rrule": rrule,
+		"dry_run": dry_run
+	})
+	.to_string()
+}
+
+fn post<'c>(app: &'c App, data: String) -> LocalRequest<'c> {
+	app.client.post("/rgi/events/series").header(ContentType::JSON).header(app.auth(NOOB)).body(data)
+}
+
+fn patch<'c>(app: &'c App, id: i64, scope: &str, data: Value) -> LocalRequest<'c> {
+	let url = format!("/rgi/events/{}/series?scope={}", id, scope);
+	app.client.patch(url).header(ContentType::JSON).header(app.auth(NOOB)).body(data.to_string())
+}
+
+/// id a začátky výskytů z odpovědi
+fn occurrences(value: &Value) -> Vec<(i64, String)> {
+	let list = value["occurrences"].as_array().unwrap();
+	list.iter().map(|o| (o["id"].as_i64().unwrap_or(-1), o["begin_time"].as_str().unwrap().to_string())).collect()
+}
+
+#[test]
+pub fn test_rrule() {
+	// DTSTART se počítá do COUNT
+	assert_eq!(
+		starts("FREQ=WEEKLY;BYDAY=TU,TH;COUNT=4", "2020-02-04T14:00", "2020-02-04T15:30", &[]),
+		vec!["2020-02-04T14:00", "2020-02-06T14:00", "2020-02-11T14:00", "2020-02-13T14:00"]
+	);
+	assert_eq!(
+		starts("FREQ=DAILY;INTERVAL=2;UNTIL=20200210", "2020-02-04 08:00", "2020-02-04 09:00", &[]),
+		vec!["2020-02-04T08:00", "2020-02-06T08:00", "2020-02-08T08:00", "2020-02-10T08:00"]
+	);
+	// měsíce bez 31. se přeskočí
+	assert_eq!(
+		starts("FREQ=MONTHLY;COUNT=3", "2020-01-31T10:00", "2020-01-31T11:00", &[]),
+		vec!["2020-01-31T10:00", "2020-03-31T10:00", "2020-05-31T10:00"]
+	);
+	// vynechaný výskyt se do COUNT počítá
+	assert_eq!(
+		starts("FREQ=WEEKLY;COUNT=3", "2020-02-03T10:00", "2020-02-03T11:00", &["2020-02-10T10:00"]),
+		vec!["2020-02-03T10:00", "2020-02-17T10:00"]
+	);
+
+	// přes změnu času zůstává seminář v 10:00 místního času, neexistující 02:30 se posune na 03:30
+	assert_eq!(
+		starts("FREQ=WEEKLY;COUNT=3", "2020-03-22T10:00", "2020-03-22T11:00", &["2020-04-05T08:00:00Z"]),
+		vec!["2020-03-22T10:00", "2020-03-29T10:00"]
+	);
+	let rule = "FREQ=WEEKLY;COUNT=2".parse::<Rule>().unwrap();
+	let expanded = expand(&rule, at("2020-03-22T10:00"), at("2020-03-22T11:00"), &[]).unwrap();
+	let utc = expanded.iter().map(|o| datetime::format(o.end_time)).collect::<Vec<_>>();
+	assert_eq!(utc, vec!["2020-03-22T10:00:00Z", "2020-03-29T09:00:00Z"]);
+	assert_eq!(
+		starts("FREQ=DAILY;COUNT=3", "2020-03-28T02:30", "2020-03-28T04:00", &[]),
+		vec!["2020-03-28T02:30", "2020-03-29T03:30", "2020-03-30T02:30"]
+	);
+
+	// UNTIL v UTC se převede na místní čas
+	let rule = "rrule:freq=weekly;byday=we,mo;until=20200301T120000Z".parse::<Rule>().unwrap();
+	assert_eq!(rule.to_string(), "FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20200301T130000");
+	assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule);
+
+	for bad in &[
+		"FREQ=WEEKLY",
+		"FREQ=YEARLY;COUNT=2",
+		"FREQ=DAILY;COUNT=2;UNTIL=20200101",
+		"FREQ=DAILY;BYDAY=MO;COUNT=2",
+		"FREQ=DAILY;INTERVAL=0;COUNT=2",
+		"COUNT=2",
+	] {
+		assert!(bad.parse::<Rule>().is_err(), "{} should be rejected", bad);
+	}
+
+	let endless = "FREQ=DAILY;UNTIL=20300101".parse::<Rule>().unwrap();
+	assert!(expand(&endless, at("2020-01-01T10:00"), at("2020-01-01T11:00"), &[]).is_err());
+	let rule = "FREQ=DAILY;COUNT=2".parse::<Rule>().unwrap();
+	assert!(expand(&rule, at("2020-01-01T10:00"), at("2020-01-01T09:00"), &[]).is_err());
+	assert!(expand(&rule, at("2020-01-01T10:00"), at("2020-01-01T11:00"), &["banana".to_string()]).is_err());
+}
+
+#[test]
+pub fn test_series() {
+	let app = App::new(&FakeRgi::new());
+	let cli = &app.client;
+
+	// schválená rezervace na druhé pondělí
+	let usr = user(APPROVER);
+	let blocker = reservation("jiná akce", 3, "2020-02-10T10:30", "2020-02-10T12:00");
+	let blocker = DieselBackend.create(&blocker, &usr).unwrap();
+	DieselBackend.approve(blocker, &usr).unwrap();
+
+	let rule = "FREQ=WEEKLY;BYDAY=MO;COUNT=4";
+	let mut response = post(&app, series(rule, "2020-02-03T10:00", "2020-02-03T11:00", true)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let plan = body(&mut response);
+	assert_eq!(plan["id"], Value::Null);
+	assert_eq!(plan["occurrences"][1]["conflicts"], json!([blocker]));
+	assert_eq!(plan["occurrences"][0]["conflicts"], json!([]));
+
+	// kolize -> nic se neuloží
+	let mut response = post(&app, series(rule, "2020-02-03T10:00", "2020-02-03T11:00", false)).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+	let error = body(&mut response);
+	assert_eq!(error["conflicts"], json!([blocker]));
+	assert_eq!(occurrences(&error), vec![(-1, "2020-02-10T09:00:00Z".to_string())]);
+	assert_eq!(DieselBackend.list().unwrap().len(), 1);
+
+	let response = post(&app, series("FREQ=HOURLY;COUNT=2", "2020-02-03T10:00", "2020-02-03T11:00", false)).dispatch();
+	assert_eq!(response.status(), Status::UnprocessableEntity);
+
+	// o hodinu dřív to projde
+	let mut response = post(&app, series(rule, "2020-02-03T09:00", "2020-02-03T10:00", false)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let created = body(&mut response);
+	let s_id = created["id"].as_i64().unwrap();
+	let ids = occurrences(&created).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+	assert_eq!(ids.len(), 4);
+
+	let mut response = cli.get(format!("/rgi/events/series/{}", s_id)).header(app.auth(OTHER)).dispatch();
+	let detail = body(&mut response);
+	assert_eq!(detail["rrule"], json!(rule));
+	assert_eq!(detail["occurrences"][3]["series_id"], json!(s_id));
+	assert_eq!(detail["occurrences"][3]["begin_time"], json!("2020-02-24T08:00:00Z"));
+
+	// posun tohoto a následujících do kolize -> 409, nic se nezmění
+	let mut response = patch(&app, ids[1], "following", json!({ "begin_time": "2020-02-10T10:00" })).dispatch();
+	assert_eq!(response.status(), Status::Conflict);
+	assert_eq!(body(&mut response)["conflicts"], json!([blocker]));
+
+	let moved = json!({ "begin_time": "2020-02-10T08:00", "name": "ráno" });
+	let mut response = patch(&app, ids[1], "following", moved).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let starts = occurrences(&body(&mut response)).into_iter().map(|(_, begin)| begin).collect::<Vec<_>>();
+	assert_eq!(starts, vec!["2020-02-10T07:00:00Z", "2020-02-17T07:00:00Z", "2020-02-24T07:00:00Z"]);
+	let first = DieselBackend.get(ids[0] as i32).unwrap().reservation;
+	let last = DieselBackend.get(ids[3] as i32).unwrap().reservation;
+	assert_eq!((first.name.as_str(), first.begin_time), ("seminář", at("2020-02-03T09:00")));
+	assert_eq!((last.name.as_str(), last.end_time), ("ráno", at("2020-02-24T09:00")));
+
+	// cizí sérii měnit nejde, scope je povinný, samostatná rezervace není série
+	let response = cli.delete(format!("/rgi/events/{}/series?scope=all", ids[0])).header(app.auth(OTHER)).dispatch();
+	assert_eq!(response.status(), Status::NotFound);
+	let response = cli.delete(format!("/rgi/events/{}/series", ids[0])).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::UnprocessableEntity);
+	let response =
+		cli.delete(format!("/rgi/events/{}/series?scope=all", blocker)).header(app.auth(APPROVER)).dispatch();
+	assert_eq!(response.status(), Status::UnprocessableEntity);
+
+	// zrušení jednoho výskytu a pak konce série
+	let response = cli.delete(format!("/rgi/events/{}/series?scope=this", ids[1])).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let response =
+		cli.delete(format!("/rgi/events/{}/series?scope=following", ids[3])).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+
+	let mut response = cli.get(format!("/rgi/events/series/{}", s_id)).header(app.auth(NOOB)).dispatch();
+	let detail = body(&mut response);
+	assert_eq!(detail["exdates"], json!(["2020-02-10T07:00:00Z"]));
+	assert_eq!(detail["rrule"], json!("FREQ=WEEKLY;BYDAY=MO;UNTIL=20200224T075959"));
+	let left = detail["occurrences"].as_array().unwrap().iter().map(|o| o["id"].as_i64().unwrap()).collect::<Vec<_>>();
+	assert_eq!(left, vec![ids[0], ids[2]]);
+
+	// celá série
+	let response = cli.delete(format!("/rgi/events/{}/series?scope=all", ids[2])).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::Ok);
+	let response = cli.get(format!("/rgi/events/series/{}", s_id)).header(app.auth(NOOB)).dispatch();
+	assert_eq!(response.status(), Status::NotFound);
+	assert_eq!(DieselBackend.list().unwrap().iter().map(|r| r.id as i64).collect::<Vec<_>>(), vec![blocker as i64]);
+}
@@ -0,0 +1,63 @@
+extern crate backend;
+extern crate diesel;
+extern crate rocket;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+use backend::db;
+use diesel::connection::SimpleConnection;
+use rocket::http::{ContentType, Status};
+use serde_json::Value;
+
+use common::{body, App, FakeRgi, MANAGER, NOOB};
+
+/// emaily nalezených uživatelů
+fn emails(found: Value) -> Vec<String> {
+	found.as_array().unwrap().iter().map(|u| u["email"].as_str().unwrap().to_string()).collect()
+}
+
+#[test]
+pub fn test_users() {
+	let app = App::new(&FakeRgi::new());
+	let cli = &app.client;
+	let role = |id: i32, role: &str, who| {
+		let patch = cli.patch(format!("/users/{}/role", id)).header(ContentType::JSON).header(app.auth(who));
+		patch.body(json!({ "role": role }).to_string()).dispatch()
+	};
+
+	assert_eq!(cli.get("/users").dispatch().status(), Status::Unauthorized);
+	assert_eq!(cli.get("/users").header(app.auth(NOOB)).dispatch().status(), Status::Forbidden);
+	let all = body(&mut cli.get("/users").header(app.auth(MANAGER)).dispatch());
+	assert_eq!(emails(all), vec!["novak@gjk.cz", "svoboda@gjk.cz", "dvorakova@gjk.cz", "cerna@gjk.cz"]);
+
+	// % a _ se hledají doslova
+	let search = |q: &str| emails(body(&mut cli.get(format!("/users?q={}", q)).header(app.auth(MANAGER)).dispatch()));
+	assert_eq!(search("NOV"), vec!["novak@gjk.cz"]);
+	assert!(search("%25").is_empty());
+	assert!(search("_").is_empty());
+
+	assert_eq!(body(&mut cli.get("/users/2").header(app.auth(MANAGER)).dispatch())["name"], json!("Petr Svoboda"));
+	assert_eq!(cli.get("/users/42").header(app.auth(MANAGER)).dispatch().status(), Status::NotFound);
+
+	// role se zapíše v kanonickém tvaru
+	assert_eq!(body(&mut role(1, "Approver", MANAGER))["role"], json!("approver"));
+	assert_eq!(role(1, "ředitel", MANAGER).status(), Status::UnprocessableEntity);
+	assert_eq!(role(42, "noob", MANAGER).status(), Status::NotFound);
+	assert_eq!(role(2, "approver", NOOB).status(), Status::Forbidden);
+
+	// neaktivní facility manager se nepočítá, posledního aktivního degradovat nejde
+	let con = db::get_con();
+	let pending = "INSERT INTO users (name, email, role, active) VALUES ('Nový', 'novy@gjk.cz', 'facilitymanager', 0)";
+	con.batch_execute(pending).unwrap();
+	assert_eq!(role(4, "noob", MANAGER).status(), Status::Conflict);
+	// neaktivního jde degradovat, i když je aktivní jen jeden
+	assert_eq!(body(&mut role(5, "noob", MANAGER))["role"], json!("noob"));
+	assert_eq!(body(&mut role(5, "facilitymanager", MANAGER))["role"], json!("facilitymanager"));
+	let activate = cli.post("/users/5/activate").header(app.auth(MANAGER)).dispatch();
+	assert_eq!(activate.status(), Status::Ok);
+	assert_eq!(body(&mut role(5, "noob", MANAGER))["role"], json!("noob"));
+	assert_eq!(role(4, "approver", MANAGER).status(), Status::Conflict);
+	assert_eq!(body(&mut cli.get("/users/4").header(app.auth(MANAGER)).dispatch())["role"], json!("facilitymanager"));
+}